@@ -0,0 +1,137 @@
+use std::io::Cursor;
+
+use bitpack::{compact::CompactScheme, BitReader, BitWrite, BitWriter};
+use criterion::{
+    criterion_group, criterion_main,
+    measurement::{Measurement, ValueFormatter},
+    BatchSize, BenchmarkId, Criterion,
+};
+use rand::Rng;
+
+const SAMPLE_SIZE: usize = 1 << 12;
+const SCHEMES: [(&str, CompactScheme); 2] = [
+    ("length prefixed", CompactScheme::LengthPrefixed),
+    ("varint", CompactScheme::Varint),
+];
+const BIT_WIDTHS: [u32; 6] = [4, 8, 16, 24, 32, 64];
+
+fn generate_numbers(bit_width: u32) -> Vec<u64> {
+    let mut rng = rand::rng();
+    (0..SAMPLE_SIZE)
+        .map(|_| rng.random::<u64>() >> (u64::BITS - bit_width))
+        .collect()
+}
+
+fn encode(scheme: CompactScheme, numbers: &[u64]) -> Vec<u8> {
+    let mut writer = BitWriter::new(Vec::new());
+    for number in numbers {
+        scheme.write_u64(&mut writer, *number).unwrap();
+    }
+    writer.flush().unwrap();
+
+    writer.into_inner()
+}
+
+fn benchmark_speed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("compact numbers speed");
+    for bit_width in BIT_WIDTHS {
+        for (name, scheme) in SCHEMES {
+            group.bench_with_input(
+                BenchmarkId::new(format!("{name}::write"), bit_width),
+                &bit_width,
+                |bencher, bit_width| {
+                    bencher.iter_batched_ref(
+                        || generate_numbers(*bit_width),
+                        |numbers| encode(scheme, numbers),
+                        BatchSize::SmallInput,
+                    );
+                },
+            );
+
+            group.bench_with_input(
+                BenchmarkId::new(format!("{name}::read"), bit_width),
+                &bit_width,
+                |bencher, bit_width| {
+                    bencher.iter_batched_ref(
+                        || BitReader::new(Cursor::new(encode(scheme, &generate_numbers(*bit_width)))),
+                        |reader| {
+                            for _ in 0..SAMPLE_SIZE {
+                                scheme.read_u64(reader).unwrap();
+                            }
+                        },
+                        BatchSize::SmallInput,
+                    );
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+struct EncodedSize;
+impl Measurement for EncodedSize {
+    type Intermediate = ();
+    type Value = f64;
+
+    fn start(&self) -> Self::Intermediate {}
+    fn end(&self, _i: Self::Intermediate) -> Self::Value {
+        0.0
+    }
+    fn add(&self, v1: &Self::Value, v2: &Self::Value) -> Self::Value {
+        *v1 + *v2
+    }
+    fn zero(&self) -> Self::Value {
+        0.0
+    }
+
+    fn to_f64(&self, value: &Self::Value) -> f64 {
+        *value
+    }
+    fn formatter(&self) -> &dyn ValueFormatter {
+        &EncodedSizeFormatter
+    }
+}
+
+struct EncodedSizeFormatter;
+impl ValueFormatter for EncodedSizeFormatter {
+    fn scale_for_machines(&self, _values: &mut [f64]) -> &'static str {
+        "bytes / number"
+    }
+    fn scale_throughputs(
+        &self,
+        _typical_value: f64,
+        _throughput: &criterion::Throughput,
+        _values: &mut [f64],
+    ) -> &'static str {
+        "bytes / number"
+    }
+    fn scale_values(&self, _typical_value: f64, _values: &mut [f64]) -> &'static str {
+        "bytes / number"
+    }
+}
+
+fn benchmark_size(c: &mut Criterion<EncodedSize>) {
+    let mut group = c.benchmark_group("compact numbers size");
+    for bit_width in BIT_WIDTHS {
+        for (name, scheme) in SCHEMES {
+            group.bench_with_input(BenchmarkId::new(name, bit_width), &bit_width, |bencher, bit_width| {
+                // criterion reports the value divided by the amount of iterations.
+                bencher.iter_custom(|iters| {
+                    let numbers = generate_numbers(*bit_width);
+                    let encoded_size: usize = numbers.iter().map(|number| scheme.encoded_len(*number)).sum();
+
+                    encoded_size as f64 / numbers.len() as f64 * iters as f64
+                });
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(speed, benchmark_speed);
+criterion_group!(
+    name = size;
+    config = Criterion::default().with_measurement(EncodedSize);
+    targets = benchmark_size
+);
+criterion_main!(speed, size);
@@ -9,9 +9,7 @@ impl Measurement for CompressionRatio {
     type Intermediate = ();
     type Value = f64;
 
-    fn start(&self) -> Self::Intermediate {
-        ()
-    }
+    fn start(&self) -> Self::Intermediate {}
     fn end(&self, _i: Self::Intermediate) -> Self::Value {
         0.0
     }
@@ -88,8 +86,8 @@ mod entropy {
         assert!(bit_probability <= 1.0);
 
         let mut probability_per_bit_count = [0.0; BITS + 1];
-        for i in 0..probability_per_bit_count.len() {
-            probability_per_bit_count[i] = bit_probability.powi(i as i32) * (1.0 - bit_probability).powi((BITS - i) as i32);
+        for (i, probability) in probability_per_bit_count.iter_mut().enumerate() {
+            *probability = bit_probability.powi(i as i32) * (1.0 - bit_probability).powi((BITS - i) as i32);
         }
 
         let mut probabilities = [0.0; u8::MAX as usize + 1];
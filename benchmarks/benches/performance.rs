@@ -5,7 +5,8 @@ use criterion::{
     Criterion,
 };
 use flate2::Compression;
-use rand::Rng;
+use huffman_format::DecodeStrategy;
+use rand::{rngs::StdRng, Rng, SeedableRng};
 
 fn benchmark_pack_for_function<R, S: Fn(Cursor<Vec<u8>>) -> R, F: Fn(&mut R, &mut Cursor<Vec<u8>>)>(
     group: &mut BenchmarkGroup<'_, WallTime>,
@@ -150,7 +151,7 @@ fn benchmark_unpack_speed(c: &mut Criterion) {
                 let mut reader = flate2::read::GzEncoder::new(input_reader, Compression::best());
                 std::io::copy(&mut reader, output_writer).unwrap();
             }, 
-            |reader| flate2::read::GzDecoder::new(reader), 
+            flate2::read::GzDecoder::new, 
             |reader, writer| {
                 std::io::copy(reader, writer).unwrap();
             }
@@ -165,7 +166,7 @@ fn benchmark_unpack_speed(c: &mut Criterion) {
                 let mut reader = xz2::read::XzEncoder::new(input_reader, 6);
                 std::io::copy(&mut reader, output_writer).unwrap();
             }, 
-            |reader| xz2::read::XzDecoder::new(reader), 
+            xz2::read::XzDecoder::new, 
             |reader, writer| {
                 std::io::copy(reader, writer).unwrap();
             }
@@ -174,5 +175,63 @@ fn benchmark_unpack_speed(c: &mut Criterion) {
     group.finish();
 }
 
-criterion_group!(benches, benchmark_pack_speed, benchmark_unpack_speed);
+fn benchmark_byte_table_speed(c: &mut Criterion) {
+    let mut group = c.benchmark_group("huffman::byte table");
+    for i in (12..=24).step_by(4) {
+        let size = 1usize << i;
+
+        group.bench_with_input(BenchmarkId::new("huffman::byte table", size), &size, |bencher, size| {
+            bencher.iter_batched_ref(
+                || {
+                    let mut rng = rand::rng();
+                    let input_buf = (0..*size).map(|_| rng.random()).collect::<Vec<u8>>();
+
+                    Cursor::new(input_buf)
+                },
+                |reader| huffman_format::get_byte_table(reader).unwrap(),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+    group.finish();
+}
+
+// Draws bytes from a geometric distribution, whose entropy grows with `spread`. The data is the
+// same on every run, so the results can be compared against a saved baseline.
+fn generate_with_entropy(size: usize, spread: f64) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(spread.to_bits());
+    (0..size)
+        .map(|_| {
+            let uniform: f64 = rng.random();
+            ((-uniform.ln() * spread) as u64).min(u8::MAX as u64) as u8
+        })
+        .collect()
+}
+
+fn benchmark_decoders(c: &mut Criterion) {
+    const STRATEGIES: [(&str, DecodeStrategy); 3] = [
+        ("tree walk", DecodeStrategy::TreeWalk),
+        ("flat tree", DecodeStrategy::FlatTree),
+        ("lookup", DecodeStrategy::Lookup),
+    ];
+    const SIZE: usize = 1 << 16;
+
+    let mut group = c.benchmark_group("huffman::decoders");
+    for spread in [0.5, 4.0, 32.0, 1024.0] {
+        let data = generate_with_entropy(SIZE, spread);
+        let entropy = huffman_format::compute_entropy(huffman_format::get_byte_table(&mut &data[..]).unwrap());
+
+        let mut packed = Vec::new();
+        huffman_format::pack_file(Cursor::new(data), &mut packed).unwrap();
+
+        for (name, strategy) in STRATEGIES {
+            group.bench_with_input(BenchmarkId::new(name, format!("{entropy:.2} bpb")), &packed, |bencher, packed| {
+                bencher.iter(|| huffman_format::unpack_with_strategy(&packed[..], std::io::sink(), strategy).unwrap());
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, benchmark_pack_speed, benchmark_unpack_speed, benchmark_byte_table_speed, benchmark_decoders);
 criterion_main!(benches);
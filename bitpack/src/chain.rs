@@ -0,0 +1,97 @@
+use std::{
+    collections::VecDeque,
+    io::{self, Read},
+};
+
+use crate::BitReader;
+
+// Reads several sources one after the other, as a single stream. Wrapped in a `BitReader`, the bit
+// cursor is kept across the boundaries between sources.
+pub struct ChainedReader<R: Read> {
+    sources: VecDeque<R>,
+}
+
+impl<R: Read> ChainedReader<R> {
+    pub fn new() -> Self {
+        Self {
+            sources: VecDeque::new(),
+        }
+    }
+
+    // More sources can be pushed as they arrive, even after the previous ones have been read.
+    pub fn push(&mut self, source: R) {
+        self.sources.push_back(source);
+    }
+}
+
+impl<R: Read> Default for ChainedReader<R> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<R: Read> FromIterator<R> for ChainedReader<R> {
+    fn from_iter<I: IntoIterator<Item = R>>(iter: I) -> Self {
+        Self {
+            sources: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl<R: Read> Read for ChainedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while let Some(source) = self.sources.front_mut() {
+            let n = source.read(buf)?;
+            if n > 0 || buf.is_empty() {
+                return Ok(n);
+            }
+
+            self.sources.pop_front();
+        }
+
+        Ok(0)
+    }
+}
+
+pub type ChainedBitReader<R> = BitReader<ChainedReader<R>>;
+
+impl<R: Read> ChainedBitReader<R> {
+    pub fn push_source(&mut self, source: R) {
+        self.get_mut().push(source);
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use crate::{BitRead, BitReader};
+
+    use super::ChainedReader;
+
+    #[test]
+    fn read_bits_across_sources() {
+        let sources: ChainedReader<&[u8]> = [&[0b11110000][..], &[], &[0b00110011]]
+            .into_iter()
+            .collect();
+        let mut reader = BitReader::new(sources);
+
+        assert_eq!(reader.read_bits(4).unwrap(), 0);
+        assert_eq!(reader.read_byte().unwrap(), 0b0011_1111);
+        assert_eq!(reader.read_bits(4).unwrap(), 0b0011);
+        assert!(reader.try_read_bits(1).unwrap().is_none());
+    }
+
+    #[test]
+    fn push_source_after_reaching_the_end() {
+        let mut reader = BitReader::new(ChainedReader::new());
+        reader.push_source(&[0b11110000][..]);
+
+        assert_eq!(reader.read_bits(4).unwrap(), 0);
+        assert_eq!(reader.fill_lookahead(8).unwrap(), (0b1111, 4));
+
+        reader.push_source(&[0b00110011][..]);
+
+        assert_eq!(reader.read_byte().unwrap(), 0b0011_1111);
+        assert_eq!(reader.bit_cursor(), 4);
+    }
+}
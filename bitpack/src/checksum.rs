@@ -0,0 +1,171 @@
+use std::io;
+
+use crate::{u8_mask, BitWrite};
+
+const STAGING_SIZE: usize = 64;
+
+pub trait Checksum {
+    fn update(&mut self, bytes: &[u8]);
+}
+
+impl Checksum for crc32fast::Hasher {
+    fn update(&mut self, bytes: &[u8]) {
+        crc32fast::Hasher::update(self, bytes);
+    }
+}
+
+#[cfg(feature = "xxhash")]
+impl Checksum for xxhash_rust::xxh3::Xxh3 {
+    fn update(&mut self, bytes: &[u8]) {
+        xxhash_rust::xxh3::Xxh3::update(self, bytes);
+    }
+}
+
+// Updates a checksum while the bits are written to the inner writer, so the output doesn't need
+// to be read again. The checksum covers the bytes a `BitWriter` starting aligned would send: the
+// bits are packed from the lowest one, and the last partial byte is padded with zeros on `flush`.
+pub struct ChecksumBitWriter<W: BitWrite, C: Checksum> {
+    inner: W,
+    checksum: C,
+    bit_buff: u8,
+    bit_cursor: usize,
+    staging: [u8; STAGING_SIZE],
+    staging_len: usize,
+}
+
+pub type Crc32BitWriter<W> = ChecksumBitWriter<W, crc32fast::Hasher>;
+
+impl<W: BitWrite, C: Checksum> ChecksumBitWriter<W, C> {
+    pub fn new(inner: W, checksum: C) -> Self {
+        Self {
+            inner,
+            checksum,
+            bit_buff: 0,
+            bit_cursor: 0,
+            staging: [0; STAGING_SIZE],
+            staging_len: 0,
+        }
+    }
+
+    // The checksum of the complete bytes written so far, the pending bits are only added by `flush`.
+    pub fn checksum(&mut self) -> &C {
+        self.update_staged();
+        &self.checksum
+    }
+
+    pub fn into_parts(mut self) -> (W, C) {
+        self.update_staged();
+        (self.inner, self.checksum)
+    }
+
+    fn record(&mut self, bits: u8, amount: usize) {
+        let bits = (bits & u8_mask(amount as u32)) as u16;
+        let buff = self.bit_buff as u16 | bits << self.bit_cursor;
+
+        self.bit_cursor += amount;
+        if self.bit_cursor >= u8::BITS as usize {
+            self.stage(buff as u8);
+
+            self.bit_cursor -= u8::BITS as usize;
+            self.bit_buff = (buff >> u8::BITS) as u8;
+        } else {
+            self.bit_buff = buff as u8;
+        }
+    }
+
+    fn stage(&mut self, byte: u8) {
+        if self.staging_len == STAGING_SIZE {
+            self.update_staged();
+        }
+
+        self.staging[self.staging_len] = byte;
+        self.staging_len += 1;
+    }
+
+    fn update_staged(&mut self) {
+        self.checksum.update(&self.staging[..self.staging_len]);
+        self.staging_len = 0;
+    }
+}
+
+impl<W: BitWrite> Crc32BitWriter<W> {
+    pub fn new_crc32(inner: W) -> Self {
+        Self::new(inner, crc32fast::Hasher::new())
+    }
+}
+
+impl<W: BitWrite, C: Checksum> BitWrite for ChecksumBitWriter<W, C> {
+    fn write_bits(&mut self, bits: u8, amount: usize) -> io::Result<()> {
+        assert!(amount <= u8::BITS as usize);
+
+        self.inner.write_bits(bits, amount)?;
+        self.record(bits, amount);
+
+        Ok(())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.inner.write_byte(byte)?;
+        self.record(byte, u8::BITS as usize);
+
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()?;
+
+        if self.bit_cursor != 0 {
+            self.stage(self.bit_buff);
+            self.bit_buff = 0;
+            self.bit_cursor = 0;
+        }
+        self.update_staged();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use crate::{compact::CompactNumberU64, BitWrite, BitWriter};
+
+    use super::{ChecksumBitWriter, Crc32BitWriter};
+
+    fn write_sample<W: BitWrite>(writer: &mut W) {
+        writer.write_bits(0b101, 3).unwrap();
+        for i in 0..200u8 {
+            writer.write_byte(i).unwrap();
+            writer.write_bits(0xFF, i as usize % 9).unwrap();
+        }
+        writer.write_writable(CompactNumberU64(1 << 40)).unwrap();
+        writer.write_bits(0b11, 2).unwrap();
+        writer.flush().unwrap();
+    }
+
+    #[test]
+    fn crc32_matches_the_output() {
+        let mut writer = Crc32BitWriter::new_crc32(BitWriter::new(Vec::new()));
+        write_sample(&mut writer);
+
+        let (inner, checksum) = writer.into_parts();
+        assert_eq!(checksum.finalize(), crc32fast::hash(&inner.into_inner()));
+    }
+
+    #[test]
+    fn checksum_covers_the_bytes_in_order() {
+        let mut writer = ChecksumBitWriter::new(BitWriter::new(Vec::new()), Bytes(Vec::new()));
+        write_sample(&mut writer);
+
+        let (inner, checksum) = writer.into_parts();
+        assert_eq!(checksum.0, inner.into_inner());
+    }
+
+    struct Bytes(Vec<u8>);
+
+    impl super::Checksum for Bytes {
+        fn update(&mut self, bytes: &[u8]) {
+            self.0.extend_from_slice(bytes);
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use std::io;
+
+use crate::{compact::CompactNumberU64, BitRead, BitReadable, BitWritable, BitWrite};
+
+// Don't trust the length prefix for the initial allocation, it could come from a malformed input.
+const MAX_INITIAL_CAPACITY: usize = 4096;
+
+fn write_length<W: BitWrite>(writer: &mut W, length: usize) -> io::Result<()> {
+    writer.write_writable(CompactNumberU64(length as u64))
+}
+
+fn read_length<R: BitRead>(reader: &mut R) -> io::Result<usize> {
+    let CompactNumberU64(length) = reader.read_readable()?;
+    usize::try_from(length).map_err(|_| io::ErrorKind::InvalidData.into())
+}
+
+impl<T: BitWritable> BitWritable for Vec<T> {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        write_length(writer, self.len())?;
+        for item in self {
+            writer.write_writable(item)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: BitReadable> BitReadable for Vec<T> {
+    fn read<R: BitRead>(reader: &mut R) -> io::Result<Self> {
+        let length = read_length(reader)?;
+
+        let mut vec = Vec::with_capacity(length.min(MAX_INITIAL_CAPACITY));
+        for _ in 0..length {
+            vec.push(reader.read_readable()?);
+        }
+
+        Ok(vec)
+    }
+}
+
+impl BitWritable for String {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        write_length(writer, self.len())?;
+        writer.write_bytes(self.as_bytes(), None)
+    }
+}
+
+impl BitReadable for String {
+    fn read<R: BitRead>(reader: &mut R) -> io::Result<Self> {
+        let length = read_length(reader)?;
+
+        let mut bytes = Vec::with_capacity(length.min(MAX_INITIAL_CAPACITY));
+        for _ in 0..length {
+            bytes.push(reader.read_byte()?);
+        }
+
+        String::from_utf8(bytes).map_err(|_| io::ErrorKind::InvalidData.into())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use crate::{compact::CompactNumberU64, BitRead, BitWrite};
+
+    #[test]
+    fn write_string() {
+        let output =
+            crate::test::get_test_write_output(|writer| writer.write_writable("abc".to_string()))
+                .unwrap();
+
+        assert_eq!(&output.vec, &[1, 3, b'a', b'b', b'c']);
+    }
+
+    #[test]
+    fn write_vec() {
+        let output = crate::test::get_test_write_output(|writer| {
+            writer.write_writable(vec![CompactNumberU64(1), CompactNumberU64(256)])
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[1, 2, 1, 1, 2, 0, 1]);
+    }
+
+    #[test]
+    fn read_string_not_aligned() {
+        let output = crate::test::get_test_read_custom_readable_output(
+            &[0b0001_0000, 0b0001_0000, 0b0001_0000, 0b0110],
+            |reader| {
+                reader.read_bits(4)?;
+                reader.read_readable::<String>()
+            },
+        )
+        .unwrap();
+
+        assert_eq!(output.result, "a");
+        assert_eq!(output.cursor_position, 4);
+    }
+
+    #[test]
+    fn string_round_trip() {
+        crate::test::assert_round_trip(String::new());
+        crate::test::assert_round_trip("hello, wörld".to_string());
+    }
+
+    #[test]
+    fn vec_round_trip() {
+        crate::test::assert_round_trip(Vec::<CompactNumberU64>::new());
+        crate::test::assert_round_trip(vec![CompactNumberU64(0), CompactNumberU64(!0)]);
+        crate::test::assert_round_trip(vec![vec!["a".to_string()], vec![]]);
+    }
+
+    mod malformed {
+        use crate::test::TestOutputGeneric;
+
+        #[test]
+        #[should_panic]
+        fn string_with_invalid_utf8() {
+            let _: TestOutputGeneric<String> =
+                crate::test::get_test_read_readable_output(&[1, 2, 0xC3, 0x28]).unwrap();
+        }
+
+        #[test]
+        #[should_panic]
+        fn string_shorter_than_its_length() {
+            let _: TestOutputGeneric<String> = crate::test::get_test_read_readable_output(&[
+                8, 255, 255, 255, 255, 255, 255, 255, 255, b'a',
+            ])
+            .unwrap();
+        }
+
+        #[test]
+        #[cfg(target_pointer_width = "32")]
+        fn length_bigger_than_usize() {
+            let error = crate::test::get_test_read_readable_output::<Vec<String>>(&[
+                5, 0, 0, 0, 0, 1,
+            ])
+            .err()
+            .unwrap();
+
+            assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+        }
+    }
+}
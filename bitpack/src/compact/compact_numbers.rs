@@ -1,57 +1,100 @@
 use std::io;
 
-use crate::{BitReadable, BitWritable};
+use crate::{BitRead, BitReadable, BitWritable, BitWrite};
 
 trait NumberInfo {
     fn required_number_of_bytes(&self) -> u8;
 }
 
-impl NumberInfo for u64 {
-    fn required_number_of_bytes(&self) -> u8 {
-        let mut n = 1;
-        loop {
-            let max = u64::checked_shl(1, n as u32 * u8::BITS)
-                .unwrap_or(0)
-                .wrapping_sub(1);
-            if max >= *self {
-                return n;
+macro_rules! impl_number_info {
+    ($($number:ty),*) => {$(
+        impl NumberInfo for $number {
+            fn required_number_of_bytes(&self) -> u8 {
+                let mut n = 1;
+                loop {
+                    let max = <$number>::checked_shl(1, n as u32 * u8::BITS)
+                        .unwrap_or(0)
+                        .wrapping_sub(1);
+                    if max >= *self {
+                        return n;
+                    }
+
+                    n += 1;
+                }
             }
-
-            n += 1;
         }
-    }
+    )*};
+}
+
+impl_number_info!(u64, u128);
+
+// Writes the amount of bytes required, followed by those lowest bytes of the little endian bytes.
+fn write_length_prefixed<W: BitWrite>(
+    writer: &mut W,
+    le_bytes: &[u8],
+    bytes_required: u8,
+) -> io::Result<()> {
+    writer.write_byte(bytes_required)?;
+    writer.write_bytes(&le_bytes[..bytes_required as usize], None)?;
+
+    Ok(())
+}
+
+fn read_length_prefixed<R: BitRead, const N: usize>(reader: &mut R) -> io::Result<[u8; N]> {
+    let bytes_required = reader.read_byte()?;
+
+    if bytes_required as usize > N {
+        return Err(io::ErrorKind::InvalidData.into())
+    } 
+
+    let mut bytes = [0u8; N];
+    reader.read_bytes(&mut bytes[..bytes_required as usize], None)?;
+
+    Ok(bytes)
 }
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct CompactNumberU64(pub u64);
 
+impl CompactNumberU64 {
+    pub fn encoded_len(&self) -> usize {
+        1 + self.0.required_number_of_bytes() as usize
+    }
+}
+
 impl BitWritable for CompactNumberU64 {
     fn write<W: crate::BitWrite>(&self, writer: &mut W) -> std::io::Result<()> {
         // TODO : That can be optimized further
 
-        let bytes_required = self.0.required_number_of_bytes();
-        writer.write_byte(bytes_required)?;
-
-        let bytes = self.0.to_le_bytes();
-        writer.write_bytes(&bytes.as_ref()[..bytes_required as usize], None)?;
-
-        Ok(())
+        write_length_prefixed(writer, &self.0.to_le_bytes(), self.0.required_number_of_bytes())
     }
 }
 
 impl BitReadable for CompactNumberU64 {
     fn read<R: crate::BitRead>(reader: &mut R) -> std::io::Result<Self> {
-        let bytes_required = reader.read_byte()?;
+        Ok(Self(u64::from_le_bytes(read_length_prefixed(reader)?)))
+    }
+}
 
-        const MAX_BYTES_REQUIRED: usize = (u64::BITS / u8::BITS) as usize;
-        if bytes_required as usize > MAX_BYTES_REQUIRED {
-            return Err(io::ErrorKind::InvalidData.into())
-        } 
+// Same encoding as `CompactNumberU64`, for numbers up to 16 bytes like hashes.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CompactNumberU128(pub u128);
 
-        let mut bytes = [0u8; (u64::BITS / u8::BITS) as usize];
-        reader.read_bytes(&mut bytes[..bytes_required as usize], None)?;
+impl CompactNumberU128 {
+    pub fn encoded_len(&self) -> usize {
+        1 + self.0.required_number_of_bytes() as usize
+    }
+}
 
-        Ok(Self(u64::from_le_bytes(bytes)))
+impl BitWritable for CompactNumberU128 {
+    fn write<W: crate::BitWrite>(&self, writer: &mut W) -> std::io::Result<()> {
+        write_length_prefixed(writer, &self.0.to_le_bytes(), self.0.required_number_of_bytes())
+    }
+}
+
+impl BitReadable for CompactNumberU128 {
+    fn read<R: crate::BitRead>(reader: &mut R) -> std::io::Result<Self> {
+        Ok(Self(u128::from_le_bytes(read_length_prefixed(reader)?)))
     }
 }
 
@@ -60,7 +103,7 @@ impl BitReadable for CompactNumberU64 {
 mod tests {
     use crate::{test::TestOutputGeneric, BitWrite};
 
-    use super::CompactNumberU64;
+    use super::{CompactNumberU128, CompactNumberU64};
 
     #[test]
     fn write_u8_number() {
@@ -214,8 +257,72 @@ mod tests {
         assert_eq!(output.result, CompactNumberU64(!0));
     }
 
+    #[test]
+    fn round_trip_every_size() {
+        for bytes_required in 1..=8 {
+            let max = u64::checked_shl(1, bytes_required * u8::BITS)
+                .unwrap_or(0)
+                .wrapping_sub(1);
+
+            crate::test::assert_round_trip(CompactNumberU64(max));
+        }
+    }
+
+    #[test]
+    fn write_u128_number() {
+        let output = crate::test::get_test_write_output(|writer| {
+            writer.write_writable(CompactNumberU128(1 << 64))
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[9, 0, 0, 0, 0, 0, 0, 0, 0, 1]);
+
+        let output = crate::test::get_test_write_output(|writer| {
+            writer.write_writable(CompactNumberU128(0xFF))
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[1, 255]);
+    }
+
+    #[test]
+    fn read_u128_number() {
+        let output: TestOutputGeneric<CompactNumberU128> =
+            crate::test::get_test_read_readable_output(&[16; 17]).unwrap();
+
+        assert_eq!(output.result, CompactNumberU128(u128::from_le_bytes([16; 16])));
+    }
+
+    #[test]
+    fn round_trip_every_u128_size() {
+        for bytes_required in 1..=16 {
+            let max = u128::checked_shl(1, bytes_required * u8::BITS)
+                .unwrap_or(0)
+                .wrapping_sub(1);
+
+            crate::test::assert_round_trip(CompactNumberU128(max));
+            assert_eq!(CompactNumberU128(max).encoded_len(), 1 + bytes_required as usize);
+        }
+    }
+
+    mod property {
+        use proptest::prelude::*;
+
+        use crate::{compact::CompactNumberU64, test::strategy};
+
+        proptest! {
+            #[test]
+            fn round_trip_at_any_alignment(number in any::<u64>(), padding in strategy::write_plan(4)) {
+                strategy::check_readable_round_trip(&CompactNumberU64(number), &padding)?;
+            }
+        }
+    }
+
     mod malformed {
-        use crate::{compact::CompactNumberU64, test::TestOutputGeneric};
+        use crate::{
+            compact::{CompactNumberU128, CompactNumberU64},
+            test::TestOutputGeneric,
+        };
 
         #[test]
         #[should_panic]
@@ -223,5 +330,12 @@ mod tests {
             let _: TestOutputGeneric<CompactNumberU64> =
                 crate::test::get_test_read_readable_output(&[9, 0, 0, 0, 0, 1]).unwrap();
         }
+
+        #[test]
+        #[should_panic]
+        fn u128_size_is_bigger_than_the_maximum_amount_of_bytes() {
+            let _: TestOutputGeneric<CompactNumberU128> =
+                crate::test::get_test_read_readable_output(&[17; 18]).unwrap();
+        }
     }
 }
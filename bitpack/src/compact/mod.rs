@@ -1,3 +1,7 @@
 mod compact_numbers;
+mod scheme;
+mod varint;
 
 pub use compact_numbers::*;
+pub use scheme::*;
+pub use varint::*;
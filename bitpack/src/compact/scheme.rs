@@ -0,0 +1,66 @@
+use std::io;
+
+use crate::{BitRead, BitWrite};
+
+use super::{CompactNumberU64, VarintU64};
+
+// The encodings available for variable length numbers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompactScheme {
+    // A byte holding the amount of bytes of the number, followed by those bytes.
+    #[default]
+    LengthPrefixed,
+    // 7 bits per byte, with a continuation bit.
+    Varint,
+}
+
+impl CompactScheme {
+    pub fn write_u64<W: BitWrite>(self, writer: &mut W, value: u64) -> io::Result<()> {
+        match self {
+            Self::LengthPrefixed => writer.write_writable(CompactNumberU64(value)),
+            Self::Varint => writer.write_writable(VarintU64(value)),
+        }
+    }
+
+    pub fn read_u64<R: BitRead>(self, reader: &mut R) -> io::Result<u64> {
+        match self {
+            Self::LengthPrefixed => Ok(reader.read_readable::<CompactNumberU64>()?.0),
+            Self::Varint => Ok(reader.read_readable::<VarintU64>()?.0),
+        }
+    }
+
+    // The amount of bytes taken by `value` once encoded.
+    pub fn encoded_len(self, value: u64) -> usize {
+        match self {
+            Self::LengthPrefixed => CompactNumberU64(value).encoded_len(),
+            Self::Varint => VarintU64(value).encoded_len(),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::{BitReader, BitWrite, BitWriter};
+
+    use super::CompactScheme;
+
+    #[test]
+    fn round_trip_and_encoded_len() {
+        for scheme in [CompactScheme::LengthPrefixed, CompactScheme::Varint] {
+            for value in [0, 1, 127, 128, 300, 1 << 32, u64::MAX] {
+                let mut writer = BitWriter::new(Vec::new());
+                scheme.write_u64(&mut writer, value).unwrap();
+                writer.flush().unwrap();
+
+                let bytes = writer.into_inner();
+                assert_eq!(bytes.len(), scheme.encoded_len(value), "{scheme:?} {value}");
+
+                let mut reader = BitReader::new(Cursor::new(bytes));
+                assert_eq!(scheme.read_u64(&mut reader).unwrap(), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use std::io;
+
+use crate::{BitRead, BitReadable, BitWritable, BitWrite};
+
+const CONTINUATION_FLAG: u8 = 0b1000_0000;
+const GROUP_BITS: u32 = u8::BITS - 1;
+const MAX_GROUPS: u32 = u64::BITS.div_ceil(GROUP_BITS);
+
+// Stores the number in groups of 7 bits, lowest group first, each byte having its top bit set when
+// another group follows.
+#[derive(Debug, PartialEq, Eq)]
+pub struct VarintU64(pub u64);
+
+impl VarintU64 {
+    pub fn encoded_len(&self) -> usize {
+        let significant_bits = (u64::BITS - self.0.leading_zeros()).max(1);
+        significant_bits.div_ceil(GROUP_BITS) as usize
+    }
+}
+
+impl BitWritable for VarintU64 {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        let mut value = self.0;
+        loop {
+            let group = value as u8 & !CONTINUATION_FLAG;
+            value >>= GROUP_BITS;
+
+            if value == 0 {
+                return writer.write_byte(group);
+            }
+            writer.write_byte(group | CONTINUATION_FLAG)?;
+        }
+    }
+}
+
+impl BitReadable for VarintU64 {
+    fn read<R: BitRead>(reader: &mut R) -> io::Result<Self> {
+        let mut value = 0u64;
+        for i in 0..MAX_GROUPS {
+            let byte = reader.read_byte()?;
+
+            let group = (byte & !CONTINUATION_FLAG) as u64;
+            let shift = i * GROUP_BITS;
+            if group.checked_shl(shift).unwrap_or(0) >> shift != group {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "The varint doesn't fit in 64 bits.",
+                ));
+            }
+            value |= group << shift;
+
+            if byte & CONTINUATION_FLAG == 0 {
+                return Ok(Self(value));
+            }
+        }
+
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "The varint doesn't fit in 64 bits.",
+        ))
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use crate::{test::TestOutputGeneric, BitWrite};
+
+    use super::VarintU64;
+
+    #[test]
+    fn write_single_group() {
+        let output =
+            crate::test::get_test_write_output(|writer| writer.write_writable(VarintU64(0)))
+                .unwrap();
+        assert_eq!(&output.vec, &[0]);
+
+        let output =
+            crate::test::get_test_write_output(|writer| writer.write_writable(VarintU64(127)))
+                .unwrap();
+        assert_eq!(&output.vec, &[127]);
+    }
+
+    #[test]
+    fn write_several_groups() {
+        let output =
+            crate::test::get_test_write_output(|writer| writer.write_writable(VarintU64(300)))
+                .unwrap();
+        assert_eq!(&output.vec, &[0b1010_1100, 0b0000_0010]);
+
+        let output =
+            crate::test::get_test_write_output(|writer| writer.write_writable(VarintU64(!0)))
+                .unwrap();
+        assert_eq!(&output.vec, &[255, 255, 255, 255, 255, 255, 255, 255, 255, 1]);
+    }
+
+    #[test]
+    fn read_several_groups() {
+        let output: TestOutputGeneric<VarintU64> =
+            crate::test::get_test_read_readable_output(&[0b1010_1100, 0b0000_0010]).unwrap();
+
+        assert_eq!(output.result, VarintU64(300));
+    }
+
+    #[test]
+    fn encoded_len() {
+        assert_eq!(VarintU64(0).encoded_len(), 1);
+        assert_eq!(VarintU64(127).encoded_len(), 1);
+        assert_eq!(VarintU64(128).encoded_len(), 2);
+        assert_eq!(VarintU64(!0).encoded_len(), 10);
+    }
+
+    #[test]
+    fn round_trip_every_size() {
+        for bits in 0..=u64::BITS {
+            let max = u64::checked_shl(1, bits).unwrap_or(0).wrapping_sub(1);
+            crate::test::assert_round_trip(VarintU64(max));
+        }
+    }
+
+    mod malformed {
+        use crate::{compact::VarintU64, test::TestOutputGeneric};
+
+        #[test]
+        #[should_panic]
+        fn too_many_groups() {
+            let _: TestOutputGeneric<VarintU64> =
+                crate::test::get_test_read_readable_output(&[255; 11]).unwrap();
+        }
+
+        #[test]
+        #[should_panic]
+        fn last_group_overflows() {
+            let _: TestOutputGeneric<VarintU64> = crate::test::get_test_read_readable_output(&[
+                255, 255, 255, 255, 255, 255, 255, 255, 255, 2,
+            ])
+            .unwrap();
+        }
+    }
+}
@@ -0,0 +1,114 @@
+use std::io;
+
+use crate::{BitRead, BitWrite};
+
+const COPY_BUFFER_SIZE: usize = 4096;
+
+// Transfers exactly `n_bits` bits from the reader to the writer, a buffer of whole bytes at a time.
+// Both sides pass the buffer through in bulk when they are aligned.
+pub fn copy_bits<R: BitRead, W: BitWrite>(reader: &mut R, writer: &mut W, n_bits: u64) -> io::Result<()> {
+    let mut buf = [0u8; COPY_BUFFER_SIZE];
+
+    let mut bytes_left = n_bits / u8::BITS as u64;
+    while bytes_left > 0 {
+        let n = bytes_left.min(COPY_BUFFER_SIZE as u64) as usize;
+
+        reader.read_bytes(&mut buf[..n], None)?;
+        writer.write_bytes(&buf[..n], None)?;
+
+        bytes_left -= n as u64;
+    }
+
+    let bits_left = (n_bits % u8::BITS as u64) as usize;
+    if bits_left > 0 {
+        let bits = reader.read_bits(bits_left)?;
+        writer.write_bits(bits, bits_left)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use std::io::Cursor;
+
+    use crate::{BitRead, BitReader, BitWrite, BitWriter};
+
+    use super::copy_bits;
+
+    #[test]
+    fn copy_nothing() {
+        let output = crate::test::get_test_write_output(|writer| {
+            let mut reader = BitReader::new(Cursor::new([1, 2]));
+            copy_bits(&mut reader, writer, 0)
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[]);
+        assert_eq!(output.cursor_position, 0);
+    }
+
+    #[test]
+    fn copy_aligned_bytes() {
+        let output = crate::test::get_test_write_output(|writer| {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            copy_bits(&mut reader, writer, 24)
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[1, 2, 3]);
+        assert_eq!(output.cursor_position, 0);
+    }
+
+    #[test]
+    fn copy_more_than_the_buffer_size() {
+        let input = (0..10_000).map(|i| i as u8).collect::<Vec<_>>();
+
+        let output = crate::test::get_test_write_output(|writer| {
+            let mut reader = BitReader::new(Cursor::new(&input));
+            copy_bits(&mut reader, writer, input.len() as u64 * 8)
+        })
+        .unwrap();
+
+        assert_eq!(output.vec, input);
+    }
+
+    #[test]
+    fn copy_large_aligned_buffer() {
+        let input = (0..1_000_000).map(|i| (i * 7 + i / 251) as u8).collect::<Vec<_>>();
+
+        let mut reader = BitReader::new(Cursor::new(&input));
+        let mut writer = BitWriter::new(Vec::new());
+        copy_bits(&mut reader, &mut writer, input.len() as u64 * 8).unwrap();
+
+        assert!(writer.is_aligned());
+        assert_eq!(writer.bytes_written(), input.len() as u64);
+        assert_eq!(writer.into_inner(), input);
+    }
+
+    #[test]
+    fn copy_not_aligned_bits() {
+        let output = crate::test::get_test_write_output(|writer| {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011]));
+            reader.read_bits(4)?;
+
+            writer.write_bits(0b1, 1)?;
+            copy_bits(&mut reader, writer, 10)
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[0b0111_1111, 0b110]);
+        assert_eq!(output.cursor_position, 3);
+    }
+
+    #[test]
+    #[should_panic]
+    fn copy_more_than_available_should_fail() {
+        crate::test::get_test_write_output(|writer| {
+            let mut reader = BitReader::new(Cursor::new([1]));
+            copy_bits(&mut reader, writer, 9)
+        })
+        .unwrap();
+    }
+}
@@ -0,0 +1,112 @@
+use std::io::{self, Read};
+
+use crate::{BitRead, BitReader};
+
+// What a read going past the end of the input does.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum EofPolicy {
+    // Returns `Ok(None)` without consuming anything, so the read can be retried once more data
+    // arrived.
+    #[default]
+    ReturnNone,
+    // Fails with `io::ErrorKind::UnexpectedEof`.
+    UnexpectedEof,
+    // Consumes the remaining bits and fills the missing ones with zeros.
+    ZeroFill,
+}
+
+pub struct EofPolicyReader<R: Read> {
+    inner: BitReader<R>,
+    policy: EofPolicy,
+}
+
+impl<R: Read> EofPolicyReader<R> {
+    pub fn new(inner: BitReader<R>, policy: EofPolicy) -> Self {
+        Self { inner, policy }
+    }
+
+    pub fn into_inner(self) -> BitReader<R> {
+        self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut BitReader<R> {
+        &mut self.inner
+    }
+
+    pub fn policy(&self) -> EofPolicy {
+        self.policy
+    }
+}
+
+impl<R: Read> BitRead for EofPolicyReader<R> {
+    fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.try_read_bits(u8::BITS as usize)
+    }
+
+    fn try_read_bits(&mut self, amount: usize) -> io::Result<Option<u8>> {
+        assert!(amount <= u8::BITS as usize);
+
+        // the bits are only consumed once we know what to do with them.
+        let (bits, available) = self.inner.fill_lookahead(amount)?;
+        if available == amount {
+            self.inner.consume_bits(amount)?;
+            return Ok(Some(bits as u8));
+        }
+
+        match self.policy {
+            EofPolicy::ReturnNone => Ok(None),
+            EofPolicy::UnexpectedEof => Err(io::ErrorKind::UnexpectedEof.into()),
+            EofPolicy::ZeroFill => {
+                self.inner.consume_bits(available)?;
+                Ok(Some(bits as u8))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use std::io;
+
+    use crate::{BitRead, BitReader, ChainedReader};
+
+    use super::{EofPolicy, EofPolicyReader};
+
+    fn reader(bytes: &[u8], policy: EofPolicy) -> EofPolicyReader<&[u8]> {
+        EofPolicyReader::new(BitReader::new(bytes), policy)
+    }
+
+    #[test]
+    fn return_none_without_consuming() {
+        let mut reader = EofPolicyReader::new(
+            BitReader::new(ChainedReader::from_iter([&[0b1111_0000][..]])),
+            EofPolicy::ReturnNone,
+        );
+        reader.read_bits(4).unwrap();
+
+        assert_eq!(reader.try_read_byte().unwrap(), None);
+
+        reader.get_mut().push_source(&[0b0011_0011][..]);
+        assert_eq!(reader.try_read_byte().unwrap(), Some(0b0011_1111));
+    }
+
+    #[test]
+    fn unexpected_eof() {
+        let mut reader = reader(&[0b1111_0000], EofPolicy::UnexpectedEof);
+        reader.read_bits(4).unwrap();
+
+        let error = reader.try_read_byte().unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn zero_fill() {
+        let mut reader = reader(&[0b1111_0000], EofPolicy::ZeroFill);
+        reader.read_bits(4).unwrap();
+
+        assert_eq!(reader.try_read_byte().unwrap(), Some(0b1111));
+        assert_eq!(reader.try_read_bits(3).unwrap(), Some(0));
+        assert_eq!(reader.read_u16(crate::Endianness::Little).unwrap(), 0);
+    }
+}
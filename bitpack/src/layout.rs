@@ -0,0 +1,260 @@
+use std::io::{self, Write};
+
+use crate::{BitWritable, BitWrite};
+
+const MAX_DUMPED_FIELD_BITS: u64 = 64;
+const DUMP_BYTES_PER_LINE: usize = 16;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LabeledField {
+    pub label: String,
+    // How many labelled fields this one is nested in.
+    pub depth: usize,
+    pub start: u64,
+    pub end: u64,
+}
+
+impl LabeledField {
+    pub fn len(&self) -> u64 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+// Forwards everything to the inner writer while keeping a copy of the written bits and the
+// position of every field written through `write_labeled`, so the layout can be dumped later.
+pub struct LabeledBitWriter<W: BitWrite> {
+    inner: W,
+    fields: Vec<LabeledField>,
+    bits: Vec<u8>,
+    bit_len: u64,
+    depth: usize,
+}
+
+impl<W: BitWrite> LabeledBitWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            fields: Vec::new(),
+            bits: Vec::new(),
+            bit_len: 0,
+            depth: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    pub fn fields(&self) -> &[LabeledField] {
+        &self.fields
+    }
+
+    pub fn bit_len(&self) -> u64 {
+        self.bit_len
+    }
+
+    // The written bits, packed the same way as in the output, without the final padding.
+    pub fn recorded_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    // The value of a field, read the same way it was written. Fields wider than 64 bits have no
+    // value.
+    pub fn field_value(&self, field: &LabeledField) -> Option<u64> {
+        if field.len() > u64::BITS as u64 {
+            return None;
+        }
+
+        Some(
+            (0..field.len())
+                .map(|i| (self.bit_at(field.start + i) as u64) << i)
+                .sum(),
+        )
+    }
+
+    pub fn dump<O: Write>(&self, out: &mut O) -> io::Result<()> {
+        for field in &self.fields {
+            let shown_len = field.len().min(MAX_DUMPED_FIELD_BITS);
+            let mut bits: String = (0..shown_len)
+                .rev()
+                .map(|i| char::from(b'0' + self.bit_at(field.start + i)))
+                .collect();
+            if shown_len < field.len() {
+                bits.insert(0, '…');
+            }
+
+            let range = format!("{}..{}", field.start, field.end);
+            let label = format!("{}{}", "  ".repeat(field.depth), field.label);
+            write!(out, "{range:<16} {label:<24} {bits}")?;
+
+            if let Some(value) = self.field_value(field) {
+                write!(out, " = {value:#x}")?;
+            }
+            writeln!(out)?;
+        }
+
+        for (line, chunk) in self.bits.chunks(DUMP_BYTES_PER_LINE).enumerate() {
+            write!(out, "{:08x}:", line * DUMP_BYTES_PER_LINE)?;
+            for byte in chunk {
+                write!(out, " {byte:02x}")?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    fn bit_at(&self, index: u64) -> u8 {
+        (self.bits[(index / 8) as usize] >> (index % 8)) & 1
+    }
+
+    fn record(&mut self, bits: u8, amount: usize) {
+        for i in 0..amount {
+            let bit_cursor = self.bit_len % 8;
+            if bit_cursor == 0 {
+                self.bits.push(0);
+            }
+
+            *self.bits.last_mut().unwrap() |= ((bits >> i) & 1) << bit_cursor;
+            self.bit_len += 1;
+        }
+    }
+}
+
+impl<W: BitWrite> BitWrite for LabeledBitWriter<W> {
+    fn write_labeled<Bw: BitWritable>(&mut self, label: &str, writable: Bw) -> io::Result<()> {
+        let index = self.fields.len();
+        self.fields.push(LabeledField {
+            label: label.to_owned(),
+            depth: self.depth,
+            start: self.bit_len,
+            end: self.bit_len,
+        });
+
+        self.depth += 1;
+        let result = writable.write(self);
+        self.depth -= 1;
+
+        self.fields[index].end = self.bit_len;
+        result
+    }
+
+    fn write_bits(&mut self, bits: u8, amount: usize) -> io::Result<()> {
+        self.record(bits, amount);
+        self.inner.write_bits(bits, amount)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.record(byte, u8::BITS as usize);
+        self.inner.write_byte(byte)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use std::io;
+
+    use crate::{BitWritable, BitWrite, BitWriter};
+
+    use super::{LabeledBitWriter, LabeledField};
+
+    struct Header {
+        version: u8,
+        flags: u8,
+    }
+
+    impl BitWritable for Header {
+        fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_labeled("version", Version(self.version))?;
+            writer.write_bits(self.flags, 3)
+        }
+    }
+
+    struct Version(u8);
+
+    impl BitWritable for Version {
+        fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_bits(self.0, 4)
+        }
+    }
+
+    fn labeled_writer() -> LabeledBitWriter<BitWriter<Vec<u8>>> {
+        let mut writer = LabeledBitWriter::new(BitWriter::new(Vec::new()));
+        writer.write_bits(0b1, 1).unwrap();
+        writer
+            .write_labeled("header", Header { version: 0b1010, flags: 0b011 })
+            .unwrap();
+        writer.write_labeled("data", Byte(0xAB)).unwrap();
+        writer
+    }
+
+    struct Byte(u8);
+
+    impl BitWritable for Byte {
+        fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+            writer.write_byte(self.0)
+        }
+    }
+
+    #[test]
+    fn records_nested_fields() {
+        let writer = labeled_writer();
+
+        let field = |label: &str, depth, start, end| LabeledField {
+            label: label.to_owned(),
+            depth,
+            start,
+            end,
+        };
+        assert_eq!(
+            writer.fields(),
+            &[
+                field("header", 0, 1, 8),
+                field("version", 1, 1, 5),
+                field("data", 0, 8, 16),
+            ]
+        );
+
+        let values: Vec<_> = writer
+            .fields()
+            .iter()
+            .map(|field| writer.field_value(field))
+            .collect();
+        assert_eq!(values, [Some(0b011_1010), Some(0b1010), Some(0xAB)]);
+    }
+
+    #[test]
+    fn forwards_to_the_inner_writer() {
+        let mut writer = labeled_writer();
+        writer.write_bits(0b1, 1).unwrap();
+        writer.flush().unwrap();
+
+        assert_eq!(writer.recorded_bytes(), &[0b0111_0101, 0xAB, 0b1]);
+        assert_eq!(writer.into_inner().into_inner(), &[0b0111_0101, 0xAB, 0b1]);
+    }
+
+    #[test]
+    fn dump() {
+        let writer = labeled_writer();
+
+        let mut out = Vec::new();
+        writer.dump(&mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "1..8             header                   0111010 = 0x3a\n\
+             1..5               version                1010 = 0xa\n\
+             8..16            data                     10101011 = 0xab\n\
+             00000000: 75 ab\n"
+        );
+    }
+}
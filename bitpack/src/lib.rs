@@ -4,13 +4,52 @@ fn u8_mask(s: u32) -> u8 {
     1u8.checked_shl(s).unwrap_or(0).wrapping_sub(1)
 }
 
+// Reverses the order of the `width` lowest bits of `value`, the other bits are dropped.
+pub fn reverse_bits(value: u8, width: usize) -> u8 {
+    assert!(width <= u8::BITS as usize);
+
+    value
+        .reverse_bits()
+        .checked_shr(u8::BITS - width as u32)
+        .unwrap_or(0)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+// Logs a bit level operation when the `trace` feature is enabled, compiles to nothing otherwise.
+macro_rules! trace_op {
+    ($($arg:tt)*) => {
+        #[cfg(feature = "trace")]
+        log::trace!($($arg)*);
+    };
+}
+
 pub mod compact;
 
+mod chain;
+mod checksum;
+mod collections;
+mod copy;
+mod eof;
+mod layout;
 mod read;
+mod sink;
+mod ubits;
 mod write;
 
 use cfg_if::cfg_if;
+pub use chain::*;
+pub use checksum::*;
+pub use copy::*;
+pub use eof::*;
+pub use layout::*;
 pub use read::*;
+pub use sink::*;
+pub use ubits::*;
 pub use write::*;
 
 cfg_if!( if #[cfg(feature = "test_framework")] {
@@ -19,3 +58,30 @@ cfg_if!( if #[cfg(feature = "test_framework")] {
     #[cfg(test)]
     mod test;
 });
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use super::reverse_bits;
+
+    #[test]
+    fn reverse_full_byte() {
+        assert_eq!(reverse_bits(0b11010000, 8), 0b00001011);
+    }
+
+    #[test]
+    fn reverse_partial_width() {
+        assert_eq!(reverse_bits(0b110, 3), 0b011);
+        assert_eq!(reverse_bits(0b1, 1), 0b1);
+    }
+
+    #[test]
+    fn reverse_drops_bits_above_width() {
+        assert_eq!(reverse_bits(0b11111001, 3), 0b100);
+    }
+
+    #[test]
+    fn reverse_zero_width() {
+        assert_eq!(reverse_bits(0b11111111, 0), 0);
+    }
+}
@@ -1,6 +1,6 @@
-use std::io::{self, Read};
+use std::{collections::VecDeque, io::{self, Read, Seek, SeekFrom}};
 
-use crate::u8_mask;
+use crate::{reverse_bits, u8_mask, Endianness};
 
 pub trait BitTryReadable: Sized {
     fn try_read<R: BitRead>(reader: &mut R) -> io::Result<Option<Self>>;
@@ -38,6 +38,22 @@ pub trait BitRead: Sized {
         Ok(())
     }
 
+    // Reads `n_bits` bits into the first `n_bits.div_ceil(8)` bytes of `buf`, the first bit read
+    // being the lowest bit of `buf[0]`. The unused high bits of the last byte are set to zero.
+    // Returns the number of bytes filled.
+    fn read_bits_into(&mut self, buf: &mut [u8], n_bits: usize) -> io::Result<usize> {
+        let byte_amount = n_bits.div_ceil(u8::BITS as usize);
+        assert!(byte_amount <= buf.len());
+
+        let last_byte_amount = n_bits % u8::BITS as usize;
+        self.read_bytes(
+            &mut buf[..byte_amount],
+            (last_byte_amount != 0).then_some(last_byte_amount),
+        )?;
+
+        Ok(byte_amount)
+    }
+
     fn read_byte(&mut self) -> io::Result<u8> {
         let Some(byte) = self.try_read_byte()? else {
             return Err(io::ErrorKind::UnexpectedEof.into());
@@ -52,14 +68,84 @@ pub trait BitRead: Sized {
         Ok(bits)
     }
 
+    // Reads `amount` bits, the first one read being the most significant one.
+    fn read_bits_rev(&mut self, amount: usize) -> io::Result<u8> {
+        Ok(reverse_bits(self.read_bits(amount)?, amount))
+    }
+
+    fn read_u16(&mut self, endianness: Endianness) -> io::Result<u16> {
+        let mut bytes = [0; 2];
+        self.read_bytes(&mut bytes, None)?;
+
+        Ok(match endianness {
+            Endianness::Little => u16::from_le_bytes(bytes),
+            Endianness::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn read_u32(&mut self, endianness: Endianness) -> io::Result<u32> {
+        let mut bytes = [0; 4];
+        self.read_bytes(&mut bytes, None)?;
+
+        Ok(match endianness {
+            Endianness::Little => u32::from_le_bytes(bytes),
+            Endianness::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn read_u64(&mut self, endianness: Endianness) -> io::Result<u64> {
+        let mut bytes = [0; 8];
+        self.read_bytes(&mut bytes, None)?;
+
+        Ok(match endianness {
+            Endianness::Little => u64::from_le_bytes(bytes),
+            Endianness::Big => u64::from_be_bytes(bytes),
+        })
+    }
+
+    // Reads a field up to 64 bits wide, the first bit read being the lowest one.
+    fn read_bits_u64(&mut self, amount: usize) -> io::Result<u64> {
+        assert!(amount <= u64::BITS as usize);
+
+        let mut bits = 0u64;
+        let mut offset = 0;
+        while offset < amount {
+            let bits_to_read = (amount - offset).min(u8::BITS as usize);
+            bits |= (self.read_bits(bits_to_read)? as u64) << offset;
+
+            offset += bits_to_read;
+        }
+
+        Ok(bits)
+    }
+
+    // Reads a `width` bits two's complement value, and extends its sign.
+    fn read_signed_bits(&mut self, width: usize) -> io::Result<i64> {
+        if width == 0 {
+            return Ok(0);
+        }
+
+        let bits = self.read_bits_u64(width)?;
+
+        let unused_bits = u64::BITS - width as u32;
+        Ok(((bits << unused_bits) as i64) >> unused_bits)
+    }
+
     fn try_read_byte(&mut self) -> io::Result<Option<u8>>;
     fn try_read_bits(&mut self, amount: usize) -> io::Result<Option<u8>>;
 }
 
+// Under this amount of bytes, reading them one by one is faster than setting up the bulk read.
+const BULK_READ_THRESHOLD: usize = 8;
+
 pub struct BitReader<R: Read> {
     inner: R,
     bit_buff: Option<u8>,
     bit_cursor: usize,
+    // bytes pulled from the inner reader by `fill_lookahead`, not yet moved to `bit_buff`.
+    lookahead: VecDeque<u8>,
+    // bytes consumed since the start of the outermost `read_transaction`.
+    journal: Option<Vec<u8>>,
 }
 
 impl<R: Read> BitReader<R> {
@@ -68,20 +154,222 @@ impl<R: Read> BitReader<R> {
             inner,
             bit_buff: None,
             bit_cursor: 0,
+            lookahead: VecDeque::new(),
+            journal: None,
         }
     }
 
+    // The bytes already pulled from the inner reader but not read yet, by `peek_bytes` or
+    // `fill_lookahead`, are lost. Use `into_parts` to keep them.
     pub fn into_inner(self) -> R {
         self.inner
     }
 
+    // The inner reader and the bytes already pulled from it but not read yet, which come before
+    // what's left in it. The bits left in a partially read byte aren't returned.
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        let unread_byte = self.bit_buff.filter(|_| self.is_aligned());
+
+        (self.inner, unread_byte.into_iter().chain(self.lookahead).collect())
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    // Reading from it skips the bytes already pulled from it but not read yet, like `into_inner`.
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
     pub fn bit_cursor(&self) -> usize {
         self.bit_cursor
     }
 
+    pub fn is_aligned(&self) -> bool {
+        self.bit_cursor == 0
+    }
+
+    pub fn aligned_reader(&mut self) -> AlignedReader<'_, R> {
+        AlignedReader { bit_reader: self }
+    }
+
+    // Returns the next bits without consuming them, the first one being the lowest bit. Less than
+    // `amount` bits are returned only when the end of the inner reader is reached.
+    pub fn fill_lookahead(&mut self, amount: usize) -> io::Result<(u64, usize)> {
+        assert!(amount <= u64::BITS as usize);
+        if amount == 0 {
+            return Ok((0, 0));
+        }
+
+        let Some(bit_buff) = self.fill_buff()? else {
+            return Ok((0, 0));
+        };
+
+        let mut bits = (bit_buff >> self.bit_cursor) as u64;
+        let mut available = u8::BITS as usize - self.bit_cursor;
+
+        let mut index = 0;
+        while available < amount {
+            let byte = if let Some(byte) = self.lookahead.get(index) {
+                *byte
+            } else {
+                let Some(byte) = try_read_one_byte(&mut self.inner)? else {
+                    break;
+                };
+                self.lookahead.push_back(byte);
+                byte
+            };
+
+            bits |= (byte as u64) << available;
+            available += u8::BITS as usize;
+            index += 1;
+        }
+
+        if available > amount {
+            bits &= u64::checked_shl(1, amount as u32).unwrap_or(0).wrapping_sub(1);
+            available = amount;
+        }
+
+        Ok((bits, available))
+    }
+
+    // Returns the next bytes without consuming them, fewer only when the end of the inner reader is
+    // reached. The reader must be aligned on a byte.
+    pub fn peek_bytes(&mut self, amount: usize) -> io::Result<Vec<u8>> {
+        assert!(self.is_aligned());
+
+        let buffered = self.bit_buff.is_some() as usize;
+        while buffered + self.lookahead.len() < amount {
+            let Some(byte) = try_read_one_byte(&mut self.inner)? else {
+                break;
+            };
+            self.lookahead.push_back(byte);
+        }
+
+        Ok(self.bit_buff.into_iter().chain(self.lookahead.iter().copied()).take(amount).collect())
+    }
+
+    pub fn consume_bits(&mut self, mut amount: usize) -> io::Result<()> {
+        while amount > 0 {
+            let bits_to_consume = amount.min(u8::BITS as usize);
+            self.read_bits(bits_to_consume)?;
+
+            amount -= bits_to_consume;
+        }
+
+        Ok(())
+    }
+
+    // Runs `read` and, if it fails because the input ended, rewinds the reader to where it was
+    // before, so it can be retried once more data arrived. The consumed bytes are kept in memory
+    // until the outermost transaction ends.
+    pub fn read_transaction<T, F>(&mut self, read: F) -> io::Result<T>
+    where
+        F: FnOnce(&mut Self) -> io::Result<T>,
+    {
+        let is_outermost = self.journal.is_none();
+        let start = self.journal.get_or_insert_with(Vec::new).len();
+        let (bit_buff, bit_cursor) = (self.bit_buff, self.bit_cursor);
+
+        let result = read(self);
+
+        if matches!(&result, Err(error) if error.kind() == io::ErrorKind::UnexpectedEof) {
+            let journal = self.journal.as_mut().unwrap();
+            for byte in journal.drain(start..).rev() {
+                self.lookahead.push_front(byte);
+            }
+
+            self.bit_buff = bit_buff;
+            self.bit_cursor = bit_cursor;
+        }
+
+        if is_outermost {
+            self.journal = None;
+        }
+
+        result
+    }
+
+    fn record_consumed(&mut self, bytes: &[u8]) {
+        if let Some(journal) = &mut self.journal {
+            journal.extend_from_slice(bytes);
+        }
+    }
+
+    // Outside of the transactions and the lookahead, the byte comes straight from the inner reader.
+    #[inline]
+    fn next_byte(&mut self) -> io::Result<Option<u8>> {
+        let byte = if self.lookahead.is_empty() {
+            try_read_one_byte(&mut self.inner)?
+        } else {
+            self.lookahead.pop_front()
+        };
+
+        if let (Some(journal), Some(byte)) = (&mut self.journal, byte) {
+            journal.push(byte);
+        }
+        Ok(byte)
+    }
+
+    // Like `read_exact` on the inner reader, but the bytes read before failing are still recorded.
+    fn read_inner_exact(&mut self, mut buf: &mut [u8]) -> io::Result<()> {
+        while !buf.is_empty() {
+            let n = match self.inner.read(buf) {
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => n,
+                Err(error) if error.kind() == io::ErrorKind::Interrupted => continue,
+                Err(error) => return Err(error),
+            };
+
+            self.record_consumed(&buf[..n]);
+            buf = &mut buf[n..];
+        }
+
+        Ok(())
+    }
+
+    fn read_full_bytes(&mut self, bytes: &mut [u8]) -> io::Result<()> {
+        if self.is_aligned() {
+            return self.aligned_reader().read_exact(bytes);
+        }
+
+        let Some(mut prev) = self.bit_buff else {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        };
+
+        // read the raw bytes in place, then shift them by the cursor a word at a time.
+        let from_lookahead = self.lookahead.len().min(bytes.len());
+        for (byte, lookahead_byte) in bytes.iter_mut().zip(self.lookahead.drain(..from_lookahead)) {
+            *byte = lookahead_byte;
+        }
+        self.record_consumed(&bytes[..from_lookahead]);
+        self.read_inner_exact(&mut bytes[from_lookahead..])?;
+
+        let offset = self.bit_cursor as u32;
+        let mut words = bytes.chunks_exact_mut(size_of::<u64>());
+        for word_bytes in &mut words {
+            let word = u64::from_le_bytes(word_bytes.try_into().unwrap());
+            let shifted = (word << (u8::BITS - offset)) | (prev >> offset) as u64;
+
+            prev = (word >> (u64::BITS - u8::BITS)) as u8;
+            word_bytes.copy_from_slice(&shifted.to_le_bytes());
+        }
+        for byte in words.into_remainder() {
+            let raw = *byte;
+            *byte = (prev >> offset) | (raw << (u8::BITS - offset));
+            prev = raw;
+        }
+
+        self.bit_buff = Some(prev);
+        trace_op!("read_bytes amount={} cursor={}", bytes.len(), self.bit_cursor);
+
+        Ok(())
+    }
+
     fn fill_buff(&mut self) -> io::Result<Option<u8>> {
         if self.bit_buff.is_none() {
-            self.bit_buff = try_read_one_byte(&mut self.inner)?;
+            self.bit_buff = self.next_byte()?;
             if self.bit_buff.is_none() {
                 return Ok(None);
             }
@@ -90,7 +378,70 @@ impl<R: Read> BitReader<R> {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BitReaderMark {
+    byte_position: u64,
+    bit_cursor: usize,
+}
+
+impl<R: Read + Seek> BitReader<R> {
+    pub fn mark(&mut self) -> io::Result<BitReaderMark> {
+        let mut byte_position = self.inner.stream_position()?;
+
+        // the buffered bytes have already been consumed from the inner reader.
+        byte_position -= self.lookahead.len() as u64;
+        if self.bit_buff.is_some() {
+            byte_position -= 1;
+        }
+
+        Ok(BitReaderMark {
+            byte_position,
+            bit_cursor: self.bit_cursor,
+        })
+    }
+
+    pub fn reset(&mut self, mark: BitReaderMark) -> io::Result<()> {
+        self.inner.seek(SeekFrom::Start(mark.byte_position))?;
+        self.lookahead.clear();
+        self.bit_buff = None;
+        self.bit_cursor = 0;
+
+        if mark.bit_cursor != 0 {
+            let Some(byte) = try_read_one_byte(&mut self.inner)? else {
+                return Err(io::ErrorKind::UnexpectedEof.into());
+            };
+
+            self.bit_buff = Some(byte);
+            self.bit_cursor = mark.bit_cursor;
+        }
+
+        Ok(())
+    }
+}
+
 impl<R: Read> BitRead for BitReader<R> {
+    fn read_bytes(&mut self, bytes: &mut [u8], last_byte_amount: Option<usize>) -> io::Result<()> {
+        let Some((last, full)) = bytes.split_last_mut() else {
+            return Ok(());
+        };
+
+        if full.len() >= BULK_READ_THRESHOLD {
+            self.read_full_bytes(full)?;
+        } else {
+            for byte in full {
+                *byte = self.read_byte()?;
+            }
+        }
+
+        *last = if let Some(amount) = last_byte_amount {
+            self.read_bits(amount)?
+        } else {
+            self.read_byte()?
+        };
+
+        Ok(())
+    }
+
     fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
         let Some(bit_buff) = self.fill_buff()? else {
             return Ok(None);
@@ -104,7 +455,7 @@ impl<R: Read> BitRead for BitReader<R> {
         let bottom_size = u8::BITS as usize - self.bit_cursor;
         let mut byte = extract_part(bit_buff, bottom_size as u32, self.bit_cursor as u32);
 
-        self.bit_buff = try_read_one_byte(&mut self.inner)?;
+        self.bit_buff = self.next_byte()?;
 
         if bottom_size != u8::BITS as usize {
             if let Some(bit_buff) = self.bit_buff {
@@ -115,6 +466,7 @@ impl<R: Read> BitRead for BitReader<R> {
             }
         }
 
+        trace_op!("read_byte value={byte} cursor={}", self.bit_cursor);
         Ok(Some(byte))
     }
 
@@ -123,6 +475,9 @@ impl<R: Read> BitRead for BitReader<R> {
         if amount == u8::BITS as usize {
             return self.try_read_byte();
         }
+        if amount == 0 {
+            return Ok(Some(0));
+        }
 
         let Some(bit_buff) = self.fill_buff()? else {
             return Ok(None);
@@ -142,7 +497,7 @@ impl<R: Read> BitRead for BitReader<R> {
             self.bit_buff = None;
 
             if new_bit_cursor > 0 {
-                let Some(buf_byte) = try_read_one_byte(&mut self.inner)? else {
+                let Some(buf_byte) = self.next_byte()? else {
                     return Ok(None);
                 };
                 self.bit_buff = Some(buf_byte);
@@ -153,11 +508,60 @@ impl<R: Read> BitRead for BitReader<R> {
         }
 
         self.bit_cursor = new_bit_cursor;
+        trace_op!("read_bits amount={amount} value={byte} cursor={}", self.bit_cursor);
 
         Ok(Some(byte))
     }
 }
 
+// Reads bytes straight from the inner reader, after the ones already pulled from it, so aligned
+// payloads can be read at full speed with `io::copy`. Only byte aligned reads are allowed.
+pub struct AlignedReader<'r, R: Read> {
+    bit_reader: &'r mut BitReader<R>,
+}
+
+impl<R: Read> Read for AlignedReader<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.bit_reader.read_aligned(buf)
+    }
+}
+
+impl<R: Read> BitReader<R> {
+    fn read_aligned(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.is_aligned() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The bit reader isn't aligned on a byte.",
+            ));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // the reader may have already pulled the next bytes from the inner reader.
+        let mut n = 0;
+        if let Some(byte) = self.bit_buff.take() {
+            buf[0] = byte;
+            n += 1;
+        }
+        let from_lookahead = n;
+        while n < buf.len() {
+            let Some(byte) = self.lookahead.pop_front() else {
+                break;
+            };
+            buf[n] = byte;
+            n += 1;
+        }
+
+        if n == 0 {
+            n = self.inner.read(buf)?;
+        }
+        self.record_consumed(&buf[from_lookahead..n]);
+
+        Ok(n)
+    }
+}
+
 fn try_read_one_byte<R: Read>(reader: &mut R) -> io::Result<Option<u8>> {
     let mut tmp = [0u8; 1];
     let n = reader.read(&mut tmp)?;
@@ -412,6 +816,19 @@ mod test {
         assert_eq!(test_output.cursor_position, 0);
     }
 
+    #[test]
+    fn test_read_zero_bits_on_empty_array() {
+        let test_output = crate::test::get_test_read_bytes_output(&[], |tester| {
+            tester.read_bits(0)?;
+
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(&test_output.vec, &[0]);
+        assert_eq!(test_output.cursor_position, 0);
+    }
+
     #[test]
     fn test_read_one_bit() {
         let test_output = crate::test::get_test_read_bytes_output(&[0b1], |tester| {
@@ -579,6 +996,553 @@ mod test {
         assert_eq!(test_output.cursor_position, 3);
     }
 
+    #[test]
+    fn test_read_values_are_returned_to_the_test() {
+        let test_output = crate::test::get_test_read_bytes_output(
+            &[0b0011_0001, 0b0000_0010, 0xAB],
+            |tester| {
+                // a length prefix, read as is by the test.
+                let length = tester.read_bits(4)?;
+                for _ in 0..length {
+                    tester.read_bits(4)?;
+                }
+                assert_eq!(tester.bits_read(), &[1, 0b0011]);
+
+                let bytes = tester.try_read_bytes(2, None)?;
+                assert_eq!(bytes, &[0b0010, 0xAB]);
+
+                Ok(())
+            },
+        )
+        .unwrap();
+
+        assert_eq!(&test_output.vec, &[1, 0b0011, 0b0010, 0xAB]);
+    }
+
+    #[test]
+    fn test_read_bits_rev() {
+        let output = crate::test::get_test_read_custom_readable_output(&[0b01_011], |reader| {
+            Ok((reader.read_bits_rev(3)?, reader.read_bits_rev(2)?))
+        })
+        .unwrap();
+
+        assert_eq!(output.result, (0b110, 0b10));
+        assert_eq!(output.cursor_position, 5);
+    }
+
+    mod bits_into {
+        use crate::BitRead;
+
+        #[test]
+        fn read_bits_into_whole_bytes() {
+            let output = crate::test::get_test_read_custom_readable_output(&[1, 2, 3], |reader| {
+                let mut buf = [0xFF; 4];
+                let n = reader.read_bits_into(&mut buf, 16)?;
+
+                Ok((n, buf))
+            })
+            .unwrap();
+
+            assert_eq!(output.result, (2, [1, 2, 0xFF, 0xFF]));
+            assert_eq!(output.cursor_position, 0);
+        }
+
+        #[test]
+        fn read_bits_into_pads_last_byte_with_zeros() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0b11110000, 0b11111111, 0b11111111],
+                |reader| {
+                    reader.read_bits(4)?;
+
+                    let mut buf = [0xFF; 3];
+                    let n = reader.read_bits_into(&mut buf, 13)?;
+
+                    Ok((n, buf))
+                },
+            )
+            .unwrap();
+
+            assert_eq!(output.result, (2, [0xFF, 0b11111, 0xFF]));
+            assert_eq!(output.cursor_position, 1);
+        }
+
+        #[test]
+        fn read_no_bits_into() {
+            let output = crate::test::get_test_read_custom_readable_output(&[], |reader| {
+                let mut buf = [];
+                reader.read_bits_into(&mut buf, 0)
+            })
+            .unwrap();
+
+            assert_eq!(output.result, 0);
+        }
+
+        #[test]
+        #[should_panic]
+        fn read_bits_into_a_too_small_buffer_should_fail() {
+            crate::test::get_test_read_custom_readable_output(&[1, 2, 3], |reader| {
+                let mut buf = [0; 1];
+                reader.read_bits_into(&mut buf, 9)
+            })
+            .unwrap();
+        }
+    }
+
+    mod numbers {
+        use crate::{BitRead, Endianness};
+
+        #[test]
+        fn read_u16() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0x02, 0x01, 0x01, 0x02],
+                |reader| Ok((reader.read_u16(Endianness::Little)?, reader.read_u16(Endianness::Big)?)),
+            )
+            .unwrap();
+
+            assert_eq!(output.result, (0x0102, 0x0102));
+        }
+
+        #[test]
+        fn read_u32() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[4, 3, 2, 1, 1, 2, 3, 4],
+                |reader| Ok((reader.read_u32(Endianness::Little)?, reader.read_u32(Endianness::Big)?)),
+            )
+            .unwrap();
+
+            assert_eq!(output.result, (0x01020304, 0x01020304));
+        }
+
+        #[test]
+        fn read_u64_not_aligned() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0x1F, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0x0],
+                |reader| {
+                    reader.read_bits(4)?;
+                    reader.read_u64(Endianness::Big)
+                },
+            )
+            .unwrap();
+
+            assert_eq!(output.result, 0x0102030405060708);
+            assert_eq!(output.cursor_position, 4);
+        }
+
+        #[test]
+        #[should_panic]
+        fn read_u32_with_not_enough_bytes_should_fail() {
+            crate::test::get_test_read_custom_readable_output(&[1, 2, 3], |reader| {
+                reader.read_u32(Endianness::Little)
+            })
+            .unwrap();
+        }
+    }
+
+    mod signed {
+        use crate::BitRead;
+
+        #[test]
+        fn read_small_signed_values() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0b00_010_111, 0b1],
+                |reader| {
+                    Ok((
+                        reader.read_signed_bits(3)?,
+                        reader.read_signed_bits(3)?,
+                        reader.read_signed_bits(3)?,
+                    ))
+                },
+            )
+            .unwrap();
+
+            assert_eq!(output.result, (-1, 2, -4));
+            assert_eq!(output.cursor_position, 1);
+        }
+
+        #[test]
+        fn read_wide_signed_value() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0b11111110, 0b1111],
+                |reader| reader.read_signed_bits(12),
+            )
+            .unwrap();
+
+            assert_eq!(output.result, -2);
+        }
+
+        #[test]
+        fn read_full_width_signed_value() {
+            let output = crate::test::get_test_read_custom_readable_output(
+                &[0, 0, 0, 0, 0, 0, 0, 0x80],
+                |reader| reader.read_signed_bits(64),
+            )
+            .unwrap();
+
+            assert_eq!(output.result, i64::MIN);
+        }
+
+        #[test]
+        fn read_zero_width_signed_value() {
+            let output =
+                crate::test::get_test_read_custom_readable_output(&[], |reader| {
+                    reader.read_signed_bits(0)
+                })
+                .unwrap();
+
+            assert_eq!(output.result, 0);
+        }
+    }
+
+    mod mark {
+        use std::io::Cursor;
+
+        use crate::{BitRead, BitReader};
+
+        #[test]
+        fn reset_to_the_start() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011]));
+            let mark = reader.mark().unwrap();
+
+            reader.read_bits(3).unwrap();
+            reader.read_byte().unwrap();
+            reader.reset(mark).unwrap();
+
+            assert_eq!(reader.read_byte().unwrap(), 0b11110000);
+            assert_eq!(reader.read_byte().unwrap(), 0b00110011);
+        }
+
+        #[test]
+        fn reset_to_a_not_aligned_position() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011, 0b01010101]));
+            reader.read_bits(4).unwrap();
+            let mark = reader.mark().unwrap();
+
+            let first_read = reader.read_byte().unwrap();
+            reader.read_bits(5).unwrap();
+            reader.reset(mark).unwrap();
+
+            assert_eq!(reader.bit_cursor(), 4);
+            assert_eq!(reader.read_byte().unwrap(), first_read);
+        }
+
+        #[test]
+        fn reset_after_an_aligned_byte_read() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3]));
+            reader.read_byte().unwrap();
+            let mark = reader.mark().unwrap();
+
+            reader.read_byte().unwrap();
+            reader.read_byte().unwrap();
+            reader.reset(mark).unwrap();
+
+            assert_eq!(reader.read_byte().unwrap(), 2);
+            assert_eq!(reader.read_byte().unwrap(), 3);
+        }
+    }
+
+    mod lookahead {
+        use std::io::{Cursor, Read};
+
+        use crate::{BitRead, BitReader};
+
+        #[test]
+        fn fill_lookahead_does_not_consume() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011, 0b01010101]));
+            reader.read_bits(4).unwrap();
+
+            assert_eq!(reader.fill_lookahead(12).unwrap(), (0b0011_0011_1111, 12));
+            assert_eq!(reader.fill_lookahead(3).unwrap(), (0b111, 3));
+
+            assert_eq!(reader.bit_cursor(), 4);
+            assert_eq!(reader.read_byte().unwrap(), 0b0011_1111);
+            assert_eq!(reader.read_byte().unwrap(), 0b0101_0011);
+        }
+
+        #[test]
+        fn fill_lookahead_returns_fewer_bits_at_the_end() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011]));
+            reader.read_bits(4).unwrap();
+
+            assert_eq!(reader.fill_lookahead(64).unwrap(), (0b0011_0011_1111, 12));
+        }
+
+        #[test]
+        fn fill_lookahead_on_empty_reader() {
+            let mut reader = BitReader::new(Cursor::new([]));
+
+            assert_eq!(reader.fill_lookahead(8).unwrap(), (0, 0));
+        }
+
+        #[test]
+        fn consume_bits_after_lookahead() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 0b00110011, 0b01010101]));
+
+            let (bits, available) = reader.fill_lookahead(20).unwrap();
+            assert_eq!((bits, available), (0b0101_0011_0011_1111_0000, 20));
+
+            reader.consume_bits(13).unwrap();
+            assert_eq!(reader.bit_cursor(), 5);
+            assert_eq!(reader.read_bits(5).unwrap(), 0b01_001);
+            assert_eq!(reader.fill_lookahead(8).unwrap(), (0b010101, 6));
+        }
+
+        #[test]
+        fn mark_accounts_for_lookahead() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            reader.read_bits(4).unwrap();
+
+            let mark = reader.mark().unwrap();
+            reader.fill_lookahead(24).unwrap();
+            assert_eq!(reader.mark().unwrap(), mark);
+
+            reader.read_byte().unwrap();
+            reader.reset(mark).unwrap();
+            assert_eq!(reader.fill_lookahead(4).unwrap(), (0, 4));
+            assert_eq!(reader.read_bits(4).unwrap(), 0);
+            assert_eq!(reader.read_byte().unwrap(), 2);
+        }
+
+        #[test]
+        fn io_read_drains_lookahead() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            reader.fill_lookahead(24).unwrap();
+
+            let mut rest = Vec::new();
+            reader.aligned_reader().read_to_end(&mut rest).unwrap();
+
+            assert_eq!(&rest, &[1, 2, 3, 4]);
+        }
+
+        #[test]
+        fn into_parts_returns_the_bytes_pulled_from_the_inner_reader() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            reader.read_byte().unwrap();
+            reader.peek_bytes(2).unwrap();
+
+            let (inner, buffered) = reader.into_parts();
+            assert_eq!(buffered, [2, 3]);
+            assert_eq!(inner.position(), 3);
+
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            reader.read_bits(4).unwrap();
+            reader.fill_lookahead(12).unwrap();
+
+            let (_, buffered) = reader.into_parts();
+            assert_eq!(buffered, [2]);
+        }
+
+        #[test]
+        fn peek_bytes_does_not_consume() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3]));
+            reader.fill_lookahead(4).unwrap();
+
+            assert_eq!(reader.peek_bytes(2).unwrap(), [1, 2]);
+            assert_eq!(reader.peek_bytes(8).unwrap(), [1, 2, 3]);
+            assert_eq!(reader.read_byte().unwrap(), 1);
+            assert_eq!(reader.peek_bytes(8).unwrap(), [2, 3]);
+
+            reader.read_bits(8).unwrap();
+            reader.read_bits(8).unwrap();
+            assert_eq!(reader.peek_bytes(1).unwrap(), []);
+        }
+    }
+
+    mod bulk_bytes {
+        use std::io::{self, Cursor};
+
+        use crate::{BitRead, BitReader};
+
+        // Only has the required methods, so `read_bytes` reads the bytes one by one.
+        struct ByteByByte<R: BitRead>(R);
+
+        impl<R: BitRead> BitRead for ByteByByte<R> {
+            fn try_read_byte(&mut self) -> io::Result<Option<u8>> {
+                self.0.try_read_byte()
+            }
+
+            fn try_read_bits(&mut self, amount: usize) -> io::Result<Option<u8>> {
+                self.0.try_read_bits(amount)
+            }
+        }
+
+        fn data() -> Vec<u8> {
+            (0..64u8).map(|i| i.wrapping_mul(37) ^ 0b1010_0101).collect()
+        }
+
+        #[test]
+        fn same_as_byte_by_byte_at_every_cursor() {
+            for offset in 0..8 {
+                for len in [1, 8, 9, 17, 24, 40] {
+                    for last_byte_amount in [None, Some(3)] {
+                        let mut reader = BitReader::new(Cursor::new(data()));
+                        let mut expected_reader = ByteByByte(BitReader::new(Cursor::new(data())));
+                        reader.read_bits(offset).unwrap();
+                        expected_reader.read_bits(offset).unwrap();
+
+                        let mut bytes = vec![0; len];
+                        let mut expected = vec![0; len];
+                        reader.read_bytes(&mut bytes, last_byte_amount).unwrap();
+                        expected_reader.read_bytes(&mut expected, last_byte_amount).unwrap();
+
+                        assert_eq!(bytes, expected, "offset {offset}, len {len}");
+                        assert_eq!(reader.read_bits(5).unwrap(), expected_reader.read_bits(5).unwrap());
+                    }
+                }
+            }
+        }
+
+        #[test]
+        fn uses_the_lookahead_bytes() {
+            for offset in [0, 3] {
+                let mut reader = BitReader::new(Cursor::new(data()));
+                let mut expected_reader = ByteByByte(BitReader::new(Cursor::new(data())));
+                reader.read_bits(offset).unwrap();
+                expected_reader.read_bits(offset).unwrap();
+                reader.fill_lookahead(40).unwrap();
+
+                let mut bytes = [0; 20];
+                let mut expected = [0; 20];
+                reader.read_bytes(&mut bytes, None).unwrap();
+                expected_reader.read_bytes(&mut expected, None).unwrap();
+
+                assert_eq!(bytes, expected, "offset {offset}");
+            }
+        }
+
+        #[test]
+        fn fail_past_the_end() {
+            let mut reader = BitReader::new(Cursor::new(data()));
+            reader.read_bits(3).unwrap();
+
+            let mut bytes = [0; 64];
+            let err = reader.read_bytes(&mut bytes, None).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        }
+    }
+
+    mod transaction {
+        use std::io::{self, Read};
+
+        use crate::{compact::CompactNumberU64, BitRead, BitReader, ChainedReader, Endianness};
+
+        fn read_header(
+            reader: &mut BitReader<ChainedReader<&[u8]>>,
+        ) -> io::Result<(u8, CompactNumberU64)> {
+            reader.read_transaction(|reader| Ok((reader.read_bits(4)?, reader.read_readable()?)))
+        }
+
+        #[test]
+        fn rollback_when_the_input_ends() {
+            let mut reader = BitReader::new(ChainedReader::new());
+            reader.push_source(&[0b0011_0101][..]);
+            reader.read_bits(4).unwrap();
+
+            // the header starts at bit 4: 4 bits, then a 2 bytes compact number.
+            reader.push_source(&[2, 0xFF][..]);
+            let error = read_header(&mut reader).unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+            assert_eq!(reader.bit_cursor(), 4);
+
+            reader.push_source(&[0, 0b0000_1111][..]);
+            let (bits, number) = read_header(&mut reader).unwrap();
+            assert_eq!(bits, 0b0011);
+            assert_eq!(number, CompactNumberU64(0xFF));
+            assert_eq!(reader.read_bits(4).unwrap(), 0b1111);
+        }
+
+        #[test]
+        fn rollback_bulk_and_aligned_reads() {
+            let data: Vec<u8> = (0..32).collect();
+
+            let mut reader = BitReader::new(ChainedReader::from_iter([&data[..20]]));
+            let error = reader
+                .read_transaction(|reader| {
+                    let mut bytes = [0; 24];
+                    reader.aligned_reader().read_exact(&mut bytes[..4])?;
+                    reader.read_bits(3)?;
+                    reader.read_bytes(&mut bytes, None)
+                })
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+            reader.push_source(&data[20..]);
+            let mut bytes = [0; 32];
+            reader.aligned_reader().read_exact(&mut bytes).unwrap();
+            assert_eq!(&bytes[..], &data[..]);
+        }
+
+        #[test]
+        fn keep_the_reads_of_a_successful_nested_transaction() {
+            let mut reader = BitReader::new(ChainedReader::from_iter([&[1, 2, 3][..]]));
+            let error = reader
+                .read_transaction(|reader| {
+                    assert_eq!(reader.read_transaction(|reader| reader.read_byte())?, 1);
+                    let result = reader.read_transaction(|reader| reader.read_u32(Endianness::Little));
+                    assert!(result.is_err());
+                    assert_eq!(reader.read_byte()?, 2);
+
+                    reader.read_u16(Endianness::Little)
+                })
+                .unwrap_err();
+            assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+
+            assert_eq!(reader.read_byte().unwrap(), 1);
+        }
+
+        #[test]
+        fn other_errors_are_not_rolled_back() {
+            let mut reader = BitReader::new(ChainedReader::from_iter([&[1, 2, 3][..]]));
+            reader
+                .read_transaction(|reader| {
+                    reader.read_byte()?;
+                    Err::<(), _>(io::ErrorKind::InvalidData.into())
+                })
+                .unwrap_err();
+
+            assert_eq!(reader.read_byte().unwrap(), 2);
+        }
+    }
+
+    mod io_read {
+        use std::io::{Cursor, Read};
+
+        use crate::{BitRead, BitReader};
+
+        #[test]
+        fn read_to_end_when_aligned() {
+            let mut reader = BitReader::new(Cursor::new([1, 2, 3, 4]));
+            reader.read_byte().unwrap();
+
+            let mut rest = Vec::new();
+            reader.aligned_reader().read_to_end(&mut rest).unwrap();
+
+            assert_eq!(&rest, &[2, 3, 4]);
+        }
+
+        #[test]
+        fn read_after_realigning_bits() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 2, 3]));
+            reader.read_bits(4).unwrap();
+            reader.read_bits(4).unwrap();
+
+            let mut rest = Vec::new();
+            reader.aligned_reader().read_to_end(&mut rest).unwrap();
+
+            assert_eq!(&rest, &[2, 3]);
+        }
+
+        #[test]
+        fn read_when_not_aligned_should_fail() {
+            let mut reader = BitReader::new(Cursor::new([0b11110000, 2, 3]));
+            reader.read_bits(4).unwrap();
+
+            let mut buf = [0; 2];
+            let err = reader.aligned_reader().read(&mut buf).unwrap_err();
+
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
+
     mod fail {
         use crate::BitRead;
 
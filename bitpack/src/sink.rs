@@ -0,0 +1,87 @@
+use std::io;
+
+use crate::BitWrite;
+
+// Discards everything written to it, like `io::sink()`, only counting the bits.
+#[derive(Debug, Default)]
+pub struct BitSink {
+    bits_written: u64,
+}
+
+impl BitSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bits_written(&self) -> u64 {
+        self.bits_written
+    }
+
+    // The bytes the written bits would take once flushed.
+    pub fn bytes_written(&self) -> u64 {
+        self.bits_written.div_ceil(u8::BITS as u64)
+    }
+}
+
+impl BitWrite for BitSink {
+    fn write_bits(&mut self, _bits: u8, amount: usize) -> io::Result<()> {
+        assert!(amount <= u8::BITS as usize);
+
+        self.bits_written += amount as u64;
+        Ok(())
+    }
+
+    fn write_byte(&mut self, _byte: u8) -> io::Result<()> {
+        self.bits_written += u8::BITS as u64;
+        Ok(())
+    }
+
+    // Pads to the next byte, like `BitWriter::flush`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.bits_written = self.bytes_written() * u8::BITS as u64;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use crate::{compact::CompactNumberU64, BitWrite, BitWriter};
+
+    use super::BitSink;
+
+    #[test]
+    fn counts_the_discarded_bits() {
+        let mut sink = BitSink::new();
+        sink.write_bits(0b101, 3).unwrap();
+        sink.write_byte(0xFF).unwrap();
+        sink.write_writable(CompactNumberU64(1 << 8)).unwrap();
+
+        assert_eq!(sink.bits_written(), 3 + 8 + 3 * 8);
+        assert_eq!(sink.bytes_written(), 5);
+    }
+
+    #[test]
+    fn flush_pads_to_a_byte_like_the_bit_writer() {
+        let mut sink = BitSink::new();
+        let mut writer = BitWriter::new(Vec::new());
+
+        for (bits, amount) in [(0b101, 3), (0xFF, 8), (0b1, 1)] {
+            sink.write_bits(bits, amount).unwrap();
+            writer.write_bits(bits, amount).unwrap();
+        }
+        sink.flush().unwrap();
+        writer.flush().unwrap();
+        assert_eq!(sink.bits_written(), 16);
+        assert_eq!(sink.bits_written(), writer.bits_written());
+
+        // flushing when aligned adds nothing
+        sink.flush().unwrap();
+        assert_eq!(sink.bits_written(), 16);
+
+        sink.write_bits(0b11, 2).unwrap();
+        writer.write_bits(0b11, 2).unwrap();
+        assert_eq!(sink.bits_written(), writer.bits_written());
+        assert_eq!(sink.bytes_written(), 3);
+    }
+}
@@ -1,6 +1,70 @@
-use std::io::{self, Cursor};
+use std::{fmt::Debug, io::{self, Cursor}};
 
-use crate::{BitRead, BitReadable, BitReader, BitWrite, BitWriter};
+use crate::{u8_mask, BitRead, BitReadable, BitReader, BitWritable, BitWrite, BitWriter};
+
+#[cfg(any(feature = "proptest", test))]
+pub mod strategy;
+
+// A single write, which the proptest strategies and the fuzz targets generate plans of.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum WriteOp {
+    Bits { bits: u8, amount: usize },
+    Byte(u8),
+    Bytes { bytes: Vec<u8>, last_byte_amount: Option<usize> },
+}
+
+impl WriteOp {
+    pub fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::Bits { bits, amount } => writer.write_bits(*bits, *amount),
+            Self::Byte(byte) => writer.write_byte(*byte),
+            Self::Bytes { bytes, last_byte_amount } => writer.write_bytes(bytes, *last_byte_amount),
+        }
+    }
+
+    pub fn read_back<R: BitRead>(&self, reader: &mut R) -> io::Result<Self> {
+        let op = match self {
+            Self::Bits { amount, .. } => Self::Bits {
+                bits: reader.read_bits(*amount)?,
+                amount: *amount,
+            },
+            Self::Byte(_) => Self::Byte(reader.read_byte()?),
+            Self::Bytes { bytes, last_byte_amount } => {
+                let mut read = vec![0; bytes.len()];
+                reader.read_bytes(&mut read, *last_byte_amount)?;
+
+                Self::Bytes {
+                    bytes: read,
+                    last_byte_amount: *last_byte_amount,
+                }
+            }
+        };
+
+        Ok(op)
+    }
+
+    // the writers silently drop the bits above `amount`, so that's what should be read back.
+    pub fn expected(&self) -> Self {
+        match self {
+            Self::Bits { bits, amount } => Self::Bits {
+                bits: bits & u8_mask(*amount as u32),
+                amount: *amount,
+            },
+            Self::Byte(byte) => Self::Byte(*byte),
+            Self::Bytes { bytes, last_byte_amount } => {
+                let mut bytes = bytes.clone();
+                if let (Some(last), Some(amount)) = (bytes.last_mut(), last_byte_amount) {
+                    *last &= u8_mask(*amount as u32);
+                }
+
+                Self::Bytes {
+                    bytes,
+                    last_byte_amount: *last_byte_amount,
+                }
+            }
+        }
+    }
+}
 
 pub struct TestOutput {
     pub vec: Vec<u8>,
@@ -40,18 +104,23 @@ impl<Br: BitRead> TestBitReader<Br> {
         }
     }
 
+    // The values read so far, in order.
+    pub fn bits_read(&self) -> &[u8] {
+        &self.bits_read
+    }
+
     pub fn try_read_bytes(
         &mut self,
         byte_amount: usize,
         last_byte_amount: Option<usize>,
-    ) -> std::io::Result<()> {
+    ) -> std::io::Result<Vec<u8>> {
         let mut vec = vec![0; byte_amount];
 
         self.inner.read_bytes(&mut vec, last_byte_amount)?;
 
-        self.bits_read.extend(vec);
+        self.bits_read.extend(&vec);
 
-        Ok(())
+        Ok(vec)
     }
 }
 
@@ -63,7 +132,7 @@ impl<Br: BitRead> BitRead for TestBitReader<Br> {
 
         self.bits_read.push(byte);
 
-        Ok(Some(0))
+        Ok(Some(byte))
     }
 
     fn try_read_bits(&mut self, amount: usize) -> std::io::Result<Option<u8>> {
@@ -73,7 +142,7 @@ impl<Br: BitRead> BitRead for TestBitReader<Br> {
 
         self.bits_read.push(byte);
 
-        Ok(Some(0))
+        Ok(Some(byte))
     }
 }
 
@@ -138,3 +207,11 @@ pub fn get_test_read_custom_readable_output<
 
     Ok(test_output)
 }
+
+pub fn assert_round_trip<T: BitReadable + BitWritable + PartialEq + Debug>(value: T) {
+    let write_output = get_test_write_output(|writer| writer.write_writable(&value)).unwrap();
+    let read_output: TestOutputGeneric<T> = get_test_read_readable_output(&write_output.vec).unwrap();
+
+    assert_eq!(read_output.result, value);
+    assert_eq!(read_output.cursor_position, write_output.cursor_position);
+}
@@ -0,0 +1,83 @@
+use std::{fmt::Debug, io::Cursor};
+
+use proptest::{collection, option, prelude::*, test_runner::TestCaseError};
+
+use crate::{test::WriteOp, BitRead, BitReadable, BitReader, BitWritable, BitWrite, BitWriter};
+
+fn bit_amount() -> impl Strategy<Value = usize> {
+    0..=u8::BITS as usize
+}
+
+pub fn write_op() -> impl Strategy<Value = WriteOp> {
+    prop_oneof![
+        (any::<u8>(), bit_amount()).prop_map(|(bits, amount)| WriteOp::Bits { bits, amount }),
+        any::<u8>().prop_map(WriteOp::Byte),
+        (collection::vec(any::<u8>(), 0..16), option::of(bit_amount())).prop_map(
+            |(bytes, last_byte_amount)| WriteOp::Bytes {
+                bytes,
+                last_byte_amount
+            }
+        ),
+    ]
+}
+
+pub fn write_plan(max_len: usize) -> impl Strategy<Value = Vec<WriteOp>> {
+    collection::vec(write_op(), 0..=max_len)
+}
+
+fn check_plan_read_back<R: BitRead>(plan: &[WriteOp], reader: &mut R) -> Result<(), TestCaseError> {
+    for op in plan {
+        prop_assert_eq!(op.read_back(reader)?, op.expected());
+    }
+
+    Ok(())
+}
+
+pub fn check_write_plan(plan: &[WriteOp]) -> Result<(), TestCaseError> {
+    let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+    for op in plan {
+        op.write(&mut writer)?;
+    }
+    writer.flush()?;
+
+    let bytes = writer.into_inner().into_inner();
+    let mut reader = BitReader::new(Cursor::new(bytes.as_slice()));
+    check_plan_read_back(plan, &mut reader)
+}
+
+// `padding` is written before the value, so the value can be checked at any bit alignment.
+pub fn check_readable_round_trip<T>(value: &T, padding: &[WriteOp]) -> Result<(), TestCaseError>
+where
+    T: BitReadable + BitWritable + PartialEq + Debug,
+{
+    let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+    for op in padding {
+        op.write(&mut writer)?;
+    }
+    writer.write_writable(value)?;
+    writer.flush()?;
+
+    let bytes = writer.into_inner().into_inner();
+    let mut reader = BitReader::new(Cursor::new(bytes.as_slice()));
+    check_plan_read_back(padding, &mut reader)?;
+
+    let read: T = reader.read_readable()?;
+    prop_assert_eq!(&read, value);
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use proptest::prelude::*;
+
+    use super::{check_write_plan, write_plan};
+
+    proptest! {
+        #[test]
+        fn write_plans_are_read_back(plan in write_plan(32)) {
+            check_write_plan(&plan)?;
+        }
+    }
+}
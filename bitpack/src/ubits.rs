@@ -0,0 +1,81 @@
+use std::io;
+
+use crate::{BitRead, BitReadable, BitWritable, BitWrite};
+
+// An unsigned integer stored on exactly N bits, so format structs document their field widths.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct UBits<const N: usize>(u64);
+
+impl<const N: usize> UBits<N> {
+    const VALID_WIDTH: () = assert!(N > 0 && N <= u64::BITS as usize, "UBits width must be between 1 and 64");
+
+    pub const MAX: u64 = u64::MAX >> (u64::BITS as usize - N);
+
+    pub fn new(value: u64) -> Option<Self> {
+        let () = Self::VALID_WIDTH;
+
+        (value <= Self::MAX).then_some(Self(value))
+    }
+
+    pub fn get(self) -> u64 {
+        self.0
+    }
+}
+
+impl<const N: usize> BitWritable for UBits<N> {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_bits_u64(self.0, N)
+    }
+}
+
+impl<const N: usize> BitReadable for UBits<N> {
+    fn read<R: BitRead>(reader: &mut R) -> io::Result<Self> {
+        let () = Self::VALID_WIDTH;
+
+        Ok(Self(reader.read_bits_u64(N)?))
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use crate::{test::TestOutputGeneric, BitWrite};
+
+    use super::UBits;
+
+    #[test]
+    fn new_rejects_values_too_wide() {
+        assert_eq!(UBits::<3>::new(7).map(UBits::get), Some(7));
+        assert_eq!(UBits::<3>::new(8), None);
+        assert_eq!(UBits::<64>::new(u64::MAX).map(UBits::get), Some(u64::MAX));
+    }
+
+    #[test]
+    fn write_fields() {
+        let output = crate::test::get_test_write_output(|writer| {
+            writer.write_writable(UBits::<3>::new(0b101).unwrap())?;
+            writer.write_writable(UBits::<12>::new(0b1111_0000_1111).unwrap())
+        })
+        .unwrap();
+
+        assert_eq!(&output.vec, &[0b01111101, 0b1111000]);
+        assert_eq!(output.cursor_position, 7);
+    }
+
+    #[test]
+    fn read_field() {
+        let output: TestOutputGeneric<UBits<12>> =
+            crate::test::get_test_read_readable_output(&[0b00001111, 0b1111]).unwrap();
+
+        assert_eq!(output.result.get(), 0b1111_0000_1111);
+        assert_eq!(output.cursor_position, 4);
+    }
+
+    #[test]
+    fn round_trip() {
+        crate::test::assert_round_trip(UBits::<1>::new(1).unwrap());
+        crate::test::assert_round_trip(UBits::<7>::new(UBits::<7>::MAX).unwrap());
+        crate::test::assert_round_trip(UBits::<33>::new(0x1_2345_6789).unwrap());
+        crate::test::assert_round_trip(UBits::<64>::new(u64::MAX).unwrap());
+    }
+}
@@ -1,6 +1,6 @@
 use std::io::{self, Write};
 
-use super::u8_mask;
+use super::{reverse_bits, u8_mask, Endianness};
 
 pub trait BitWritable {
     fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()>;
@@ -17,6 +17,25 @@ pub trait BitWrite: Sized {
         writable.write(self)
     }
 
+    fn write_all_writables<I>(&mut self, writables: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: BitWritable,
+    {
+        for writable in writables {
+            self.write_writable(writable)?;
+        }
+
+        Ok(())
+    }
+
+    // Writes the writable as a named field. Only meaningful to writers recording the layout of
+    // their output, like `LabeledBitWriter`, the others write it as is.
+    fn write_labeled<Bw: BitWritable>(&mut self, label: &str, writable: Bw) -> io::Result<()> {
+        let _ = label;
+        self.write_writable(writable)
+    }
+
     fn write_bytes(&mut self, bytes: &[u8], last_byte_amount: Option<usize>) -> io::Result<()> {
         if bytes.is_empty() {
             return Ok(());
@@ -35,15 +54,82 @@ pub trait BitWrite: Sized {
         Ok(())
     }
 
+    // Like `write_bits`, but fails instead of silently dropping the set bits above `amount`.
+    fn write_bits_checked(&mut self, bits: u8, amount: usize) -> io::Result<()> {
+        assert!(amount <= u8::BITS as usize);
+
+        if bits & !u8_mask(amount as u32) != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("The value {bits:#b} doesn't fit in {amount} bits."),
+            ));
+        }
+
+        self.write_bits(bits, amount)
+    }
+
+    // Writes the `amount` lowest bits starting from the most significant one.
+    fn write_bits_rev(&mut self, bits: u8, amount: usize) -> io::Result<()> {
+        self.write_bits(reverse_bits(bits, amount), amount)
+    }
+
+    fn write_u16(&mut self, value: u16, endianness: Endianness) -> io::Result<()> {
+        let bytes = match endianness {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        };
+        self.write_bytes(&bytes, None)
+    }
+
+    fn write_u32(&mut self, value: u32, endianness: Endianness) -> io::Result<()> {
+        let bytes = match endianness {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        };
+        self.write_bytes(&bytes, None)
+    }
+
+    fn write_u64(&mut self, value: u64, endianness: Endianness) -> io::Result<()> {
+        let bytes = match endianness {
+            Endianness::Little => value.to_le_bytes(),
+            Endianness::Big => value.to_be_bytes(),
+        };
+        self.write_bytes(&bytes, None)
+    }
+
+    // Writes the `amount` lowest bits of `bits`, for fields up to 64 bits wide.
+    fn write_bits_u64(&mut self, mut bits: u64, amount: usize) -> io::Result<()> {
+        assert!(amount <= u64::BITS as usize);
+
+        let mut remaining = amount;
+        while remaining > 0 {
+            let bits_to_write = remaining.min(u8::BITS as usize);
+            self.write_bits(bits as u8, bits_to_write)?;
+
+            bits = bits.checked_shr(u8::BITS).unwrap_or(0);
+            remaining -= bits_to_write;
+        }
+
+        Ok(())
+    }
+
+    // Writes the `width` lowest bits of the two's complement representation of `value`.
+    fn write_signed_bits(&mut self, value: i64, width: usize) -> io::Result<()> {
+        self.write_bits_u64(value as u64, width)
+    }
+
     fn write_bits(&mut self, bits: u8, amount: usize) -> io::Result<()>;
     fn write_byte(&mut self, byte: u8) -> io::Result<()>;
     fn flush(&mut self) -> io::Result<()>;
 }
 
+const STAGING_BUFFER_SIZE: usize = 8192;
+
 pub struct BitWriter<W: Write> {
     inner: W,
     bit_buff: u8,
     bit_cursor: usize,
+    bytes_written: u64,
 }
 
 impl<W: Write> BitWriter<W> {
@@ -52,6 +138,7 @@ impl<W: Write> BitWriter<W> {
             inner,
             bit_buff: 0,
             bit_cursor: 0,
+            bytes_written: 0,
         }
     }
 
@@ -62,18 +149,123 @@ impl<W: Write> BitWriter<W> {
     pub fn bit_cursor(&self) -> usize {
         self.bit_cursor
     }
+
+    pub fn is_aligned(&self) -> bool {
+        self.bit_cursor == 0
+    }
+
+    // Bytes sent to the inner writer, the pending bits aren't counted until they are flushed.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    // Bits sent to the inner writer plus the pending ones.
+    pub fn bits_written(&self) -> u64 {
+        self.bytes_written * u8::BITS as u64 + self.bit_cursor as u64
+    }
+
+    // The amount of bits written but not yet sent to the inner writer.
+    pub fn pending_bits(&self) -> usize {
+        self.bit_cursor
+    }
+
+    // The partial byte being built, its unused high bits are zero.
+    pub fn pending_byte(&self) -> u8 {
+        self.bit_buff
+    }
+
+    pub fn aligned_writer(&mut self) -> AlignedWriter<'_, W> {
+        AlignedWriter { bit_writer: self }
+    }
+
+    // Removes the pending bits without writing them, leaving the writer aligned. Returns them with
+    // their amount, so they can be padded or written somewhere else.
+    pub fn take_pending(&mut self) -> (u8, usize) {
+        let pending = (self.bit_buff, self.bit_cursor);
+        self.bit_buff = 0;
+        self.bit_cursor = 0;
+
+        pending
+    }
+
+    fn write_buff_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.inner.write_all(&[byte])?;
+        self.bytes_written += 1;
+
+        Ok(())
+    }
 }
 
 impl<W: Write> BitWrite for BitWriter<W> {
+    // The writables are written to an in-memory staging writer, which is sent to the inner writer in
+    // chunks instead of one byte at a time.
+    fn write_all_writables<I>(&mut self, writables: I) -> io::Result<()>
+    where
+        I: IntoIterator,
+        I::Item: BitWritable,
+    {
+        let mut staging = BitWriter {
+            inner: Vec::with_capacity(STAGING_BUFFER_SIZE),
+            bit_buff: self.bit_buff,
+            bit_cursor: self.bit_cursor,
+            bytes_written: 0,
+        };
+
+        for writable in writables {
+            staging.write_writable(writable)?;
+
+            if staging.inner.len() >= STAGING_BUFFER_SIZE {
+                self.inner.write_all(&staging.inner)?;
+                self.bytes_written += staging.inner.len() as u64;
+                staging.inner.clear();
+            }
+        }
+
+        self.inner.write_all(&staging.inner)?;
+        self.bytes_written += staging.inner.len() as u64;
+
+        self.bit_buff = staging.bit_buff;
+        self.bit_cursor = staging.bit_cursor;
+
+        Ok(())
+    }
+
+    // When aligned, the whole full bytes are sent in one write instead of one byte at a time.
+    fn write_bytes(&mut self, bytes: &[u8], last_byte_amount: Option<usize>) -> io::Result<()> {
+        let (full, last) = match last_byte_amount {
+            Some(_) => match bytes.split_last() {
+                Some((last, full)) => (full, Some(*last)),
+                None => return Ok(()),
+            },
+            None => (bytes, None),
+        };
+
+        if self.is_aligned() {
+            self.inner.write_all(full)?;
+            self.bytes_written += full.len() as u64;
+        } else {
+            for byte in full {
+                self.write_byte(*byte)?;
+            }
+        }
+
+        if let (Some(last), Some(amount)) = (last, last_byte_amount) {
+            self.write_bits(last, amount)?;
+        }
+
+        Ok(())
+    }
+
     fn write_byte(&mut self, byte: u8) -> io::Result<()> {
         let bits_to_consume = u8::BITS as usize - self.bit_cursor;
 
         let mask = u8_mask(bits_to_consume as u32);
         let byte_to_send = self.bit_buff | (byte & mask) << self.bit_cursor;
 
-        self.inner.write_all(&[byte_to_send])?;
+        self.write_buff_byte(byte_to_send)?;
 
         self.bit_buff = byte.checked_shr(bits_to_consume as u32).unwrap_or(0);
+        trace_op!("write_byte value={byte} cursor={}", self.bit_cursor);
 
         Ok(())
     }
@@ -95,7 +287,7 @@ impl<W: Write> BitWrite for BitWriter<W> {
         // would let the cursor with a normally impossible value.
         let mut new_bit_cursor = self.bit_cursor + amount;
         if new_bit_cursor >= u8::BITS as usize {
-            self.inner.write_all(&[self.bit_buff])?;
+            self.write_buff_byte(self.bit_buff)?;
 
             new_bit_cursor -= u8::BITS as usize;
 
@@ -104,13 +296,16 @@ impl<W: Write> BitWrite for BitWriter<W> {
         }
 
         self.bit_cursor = new_bit_cursor;
+        trace_op!("write_bits amount={amount} value={bits} cursor={}", self.bit_cursor);
 
         Ok(())
     }
 
     fn flush(&mut self) -> io::Result<()> {
         if self.bit_cursor != 0 {
-            self.inner.write_all(&[self.bit_buff])?;
+            trace_op!("flush padding={}", u8::BITS as usize - self.bit_cursor);
+
+            self.write_buff_byte(self.bit_buff)?;
             self.bit_buff = 0;
             self.bit_cursor = 0;
         }
@@ -119,6 +314,33 @@ impl<W: Write> BitWrite for BitWriter<W> {
     }
 }
 
+// Writes bytes straight to the inner writer, so aligned payloads can be written at full speed with
+// `io::copy`. Only byte aligned writes are allowed.
+pub struct AlignedWriter<'w, W: Write> {
+    bit_writer: &'w mut BitWriter<W>,
+}
+
+impl<W: Write> Write for AlignedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.bit_writer.is_aligned() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "The bit writer isn't aligned on a byte.",
+            ));
+        }
+
+        let n = self.bit_writer.inner.write(buf)?;
+        self.bit_writer.bytes_written += n as u64;
+
+        Ok(n)
+    }
+
+    // Unlike `BitWrite::flush`, this doesn't pad the pending bits, it only flushes the inner writer.
+    fn flush(&mut self) -> io::Result<()> {
+        self.bit_writer.inner.flush()
+    }
+}
+
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
 mod test {
@@ -486,4 +708,256 @@ mod test {
         assert_eq!(&test_output.vec, &[0b00011100, 0b011]);
         assert_eq!(test_output.cursor_position, 0);
     }
+
+    #[test]
+    fn test_write_bits_checked() {
+        let test_output = crate::test::get_test_write_output(|writer| {
+            writer.write_bits_checked(0b101, 3)?;
+            writer.write_bits_checked(0b11111, 5)?;
+            writer.write_bits_checked(0, 0)
+        })
+        .unwrap();
+
+        assert_eq!(&test_output.vec, &[0b11111101]);
+        assert_eq!(test_output.cursor_position, 0);
+    }
+
+    #[test]
+    fn test_write_bits_checked_rejects_oversized_values() {
+        let mut cursor_position = None;
+        let err = crate::test::get_test_write_output(|writer| {
+            writer.write_bits(0b1, 1)?;
+
+            let res = writer.write_bits_checked(0b1101, 3);
+            cursor_position = Some(writer.bit_cursor());
+            res
+        })
+        .err()
+        .unwrap();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        assert_eq!(cursor_position, Some(1));
+    }
+
+    #[test]
+    fn test_write_bits_rev() {
+        let test_output = crate::test::get_test_write_output(|writer| {
+            writer.write_bits_rev(0b110, 3)?;
+            writer.write_bits_rev(0b10, 2)?;
+
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(&test_output.vec, &[0b01_011]);
+        assert_eq!(test_output.cursor_position, 5);
+    }
+
+    mod writables {
+        use std::io::Cursor;
+
+        use crate::{compact::CompactNumberU64, BitWrite, BitWriter};
+
+        #[test]
+        fn write_all_writables_is_equivalent_to_write_writable() {
+            let numbers = (0..5000u64).map(|i| CompactNumberU64(i * 37)).collect::<Vec<_>>();
+
+            let batched = crate::test::get_test_write_output(|writer| {
+                writer.write_bits(0b101, 3)?;
+                writer.write_all_writables(&numbers)?;
+                writer.write_bits(0b1, 1)
+            })
+            .unwrap();
+
+            let one_by_one = crate::test::get_test_write_output(|writer| {
+                writer.write_bits(0b101, 3)?;
+                for number in &numbers {
+                    writer.write_writable(number)?;
+                }
+                writer.write_bits(0b1, 1)
+            })
+            .unwrap();
+
+            assert_eq!(batched.vec, one_by_one.vec);
+            assert_eq!(batched.cursor_position, one_by_one.cursor_position);
+        }
+
+        #[test]
+        fn write_all_writables_counts_bytes() {
+            let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+            writer.write_bits(0b1, 1).unwrap();
+            writer
+                .write_all_writables([CompactNumberU64(1), CompactNumberU64(2)])
+                .unwrap();
+
+            assert_eq!(writer.bytes_written(), 4);
+            assert_eq!(writer.bits_written(), 33);
+        }
+    }
+
+    mod counters {
+        use std::io::{Cursor, Write};
+
+        use crate::{BitWrite, BitWriter};
+
+        #[test]
+        fn count_bytes_and_bits_written() {
+            let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+            assert_eq!(writer.bytes_written(), 0);
+            assert_eq!(writer.bits_written(), 0);
+
+            writer.write_bits(0b101, 3).unwrap();
+            assert_eq!(writer.bytes_written(), 0);
+            assert_eq!(writer.bits_written(), 3);
+
+            writer.write_bytes(&[1, 2], None).unwrap();
+            assert_eq!(writer.bytes_written(), 2);
+            assert_eq!(writer.bits_written(), 19);
+
+            writer.flush().unwrap();
+            assert_eq!(writer.bytes_written(), 3);
+            assert_eq!(writer.bits_written(), 24);
+
+            writer.aligned_writer().write_all(&[3, 4]).unwrap();
+            assert_eq!(writer.bytes_written(), 5);
+            assert_eq!(writer.into_inner().into_inner().len(), 5);
+        }
+    }
+
+    mod pending {
+        use std::io::Write;
+
+        use crate::{BitWrite, BitWriter};
+
+        #[test]
+        fn query_pending_bits() {
+            let mut writer = BitWriter::new(Vec::new());
+            writer.write_bits(0b101, 3).unwrap();
+            writer.write_byte(0xFF).unwrap();
+
+            assert_eq!(writer.pending_bits(), 3);
+            assert_eq!(writer.pending_byte(), 0b111);
+            assert_eq!(writer.bytes_written(), 1);
+        }
+
+        #[test]
+        fn take_pending_and_pad_deliberately() {
+            let mut writer = BitWriter::new(Vec::new());
+            writer.write_bits(0b101, 3).unwrap();
+
+            let (bits, amount) = writer.take_pending();
+            assert_eq!((bits, amount), (0b101, 3));
+            assert!(writer.is_aligned());
+
+            // pad with ones instead of the zeros `flush` would use.
+            writer.aligned_writer().write_all(&[bits | !0 << amount]).unwrap();
+            writer.flush().unwrap();
+
+            assert_eq!(writer.into_inner(), &[0b1111_1101]);
+        }
+    }
+
+    mod numbers {
+        use crate::{BitWrite, Endianness};
+
+        #[test]
+        fn write_u16() {
+            let output = crate::test::get_test_write_output(|writer| {
+                writer.write_u16(0x0102, Endianness::Little)?;
+                writer.write_u16(0x0102, Endianness::Big)
+            })
+            .unwrap();
+
+            assert_eq!(&output.vec, &[0x02, 0x01, 0x01, 0x02]);
+        }
+
+        #[test]
+        fn write_u32() {
+            let output = crate::test::get_test_write_output(|writer| {
+                writer.write_u32(0x01020304, Endianness::Little)?;
+                writer.write_u32(0x01020304, Endianness::Big)
+            })
+            .unwrap();
+
+            assert_eq!(&output.vec, &[4, 3, 2, 1, 1, 2, 3, 4]);
+        }
+
+        #[test]
+        fn write_u64_not_aligned() {
+            let output = crate::test::get_test_write_output(|writer| {
+                writer.write_bits(0b1111, 4)?;
+                writer.write_u64(0x0102030405060708, Endianness::Big)
+            })
+            .unwrap();
+
+            assert_eq!(
+                &output.vec,
+                &[0x1F, 0x20, 0x30, 0x40, 0x50, 0x60, 0x70, 0x80, 0x0]
+            );
+            assert_eq!(output.cursor_position, 4);
+        }
+    }
+
+    mod signed {
+        use crate::BitWrite;
+
+        #[test]
+        fn write_small_signed_values() {
+            let output = crate::test::get_test_write_output(|writer| {
+                writer.write_signed_bits(-1, 3)?;
+                writer.write_signed_bits(2, 3)?;
+                writer.write_signed_bits(-4, 3)
+            })
+            .unwrap();
+
+            assert_eq!(&output.vec, &[0b00_010_111, 0b1]);
+            assert_eq!(output.cursor_position, 1);
+        }
+
+        #[test]
+        fn write_wide_signed_value() {
+            let output =
+                crate::test::get_test_write_output(|writer| writer.write_signed_bits(-2, 12))
+                    .unwrap();
+
+            assert_eq!(&output.vec, &[0b11111110, 0b1111]);
+            assert_eq!(output.cursor_position, 4);
+        }
+
+        #[test]
+        fn write_full_width_signed_value() {
+            let output = crate::test::get_test_write_output(|writer| {
+                writer.write_signed_bits(i64::MIN, 64)
+            })
+            .unwrap();
+
+            assert_eq!(&output.vec, &[0, 0, 0, 0, 0, 0, 0, 0x80]);
+        }
+    }
+
+    mod io_write {
+        use std::io::{Cursor, Write};
+
+        use crate::{BitWrite, BitWriter};
+
+        #[test]
+        fn write_all_when_aligned() {
+            let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+            writer.write_bits(0b1111, 4).unwrap();
+            writer.write_bits(0b0000, 4).unwrap();
+            writer.aligned_writer().write_all(&[2, 3]).unwrap();
+
+            assert_eq!(writer.into_inner().into_inner(), &[0b00001111, 2, 3]);
+        }
+
+        #[test]
+        fn write_when_not_aligned_should_fail() {
+            let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+            writer.write_bits(0b1111, 4).unwrap();
+
+            let err = writer.aligned_writer().write(&[2, 3]).unwrap_err();
+
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+        }
+    }
 }
@@ -0,0 +1,1418 @@
+use std::{ffi::OsString, fs::File, io::{self, IsTerminal, Read, Write}, path::{Path, PathBuf}, process::ExitCode};
+
+use anyhow::Context;
+use clap::{CommandFactory, ValueHint};
+use clap_complete::Shell;
+use huffman_format::{ChecksumKind, UnpackOptions};
+use log::{warn, LevelFilter};
+
+use crate::io::{first_volume_base, Input, Output, OutputWrite};
+
+// Packing reads the input twice, so stdin is buffered in memory up to that size by default, then
+// in a temporary file.
+const STDIN_MEMORY_BUFFER_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ValidationError {
+    #[error("Stdin can only be used once as input.")]
+    StdinUsedMultipleTimes,
+
+    #[error("The output can't be specified when there are several inputs.")]
+    OutputWithMultipleInputs,
+
+    #[error("The output file must be specified when using stdin as input.")]
+    RequiresOutputWhenUsingStdin,
+
+    #[error("The output can't be a directory when using stdin as input.")]
+    OutputDirectoryWithStdin,
+
+    #[error("Refusing to write packed data to a terminal, use `--force` to do it anyway.")]
+    PackedDataToTerminal,
+
+    #[error("`concat` needs an output, given with `-o` or `-c`.")]
+    ConcatRequiresOutput,
+
+    #[error("`--split-size` is only supported when packing to files, with a size of at least one byte.")]
+    InvalidSplit
+}
+
+// The exit codes, so scripts can tell why the command failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Exit {
+    Success = 0,
+    // Any failure not covered below.
+    Failure = 1,
+    // Invalid arguments, which is also what clap uses.
+    Usage = 2,
+    InputNotFound = 3,
+    OutputExists = 4,
+    // The packed data is invalid or truncated.
+    CorruptArchive = 5,
+    Io = 6,
+}
+
+impl Exit {
+    pub fn of(err: &anyhow::Error) -> Self {
+        if let Some(failed) = err.downcast_ref::<InputsFailed>() {
+            return failed.exit
+        }
+        if err.downcast_ref::<ValidationError>().is_some() {
+            return Self::Usage
+        }
+
+        let Some(io_err) = err.chain().find_map(|err| err.downcast_ref::<io::Error>()) else {
+            return Self::Failure
+        };
+        match io_err.kind() {
+            io::ErrorKind::NotFound => Self::InputNotFound,
+            io::ErrorKind::AlreadyExists => Self::OutputExists,
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => Self::CorruptArchive,
+            _ => Self::Io,
+        }
+    }
+}
+
+impl From<Exit> for ExitCode {
+    fn from(exit: Exit) -> Self {
+        ExitCode::from(exit as u8)
+    }
+}
+
+// Returned when some of the inputs failed, with the exit code of the first failure.
+#[derive(Debug, thiserror::Error)]
+#[error("Failed to process {failed} of {total} inputs")]
+pub struct InputsFailed {
+    pub failed: usize,
+    pub total: usize,
+    pub exit: Exit,
+}
+
+// Printed by `--version`, for bug reports and compatibility checks.
+static LONG_VERSION: once_cell::sync::Lazy<String> = once_cell::sync::Lazy::new(|| {
+    let mut features = huffman_format::FEATURES.to_vec();
+    features.extend(["split volumes", "parallel files"]);
+
+    format!(
+        "{}\nhuffman_format {}\nformats : {}\nfeatures : {}",
+        env!("CARGO_PKG_VERSION"), huffman_format::VERSION, huffman_format::FORMATS, features.join(", ")
+    )
+});
+
+// The options shared by the commands are global, so they can also come before the command.
+#[derive(clap::Parser, Debug)]
+#[clap(version, long_version = LONG_VERSION.as_str())]
+#[clap(after_help = "Exit codes: 0 success, 1 failure, 2 usage error, 3 input not found, 4 output exists, 5 corrupt archive, 6 I/O error.\n\nThe `HC_OPTS` environment variable holds options used before the ones of the command line, separated by spaces. The ones the command doesn't have are ignored.")]
+pub struct Cli {
+    #[clap(subcommand)]
+    pub command: Command,
+
+    /// The output file, or directory when there are several inputs. `-` is stdout.
+    #[clap(short, long, value_parser = Output::parse_value, value_hint = ValueHint::AnyPath, global = true)]
+    output: Option<Output>,
+
+    /// Writes to stdout, which needs `--force` for packed data when it's a terminal.
+    #[clap(short = 'c', long, conflicts_with = "output", global = true)]
+    stdout: bool,
+
+    /// Only logs the errors.
+    #[clap(short, long, conflicts_with = "verbose", global = true)]
+    pub quiet: bool,
+
+    /// Logs more details, can be repeated.
+    #[clap(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// `json` prints a result object per file to stdout, or to stderr when the data is written
+    /// to stdout.
+    #[clap(long, value_enum, default_value_t = Format::Text, global = true)]
+    pub format: Format,
+
+    /// `auto` colors the logs when stderr is a terminal and `NO_COLOR` isn't set.
+    #[clap(long, value_enum, default_value_t = Color::Auto, value_name = "WHEN", global = true)]
+    color: Color,
+
+    /// Also appends the logs to that file, without colors and with the time of each line.
+    #[clap(long, value_name = "PATH", value_hint = ValueHint::FilePath, global = true)]
+    pub log_file: Option<PathBuf>,
+
+    /// Writes packed data to a terminal and reads from stdin even when it's a terminal.
+    #[clap(short, long, global = true)]
+    pub force: bool,
+
+    /// Ignores the configuration file.
+    #[clap(long, global = true)]
+    pub no_config: bool,
+
+    /// Prints the times, the throughput and the sizes of every processed file.
+    #[clap(long, global = true)]
+    pub time: bool,
+
+    /// Prints the sizes like `1.4 MiB` instead of a count of bytes, except in the JSON reports.
+    #[clap(long, global = true)]
+    pub human: bool,
+
+    /// `json` writes a record of the bytes read and written, the percentage and the remaining
+    /// time as a line to stderr twice a second, and when a file is done.
+    #[clap(long, value_enum, value_name = "FORMAT", global = true)]
+    progress: Option<Progress>,
+
+    /// Writes the progress records to that file descriptor instead of stderr.
+    #[cfg(unix)]
+    #[clap(long, value_name = "FD", requires = "progress", global = true)]
+    progress_fd: Option<i32>
+}
+
+#[derive(clap::Args, Debug)]
+pub struct Inputs {
+    /// The files to process, `-` is stdin.
+    #[clap(value_parser = Input::parse_value, required_unless_present = "files_from", value_hint = ValueHint::FilePath)]
+    inputs: Vec<Input>,
+
+    /// Reads more inputs from a list with one path per line, or from stdin with `-`.
+    #[clap(long, value_name = "LIST", value_hint = ValueHint::FilePath)]
+    files_from: Option<PathBuf>,
+
+    /// The paths of the list are separated by null characters instead of new lines.
+    #[clap(short = '0', long, requires = "files_from")]
+    null: bool
+}
+
+// The options of `pack` and `unpack`, which write an output for each input.
+#[derive(clap::Args, Debug)]
+pub struct OutputArgs {
+    /// Replaces the outputs which already exist.
+    #[clap(short='W', long)]
+    overwrite: bool,
+
+    /// Skips the inputs whose output already exists, without failing, so an interrupted batch
+    /// can be run again to finish it.
+    #[clap(long, conflicts_with = "overwrite")]
+    skip_existing: bool,
+
+    /// Keeps the input files, which is the default.
+    #[clap(short = 'k', long, conflicts_with = "rm")]
+    keep: bool,
+
+    /// Removes the input files once they have been processed successfully.
+    #[clap(long)]
+    rm: bool,
+
+    /// The amount of threads to use, all the cores by default.
+    #[clap(short = 'T', long, value_parser = clap::value_parser!(u32).range(1..))]
+    threads: Option<u32>,
+
+    /// The extension added when packing and removed when unpacking.
+    #[clap(short = 'S', long, value_parser = parse_suffix)]
+    suffix: Option<String>,
+
+    /// Packs and unpacks the targets of the symbolic links, which are skipped by default.
+    #[clap(long, overrides_with = "no_dereference")]
+    dereference: bool,
+
+    /// Skips the symbolic links, which is the default.
+    #[clap(long, overrides_with = "dereference")]
+    no_dereference: bool,
+
+    /// Keeps the output of a failed file as `<OUTPUT>.part` instead of removing it.
+    #[clap(long)]
+    keep_partial: bool
+}
+
+#[derive(clap::Args, Debug)]
+pub struct PackArgs {
+    #[clap(flatten)]
+    pub inputs: Inputs,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    /// Adds a checksum of the data, `crc32` when the kind isn't given.
+    #[clap(long, value_enum, num_args = 0..=1, require_equals = true, default_missing_value = "crc32", value_name = "KIND")]
+    checksum: Option<Checksum>,
+
+    /// Only counts the bytes of the inputs, and reports the estimated size of the outputs.
+    #[clap(long)]
+    pub dry_run: bool,
+
+    /// Packs the files which would get bigger, which are skipped by default.
+    #[clap(long)]
+    pub force_expand: bool,
+
+    /// Unpacks the packed output once written and compares it with the input, before removing
+    /// the input with `--rm`.
+    #[clap(long)]
+    pub verify_after_pack: bool,
+
+    /// The most memory used to buffer stdin and the pipes, which are read twice, beyond which a
+    /// temporary file is used.
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    pub stdin_buffer: Option<u64>,
+
+    /// Splits the packed output in volumes of at most that size, named `.001`, `.002`, ...
+    /// Unpacking a `.001` file reads the next volumes after it.
+    #[clap(long, value_parser = parse_size)]
+    pub split_size: Option<u64>
+}
+
+// The options of the commands decoding the data.
+#[derive(clap::Args, Debug)]
+pub struct ReadArgs {
+    #[clap(flatten)]
+    pub inputs: Inputs,
+
+    /// Only unpacks that member of concatenated files, numbered from 1 like by `list`. Where the
+    /// members end isn't stored, so the ones before it are still decoded, which takes as long as
+    /// unpacking them.
+    #[clap(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    member: Option<u64>,
+
+    /// Refuses to unpack more than that size, which is checked before decoding. There is no
+    /// memory limit for unpacking: the decoder streams the data with fixed-size buffers and a
+    /// tree of at most 256 leaves, whatever the input.
+    #[clap(long, value_parser = parse_size)]
+    max_size: Option<u64>,
+
+    /// Doesn't compare the unpacked data with its checksum.
+    #[clap(long)]
+    pub no_verify: bool
+}
+
+#[derive(clap::Args, Debug)]
+pub struct UnpackArgs {
+    #[clap(flatten)]
+    pub read: ReadArgs,
+
+    #[clap(flatten)]
+    pub output: OutputArgs,
+
+    /// Unpacks into `tar -x`, which extracts in the output directory or the current one.
+    #[clap(long, conflicts_with = "stdout")]
+    pub untar: bool
+}
+
+#[derive(clap::Args, Debug)]
+pub struct ConcatArgs {
+    #[clap(flatten)]
+    pub inputs: Inputs,
+
+    /// Replaces the output if it already exists.
+    #[clap(short='W', long)]
+    overwrite: bool
+}
+
+#[derive(clap::Args, Debug)]
+pub struct TreeArgs {
+    #[clap(flatten)]
+    pub inputs: Inputs,
+
+    /// Prints the graph in the DOT language.
+    #[clap(long)]
+    pub dot: bool
+}
+
+#[derive(clap::Args, Debug)]
+pub struct DumpArgs {
+    #[clap(flatten)]
+    pub inputs: Inputs,
+
+    /// The amount of codes of the data to print.
+    #[clap(long, value_name = "N", default_value_t = 16)]
+    pub codewords: u64
+}
+
+// Built, so the subcommands also have the global options.
+fn built_command() -> clap::Command {
+    let mut command = Cli::command();
+    command.build();
+
+    command
+}
+
+// The amount of arguments taken by the option `args` start with, with its value, or `None`
+// when it's not an option.
+fn option_len(command: &clap::Command, args: &[OsString]) -> Option<usize> {
+    let arg = args.first()?.to_str()?;
+    if arg == "-" || !arg.starts_with('-') {
+        return None
+    }
+
+    let all_args = || command.get_arguments().chain(command.get_subcommands().flat_map(|subcommand| subcommand.get_arguments()));
+    let takes_value = |found: Option<&clap::Arg>| found.is_some_and(|found| found.get_action().takes_values() && !found.is_require_equals_set());
+
+    if let Some(long) = arg.strip_prefix("--") {
+        let found = all_args().find(|found| found.get_long() == Some(long));
+        return Some(if takes_value(found) { 2 } else { 1 })
+    }
+
+    // short options can be grouped like `-qW`, the one taking a value ends the group.
+    for (index, short) in arg.char_indices().skip(1) {
+        if takes_value(all_args().find(|found| found.get_short() == Some(short))) {
+            return Some(if index + short.len_utf8() == arg.len() { 2 } else { 1 })
+        }
+    }
+
+    Some(1)
+}
+
+fn has_option(command: &clap::Command, arg: &str) -> bool {
+    let mut args = command.get_arguments();
+    match arg.strip_prefix("--") {
+        Some(long) => {
+            let long = long.split_once('=').map_or(long, |(long, _)| long);
+            args.any(|found| found.get_long() == Some(long))
+        }
+        None => {
+            let short = arg.chars().nth(1);
+            args.any(|found| found.get_short().is_some() && found.get_short() == short)
+        }
+    }
+}
+
+// The command used to be a value anywhere among the options, like in `hc -o out pack file`, so
+// it's moved first, before the options of the subcommand.
+pub fn command_first(args: impl Iterator<Item = OsString>) -> Vec<OsString> {
+    let mut args: Vec<OsString> = args.collect();
+    let command = built_command();
+
+    let mut index = 1;
+    while index < args.len() && args[index] != "--" {
+        match option_len(&command, &args[index..]) {
+            Some(len) => index += len,
+            None => {
+                if command.find_subcommand(&args[index]).is_some() {
+                    let name = args.remove(index);
+                    args.insert(1, name);
+                }
+                break
+            }
+        }
+    }
+
+    args
+}
+
+// Inserts the options of `HC_OPTS` after the command, without the ones of the other commands,
+// like `--split-size` for `unpack`.
+pub fn with_env_options(mut args: Vec<OsString>, env_options: Option<OsString>) -> Vec<OsString> {
+    let Some(env_options) = env_options else {
+        return args
+    };
+    let env_args: Vec<OsString> = env_options.to_string_lossy().split_whitespace().map(OsString::from).collect();
+
+    let command = built_command();
+    let subcommand = args.get(1).and_then(|arg| command.find_subcommand(arg));
+    let target = subcommand.unwrap_or(&command);
+    let all_commands: Vec<&clap::Command> = std::iter::once(&command).chain(command.get_subcommands()).collect();
+
+    let mut options = Vec::new();
+    let mut index = 0;
+    while index < env_args.len() {
+        let len = option_len(&command, &env_args[index..]).unwrap_or(1).min(env_args.len() - index);
+        // the unknown options are kept, for clap to report them.
+        let arg = env_args[index].to_string_lossy();
+        let elsewhere = all_commands.iter().any(|command| has_option(command, &arg));
+        if has_option(target, &arg) || !elsewhere {
+            options.extend_from_slice(&env_args[index..index + len]);
+        }
+        index += len;
+    }
+
+    let position = if subcommand.is_some() { 2 } else { 1 }.min(args.len());
+    args.splice(position..position, options);
+
+    args
+}
+
+// Defaults for some of the options, which the flags override. There is no `level` key: the
+// encoder has a single mode, so `--level` was dropped with it.
+#[derive(serde::Deserialize, Debug, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    threads: Option<u32>,
+    suffix: Option<String>,
+    // Removes the inputs, unless `--keep` is used.
+    rm: Option<bool>,
+    // Keeps the inputs, unless `--rm` is used.
+    keep: Option<bool>,
+    checksum: Option<Checksum>,
+}
+
+impl Config {
+    // `$XDG_CONFIG_HOME/huffman_format/config.toml`, where the default directory is `~/.config`.
+    pub fn path() -> Option<PathBuf> {
+        let config_dir = std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| Some(dirs::home_dir()?.join(".config")))?;
+
+        Some(config_dir.join("huffman_format").join("config.toml"))
+    }
+
+    // A missing file gives the default configuration.
+    pub fn load() -> anyhow::Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default())
+        };
+
+        let content = match std::fs::read_to_string(&path) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err).with_context(|| format!("Failed to read the configuration file `{}`", path.display())),
+        };
+
+        Self::parse(&content).with_context(|| format!("Invalid configuration file `{}`", path.display()))
+    }
+
+    pub fn parse(content: &str) -> anyhow::Result<Self> {
+        let config: Self = toml::from_str(content)?;
+
+        if config.threads == Some(0) {
+            anyhow::bail!("There must be at least one thread.")
+        }
+        if config.rm == Some(true) && config.keep == Some(true) {
+            anyhow::bail!("`rm` and `keep` can't both be true.")
+        }
+        let suffix = config.suffix.as_deref().map(parse_suffix).transpose().map_err(anyhow::Error::msg)?;
+
+        Ok(Self { suffix, ..config })
+    }
+}
+
+fn parse_suffix(str: &str) -> Result<String, String> {
+    let suffix = str.strip_prefix('.').unwrap_or(str);
+    if suffix.is_empty() {
+        return Err("The suffix can't be empty.".to_string())
+    }
+
+    Ok(suffix.to_string())
+}
+
+// Takes a shell instead of inputs.
+#[derive(clap::Args, Debug)]
+pub struct Completions {
+    #[clap(value_enum)]
+    shell: Shell
+}
+
+impl Completions {
+    pub fn print(&self) -> io::Result<()> {
+        let mut command = Cli::command();
+        let name = command.get_name().to_owned();
+
+        // generated in memory, as `generate` panics when stdout is closed.
+        let mut script = Vec::new();
+        clap_complete::generate(self.shell, &mut command, name, &mut script);
+        OutputWrite::Stdout(io::stdout().lock()).write_all(&script)
+    }
+}
+
+// Packs and unpacks a file, or a built-in corpus, and compares the result with the original.
+#[derive(clap::Args, Debug)]
+pub struct Selftest {
+    #[clap(value_hint = ValueHint::FilePath)]
+    pub file: Option<PathBuf>
+}
+
+// Parses a size with an optional binary suffix : K, M, G or T, followed by an optional `iB` or `B`.
+pub fn parse_size(str: &str) -> Result<u64, String> {
+    let str = str.trim();
+    let digits_end = str.find(|c: char| !c.is_ascii_digit()).unwrap_or(str.len());
+    let (number, suffix) = str.split_at(digits_end);
+
+    let number: u64 = number.parse().map_err(|_| format!("Expected a size, found `{}`.", str))?;
+
+    let suffix = suffix.trim_start().to_ascii_uppercase();
+    let suffix = suffix.strip_suffix("IB").or_else(|| suffix.strip_suffix('B')).unwrap_or(&suffix);
+    let shift = match suffix {
+        "" => 0,
+        "K" => 10,
+        "M" => 20,
+        "G" => 30,
+        "T" => 40,
+        _ => return Err(format!("Unknown size suffix in `{}`.", str)),
+    };
+
+    number.checked_mul(1 << shift).ok_or_else(|| format!("The size `{}` is too big.", str))
+}
+
+impl Cli {
+    // The level asked with the flags, if any.
+    pub fn log_level(&self) -> Option<LevelFilter> {
+        if self.quiet {
+            return Some(LevelFilter::Error)
+        }
+
+        match self.verbose {
+            0 => None,
+            1 => Some(LevelFilter::Debug),
+            _ => Some(LevelFilter::Trace),
+        }
+    }
+
+    pub fn colors(&self) -> bool {
+        match self.color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => {
+                std::env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty()) && io::stderr().is_terminal()
+            }
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        match self.output_args().and_then(|output| output.threads) {
+            Some(threads) => threads as usize,
+            None => std::thread::available_parallelism().map_or(1, |threads| threads.get()),
+        }
+    }
+
+    pub fn removes_inputs(&self) -> bool {
+        self.output_args().is_some_and(|output| output.rm && !output.keep)
+    }
+
+    pub fn overwrite(&self) -> bool {
+        match &self.command {
+            Command::Concat(concat) => concat.overwrite,
+            _ => self.output_args().is_some_and(|output| output.overwrite),
+        }
+    }
+
+    pub fn skip_existing(&self) -> bool {
+        self.output_args().is_some_and(|output| output.skip_existing)
+    }
+
+    pub fn dereference(&self) -> bool {
+        self.output_args().is_some_and(|output| output.dereference)
+    }
+
+    pub fn keep_partial(&self) -> bool {
+        self.output_args().is_some_and(|output| output.keep_partial)
+    }
+
+    pub fn writes_to_stdout(&self) -> bool {
+        self.stdout || matches!(self.command, Command::Cat(_)) || self.output == Some(Output::Stdout)
+    }
+
+    pub fn pack_args(&self) -> Option<&PackArgs> {
+        match &self.command {
+            Command::Pack(pack) => Some(pack),
+            _ => None
+        }
+    }
+
+    fn output_args(&self) -> Option<&OutputArgs> {
+        match &self.command {
+            Command::Pack(pack) => Some(&pack.output),
+            Command::Unpack(unpack) => Some(&unpack.output),
+            _ => None
+        }
+    }
+
+    fn read_args(&self) -> Option<&ReadArgs> {
+        match &self.command {
+            Command::Unpack(unpack) => Some(&unpack.read),
+            Command::Test(read) | Command::Cat(read) => Some(read),
+            _ => None
+        }
+    }
+
+    pub fn dry_run(&self) -> bool {
+        self.pack_args().is_some_and(|pack| pack.dry_run)
+    }
+
+    pub fn split_size(&self) -> Option<u64> {
+        self.pack_args().and_then(|pack| pack.split_size)
+    }
+
+    pub fn untar(&self) -> bool {
+        matches!(&self.command, Command::Unpack(unpack) if unpack.untar)
+    }
+
+    pub fn apply_config(&mut self, config: Config) {
+        let output = match &mut self.command {
+            Command::Pack(pack) => {
+                pack.checksum = pack.checksum.or(config.checksum);
+                &mut pack.output
+            }
+            Command::Unpack(unpack) => &mut unpack.output,
+            _ => return
+        };
+
+        output.threads = output.threads.or(config.threads);
+        output.suffix = output.suffix.take().or(config.suffix);
+        if config.rm == Some(true) && !output.keep {
+            output.rm = true;
+        }
+        if config.keep == Some(true) && !output.rm {
+            output.keep = true;
+        }
+    }
+
+    pub fn format_size(&self, size: u64) -> String {
+        const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+
+        if !self.human || size < 1024 {
+            return format!("{} bytes", size)
+        }
+
+        let mut value = size as f64 / 1024.0;
+        let mut unit = 0;
+        while value >= 1024.0 && unit < UNITS.len() - 1 {
+            value /= 1024.0;
+            unit += 1;
+        }
+
+        format!("{:.1} {}", value, UNITS[unit])
+    }
+
+    pub fn unpack_options(&self) -> UnpackOptions {
+        let Some(read) = self.read_args() else {
+            return UnpackOptions::default()
+        };
+
+        UnpackOptions {
+            verify: !read.no_verify,
+            max_size: read.max_size,
+            member: read.member.map(|member| member as usize - 1),
+        }
+    }
+
+    pub fn reports_progress(&self) -> bool {
+        self.progress.is_some()
+    }
+
+    // The descriptor of `--progress-fd`, once checked to be open.
+    #[cfg(unix)]
+    pub fn progress_file(&self) -> io::Result<Option<File>> {
+        let Some(fd) = self.progress_fd else {
+            return Ok(None)
+        };
+
+        // SAFETY: `fcntl` only reads the flags of the descriptor, which is then owned by the
+        // file until the end of the process.
+        if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+            return Err(io::Error::last_os_error())
+        }
+        Ok(Some(unsafe { <File as std::os::unix::io::FromRawFd>::from_raw_fd(fd) }))
+    }
+
+    #[cfg(not(unix))]
+    pub fn progress_file(&self) -> io::Result<Option<File>> {
+        Ok(None)
+    }
+
+    pub fn stdin_buffer(&self) -> u64 {
+        self.pack_args().and_then(|pack| pack.stdin_buffer).unwrap_or(STDIN_MEMORY_BUFFER_SIZE)
+    }
+
+    pub fn checksum(&self) -> ChecksumKind {
+        self.pack_args().and_then(|pack| pack.checksum).map_or(ChecksumKind::None, ChecksumKind::from)
+    }
+
+    fn suffix(&self) -> &str {
+        self.output_args().and_then(|output| output.suffix.as_deref()).unwrap_or("hc")
+    }
+
+    // Adds the inputs listed by `--files-from`.
+    pub fn load_files_from(&mut self) -> anyhow::Result<()> {
+        let Some(inputs) = self.command.inputs_mut() else {
+            return Ok(())
+        };
+        let Some(list_path) = &inputs.files_from else {
+            return Ok(())
+        };
+
+        let mut list = Vec::new();
+        if list_path.as_os_str() == "-" {
+            if inputs.inputs.contains(&Input::Stdin) {
+                return Err(ValidationError::StdinUsedMultipleTimes.into())
+            }
+            io::stdin().read_to_end(&mut list)
+        } else {
+            File::open(list_path).and_then(|mut file| file.read_to_end(&mut list))
+        }.with_context(|| "Failed to read the list of inputs")?;
+
+        let separator = if inputs.null { b'\0' } else { b'\n' };
+        for path in list.split(|byte| *byte == separator).filter(|path| !path.is_empty()) {
+            // the paths are kept as they are on unix, where they aren't always valid UTF-8.
+            #[cfg(unix)]
+            let path = PathBuf::from(<OsString as std::os::unix::ffi::OsStringExt>::from_vec(path.to_vec()));
+            #[cfg(not(unix))]
+            let path = PathBuf::from(String::from_utf8_lossy(path).into_owned());
+
+            let input = Input::from_path(path.clone())
+                .map_err(|err| anyhow::anyhow!("`{}` : {}", path.display(), err))?;
+            inputs.inputs.push(input);
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_inputs(&self) -> Result<&[Input], ValidationError> {
+        let inputs = self.command.inputs().map_or(&[][..], |inputs| &inputs.inputs);
+        if inputs.iter().filter(|input| matches!(input, Input::Stdin)).count() > 1 {
+            return Err(ValidationError::StdinUsedMultipleTimes)
+        }
+        if inputs.len() > 1 && self.output.is_some() && self.output_directory().is_none() && !matches!(self.command, Command::Concat(_)) {
+            return Err(ValidationError::OutputWithMultipleInputs)
+        }
+
+        Ok(inputs)
+    }
+    
+    // Where `--untar` extracts, `None` for the current directory.
+    pub fn untar_directory(&self) -> Option<&Path> {
+        match &self.output {
+            Some(Output::File(path)) => Some(path),
+            _ => None
+        }
+    }
+
+    // The output when it's an existing directory, in which the outputs are created.
+    fn output_directory(&self) -> Option<&Path> {
+        match &self.output {
+            Some(Output::File(path)) if path.is_dir() => Some(path),
+            _ => None
+        }
+    }
+
+    pub fn validate_output(&self, input: &Input) -> Result<Output, ValidationError> {
+        if matches!(self.command, Command::Info(_) | Command::Test(_) | Command::Bench(_) | Command::Stats(_) | Command::Tree(_) | Command::List(_) | Command::DebugDump(_)) || self.writes_to_stdout() {
+            return Ok(Output::Stdout)
+        }
+        if matches!(self.command, Command::Concat(_)) {
+            return self.output.clone().ok_or(ValidationError::ConcatRequiresOutput)
+        }
+        if let Some(directory) = self.output_directory() {
+            let Input::File(input_path) = input else {
+                return Err(ValidationError::OutputDirectoryWithStdin)
+            };
+
+            let path = self.derive_output_path(input_path);
+            return Ok(Output::File(directory.join(path.file_name().unwrap_or_default())))
+        }
+        if let Some(output) = &self.output {
+            return Ok(output.clone())
+        }
+
+        if let Input::File(input_path) = input {
+            return Ok(Output::File(self.derive_output_path(input_path)))
+        }
+
+        if !io::stdout().is_terminal() {
+            Ok(Output::Stdout)
+        } else {
+            Err(ValidationError::RequiresOutputWhenUsingStdin)
+        }
+    }
+
+    // The file name without the suffix, if it has it.
+    fn strip_suffix<'a>(&self, path: &'a Path) -> Option<&'a str> {
+        // the suffix can have several parts, like `tar.hc`.
+        path.file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(self.suffix())?.strip_suffix('.'))
+            .filter(|name| !name.is_empty())
+    }
+
+    pub fn has_suffix(&self, input: &Input) -> bool {
+        matches!(input, Input::File(path) if self.strip_suffix(first_volume_base(path).as_deref().unwrap_or(path)).is_some())
+    }
+
+    fn derive_output_path(&self, input_path: &Path) -> PathBuf {
+        fn add_extension(path: &mut PathBuf, part: &str) -> OsString{
+            let mut extension = path.extension().unwrap_or_default().to_owned();
+            if !extension.is_empty() {
+                extension.push(".");
+            }
+            extension.push(part);
+
+            path.set_extension(&extension);
+
+            extension
+        }
+
+        let volume_base = first_volume_base(input_path).filter(|_| matches!(self.command, Command::Unpack(_) | Command::Cat(_)));
+        let input_path = volume_base.as_deref().unwrap_or(input_path);
+
+        let stripped_name = self.strip_suffix(input_path);
+        let mut path = input_path.to_owned();
+
+        match self.command {
+            Command::Pack(_) => {
+                add_extension(&mut path, self.suffix());
+                path
+            }
+            Command::Unpack(_) | Command::Cat(_) => if let Some(stripped_name) = stripped_name {
+                path.set_file_name(stripped_name);
+                path
+            } else {
+                let new_extension = add_extension(&mut path, "unpacked");
+
+                warn!("The input file doesn't have the extension `{}`. The output file extension be `{}`", self.suffix(), new_extension.to_string_lossy());
+
+                path
+            }
+            Command::Info(_) | Command::Test(_) | Command::Bench(_) | Command::Stats(_) | Command::Concat(_) | Command::Tree(_) | Command::List(_) | Command::DebugDump(_) | Command::Completions(_) | Command::Selftest(_) => unreachable!(),
+        }
+    }
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    Json
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Auto,
+    Always,
+    Never
+}
+
+#[derive(clap::ValueEnum, serde::Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Checksum {
+    Crc32,
+    Xxh64,
+    None
+}
+
+impl From<Checksum> for ChecksumKind {
+    fn from(checksum: Checksum) -> Self {
+        match checksum {
+            Checksum::Crc32 => Self::Crc32,
+            Checksum::Xxh64 => Self::Xxh64,
+            Checksum::None => Self::None,
+        }
+    }
+}
+
+// The later occurrences of an option override the earlier ones, like the ones of `HC_OPTS`.
+#[derive(clap::Subcommand, Debug)]
+pub enum Command {
+    #[clap(about = "Packs files, adding the suffix to their names", args_override_self = true)]
+    Pack(PackArgs),
+    #[clap(about = "Unpacks files, removing the suffix from their names", args_override_self = true)]
+    Unpack(UnpackArgs),
+    #[clap(about = "Prints the headers of packed files", args_override_self = true)]
+    Info(Inputs),
+    #[clap(about = "Decodes packed files without writing them, to check their integrity", args_override_self = true)]
+    Test(ReadArgs),
+    #[clap(about = "Unpacks to stdout", args_override_self = true)]
+    Cat(ReadArgs),
+    #[clap(about = "Packs and unpacks in memory, and compares with the entropy and gzip", args_override_self = true)]
+    Bench(Inputs),
+    #[clap(about = "Prints the byte histogram and the estimated packed size, without packing", args_override_self = true)]
+    Stats(Inputs),
+    #[clap(about = "Joins packed files into one, which unpacks to the concatenation of their data", args_override_self = true)]
+    Concat(ConcatArgs),
+    #[clap(about = "Prints the Huffman tree of packed files, with the code of every byte", args_override_self = true)]
+    Tree(TreeArgs),
+    // the members have no names, they are numbered from 1.
+    #[clap(about = "Lists the members of packed files, the files joined by `concat`, with their sizes", args_override_self = true)]
+    List(Inputs),
+    // for working on the format, so it's not in the help.
+    #[clap(about = "Prints the fields of the headers with their bit offsets, the tree, and the first codes of the data", hide = true, args_override_self = true)]
+    DebugDump(DumpArgs),
+    #[clap(about = "Prints the completion script of a shell")]
+    Completions(Completions),
+    #[clap(about = "Packs and unpacks a file, or a built-in corpus, and checks that it round trips")]
+    Selftest(Selftest)
+}
+
+impl Command {
+    // `None` for the commands without inputs.
+    fn inputs(&self) -> Option<&Inputs> {
+        match self {
+            Self::Pack(pack) => Some(&pack.inputs),
+            Self::Concat(concat) => Some(&concat.inputs),
+            Self::Unpack(unpack) => Some(&unpack.read.inputs),
+            Self::Test(read) | Self::Cat(read) => Some(&read.inputs),
+            Self::Tree(tree) => Some(&tree.inputs),
+            Self::DebugDump(dump) => Some(&dump.inputs),
+            Self::Info(inputs) | Self::Bench(inputs) | Self::Stats(inputs) | Self::List(inputs) => Some(inputs),
+            Self::Completions(_) | Self::Selftest(_) => None,
+        }
+    }
+
+    fn inputs_mut(&mut self) -> Option<&mut Inputs> {
+        match self {
+            Self::Pack(pack) => Some(&mut pack.inputs),
+            Self::Concat(concat) => Some(&mut concat.inputs),
+            Self::Unpack(unpack) => Some(&mut unpack.read.inputs),
+            Self::Test(read) | Self::Cat(read) => Some(&mut read.inputs),
+            Self::Tree(tree) => Some(&mut tree.inputs),
+            Self::DebugDump(dump) => Some(&mut dump.inputs),
+            Self::Info(inputs) | Self::Bench(inputs) | Self::Stats(inputs) | Self::List(inputs) => Some(inputs),
+            Self::Completions(_) | Self::Selftest(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use super::{Cli, ValidationError};
+
+    #[test]
+    fn test_clap_arguments() {
+        use clap::CommandFactory;
+        Cli::command().debug_assert();
+    }
+
+    #[test]
+    fn clap_describes_the_flags_in_the_help() {
+        let help = Cli::try_parse_from(["", "pack", "--help"]).unwrap_err().to_string();
+        assert!(help.contains("Only counts the bytes of the inputs, and reports the estimated size of the outputs"));
+        assert!(help.contains("The amount of threads to use, all the cores by default"));
+    }
+
+    #[test]
+    fn clap_accepts_stdin_when_packing() {
+        let cli = Cli::try_parse_from(["", "pack", "-", "-o", "-"]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::Stdin][..]));
+    }
+
+    #[test]
+    fn clap_unpack_requires_output_when_using_stdin_as_input() {
+        let cli = Cli::try_parse_from(["", "unpack", "-"]).unwrap();
+        assert_eq!(cli.validate_output(&crate::io::Input::Stdin), Err(ValidationError::RequiresOutputWhenUsingStdin));
+    }
+
+    #[test]
+    fn clap_accepts_multiple_inputs() {
+        create_temp_files!("a" => a_path_str, "b" => b_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, &b_path_str]).unwrap();
+        let inputs = cli.validate_inputs().unwrap();
+        assert_eq!(inputs.len(), 2);
+        assert_eq!(cli.validate_output(&inputs[1]), Ok(crate::io::Output::File(temp_dir.path().join("b.hc"))));
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, &b_path_str, "-o", "c"]).unwrap();
+        assert_eq!(cli.validate_inputs(), Err(ValidationError::OutputWithMultipleInputs));
+
+        let cli = Cli::try_parse_from(["", "pack", "-", &a_path_str, "-"]).unwrap();
+        assert_eq!(cli.validate_inputs(), Err(ValidationError::StdinUsedMultipleTimes));
+    }
+
+    #[test]
+    fn clap_stdout_flag_and_cat_force_stdout() {
+        create_temp_files!("a.hc" => a_path_str in temp_dir);
+        let input = crate::io::Input::File(PathBuf::from(&a_path_str));
+
+        let cli = Cli::try_parse_from(["", "unpack", "-c", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&input), Ok(crate::io::Output::Stdout));
+
+        let cli = Cli::try_parse_from(["", "cat", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&input), Ok(crate::io::Output::Stdout));
+
+        assert!(Cli::try_parse_from(["", "pack", "-c", &a_path_str, "-o", "b"]).is_err());
+    }
+
+    #[test]
+    fn clap_keep_and_rm_conflict() {
+        let cli = Cli::try_parse_from(["", "pack", "-", "--rm"]).unwrap();
+        assert!(cli.removes_inputs());
+
+        let cli = Cli::try_parse_from(["", "pack", "-"]).unwrap();
+        assert!(!cli.removes_inputs());
+
+        assert!(Cli::try_parse_from(["", "pack", "-", "--rm", "--keep"]).is_err());
+    }
+
+    #[test]
+    fn clap_verbosity_flags() {
+        use ::log::LevelFilter;
+
+        let level = |args: &[&str]| Cli::try_parse_from(["", "pack", "-"].iter().chain(args)).unwrap().log_level();
+        assert_eq!(level(&[]), None);
+        assert_eq!(level(&["-q"]), Some(LevelFilter::Error));
+        assert_eq!(level(&["-v"]), Some(LevelFilter::Debug));
+        assert_eq!(level(&["-vv"]), Some(LevelFilter::Trace));
+
+        assert!(Cli::try_parse_from(["", "pack", "-", "-q", "-v"]).is_err());
+    }
+
+    #[test]
+    fn exit_codes_follow_the_error_kind() {
+        use std::io;
+
+        use super::{Exit, InputsFailed};
+
+        let exit_of = |kind: io::ErrorKind| Exit::of(&anyhow::Error::from(io::Error::from(kind)).context("context"));
+        assert_eq!(exit_of(io::ErrorKind::NotFound), Exit::InputNotFound);
+        assert_eq!(exit_of(io::ErrorKind::AlreadyExists), Exit::OutputExists);
+        assert_eq!(exit_of(io::ErrorKind::UnexpectedEof), Exit::CorruptArchive);
+        assert_eq!(exit_of(io::ErrorKind::PermissionDenied), Exit::Io);
+
+        assert_eq!(Exit::of(&ValidationError::StdinUsedMultipleTimes.into()), Exit::Usage);
+        assert_eq!(Exit::of(&anyhow::anyhow!("other")), Exit::Failure);
+        assert_eq!(
+            Exit::of(&InputsFailed { failed: 1, total: 2, exit: Exit::OutputExists }.into()),
+            Exit::OutputExists
+        );
+    }
+
+    #[test]
+    fn clap_threads() {
+        let cli = Cli::try_parse_from(["", "pack", "-", "-T", "3"]).unwrap();
+        assert_eq!(cli.threads(), 3);
+
+        let cli = Cli::try_parse_from(["", "pack", "-"]).unwrap();
+        assert!(cli.threads() >= 1);
+
+        assert!(Cli::try_parse_from(["", "pack", "-", "--threads", "0"]).is_err());
+    }
+
+    #[test]
+    fn sizes_are_parsed_with_their_suffix() {
+        use super::parse_size;
+
+        assert_eq!(parse_size("512"), Ok(512));
+        assert_eq!(parse_size("256K"), Ok(256 << 10));
+        assert_eq!(parse_size("4MiB"), Ok(4 << 20));
+        assert_eq!(parse_size("1 gb"), Ok(1 << 30));
+        assert_eq!(parse_size("2T"), Ok(2 << 40));
+
+        assert!(parse_size("").is_err());
+        assert!(parse_size("K").is_err());
+        assert!(parse_size("12Q").is_err());
+        assert!(parse_size("99999999999T").is_err());
+    }
+
+    #[test]
+    fn completions_are_generated() {
+        use super::Command;
+
+        for shell in ["bash", "fish", "zsh", "powershell"] {
+            assert!(matches!(Cli::try_parse_from(["", "completions", shell]).unwrap().command, Command::Completions(_)));
+        }
+        assert!(Cli::try_parse_from(["", "completions", "cmd"]).is_err());
+    }
+
+    #[test]
+    fn clap_commands_without_inputs_take_the_global_options() {
+        use super::{command_first, Command};
+
+        let args = command_first(["", "-v", "selftest", "a"].into_iter().map(std::ffi::OsString::from));
+        let cli = Cli::try_parse_from(args).unwrap();
+        assert!(matches!(&cli.command, Command::Selftest(selftest) if selftest.file == Some(PathBuf::from("a"))));
+        assert_eq!(cli.validate_inputs(), Ok(&[][..]));
+    }
+
+    #[test]
+    fn clap_accept_paths_correctly() {
+        create_temp_files!("a" => a_path_str, "b" => b_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "unpack", &a_path_str, "-o", &b_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(PathBuf::from(&b_path_str))));
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, "-o", &b_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(PathBuf::from(&b_path_str))));
+    }
+
+    #[test]
+    fn test_unpack_output_path_is_deduced_correctly_from_input_path_when_not_provided() {
+        create_temp_files!("a.hc" => a_path_str, "a" => a2_path_str, "a.extension" => a_with_extension_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "unpack", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", &a2_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a2_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.unpacked"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", &a_with_extension_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a_with_extension_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join(a_with_extension_path_str + ".unpacked"))));
+    }
+
+    #[test]
+    fn testpack_output_path_is_deduced_correctly_from_input_path_when_not_provided() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_inputs(), Ok(&[crate::io::Input::File(PathBuf::from(&a_path_str))][..]));
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.hc"))));
+    }
+
+    #[test]
+    fn unpack_output_path_is_deduced_from_a_custom_suffix() {
+        create_temp_files!("a.huf" => a_path_str, "a.hc" => a2_path_str, "a.tar.hc" => a_tar_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "unpack", "-S", "huf", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", "--suffix", ".huf", &a2_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.hc.unpacked"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", "-S", "tar.hc", &a_tar_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a"))));
+
+        assert!(Cli::try_parse_from(["", "unpack", "-S", ".", &a_path_str]).is_err());
+    }
+
+    #[test]
+    fn pack_output_path_is_deduced_from_a_custom_suffix() {
+        create_temp_files!("a" => a_path_str, "a.txt" => a_txt_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "pack", "-S", "huf", &a_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.huf"))));
+
+        let cli = Cli::try_parse_from(["", "pack", "--suffix", ".huf", &a_txt_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.txt.huf"))));
+    }
+
+    #[test]
+    fn output_directory_receives_the_deduced_file_names() {
+        create_temp_files!("a" => a_path_str, "b.hc" => b_path_str in temp_dir);
+        let out_dir = temp_dir.path().join("out");
+        std::fs::create_dir(&out_dir).unwrap();
+        let out_dir_str = out_dir.display().to_string();
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, &b_path_str, "-o", &out_dir_str]).unwrap();
+        let inputs = cli.validate_inputs().unwrap();
+        assert_eq!(cli.validate_output(&inputs[0]), Ok(crate::io::Output::File(out_dir.join("a.hc"))));
+        assert_eq!(cli.validate_output(&inputs[1]), Ok(crate::io::Output::File(out_dir.join("b.hc.hc"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", &b_path_str, "-o", &out_dir_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(out_dir.join("b"))));
+
+        let cli = Cli::try_parse_from(["", "pack", "-", "-o", &out_dir_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Err(ValidationError::OutputDirectoryWithStdin));
+    }
+
+    #[test]
+    fn packed_files_are_recognized_by_their_suffix() {
+        create_temp_files!("a.hc" => a_path_str, "b" => b_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, &b_path_str, "-"]).unwrap();
+        let inputs = cli.validate_inputs().unwrap();
+        assert!(cli.has_suffix(&inputs[0]));
+        assert!(!cli.has_suffix(&inputs[1]));
+        assert!(!cli.has_suffix(&inputs[2]));
+    }
+
+    #[test]
+    fn inputs_are_read_from_a_list() {
+        create_temp_files!("a" => a_path_str, "b\nc" => bc_path_str, "list" => list_path_str in temp_dir);
+
+        std::fs::write(&list_path_str, format!("{}\n\n{}\n", a_path_str, a_path_str)).unwrap();
+        let mut cli = Cli::try_parse_from(["", "pack", "--files-from", &list_path_str]).unwrap();
+        cli.load_files_from().unwrap();
+        assert_eq!(cli.validate_inputs().unwrap().len(), 2);
+
+        std::fs::write(&list_path_str, format!("{}\0{}\0", a_path_str, bc_path_str)).unwrap();
+        let mut cli = Cli::try_parse_from(["", "pack", &a_path_str, "--files-from", &list_path_str, "-0"]).unwrap();
+        cli.load_files_from().unwrap();
+        assert_eq!(
+            cli.validate_inputs(),
+            Ok(&[
+                crate::io::Input::File(PathBuf::from(&a_path_str)),
+                crate::io::Input::File(PathBuf::from(&a_path_str)),
+                crate::io::Input::File(PathBuf::from(&bc_path_str)),
+            ][..])
+        );
+
+        std::fs::write(&list_path_str, temp_dir.path().display().to_string()).unwrap();
+        let mut cli = Cli::try_parse_from(["", "pack", "--files-from", &list_path_str]).unwrap();
+        assert!(cli.load_files_from().is_err());
+
+        assert!(Cli::try_parse_from(["", "pack"]).is_err());
+        assert!(Cli::try_parse_from(["", "pack", "-0", &a_path_str]).is_err());
+    }
+
+    #[test]
+    fn config_gives_the_defaults_of_the_flags() {
+        use super::Config;
+
+        let config = Config::parse("checksum = \"xxh64\"\nthreads = 2\nsuffix = \".huf\"\nrm = true\n").unwrap();
+
+        let mut cli = Cli::try_parse_from(["", "pack", "a", "--threads", "5"]).unwrap();
+        cli.apply_config(config);
+        assert_eq!(cli.checksum(), huffman_format::ChecksumKind::Xxh64);
+        assert_eq!(cli.threads(), 5);
+        assert!(cli.removes_inputs());
+        assert_eq!(cli.validate_output(&crate::io::Input::File(PathBuf::from("a"))), Ok(crate::io::Output::File(PathBuf::from("a.huf"))));
+
+        let mut cli = Cli::try_parse_from(["", "pack", "a", "--keep", "-S", "x"]).unwrap();
+        cli.apply_config(Config::parse("rm = true\nsuffix = \"huf\"").unwrap());
+        assert!(!cli.removes_inputs());
+        assert_eq!(cli.validate_output(&crate::io::Input::File(PathBuf::from("a"))), Ok(crate::io::Output::File(PathBuf::from("a.x"))));
+
+        let mut cli = Cli::try_parse_from(["", "unpack", "a.hc"]).unwrap();
+        cli.apply_config(Config::parse("keep = true").unwrap());
+        assert!(!cli.removes_inputs());
+
+        let mut cli = Cli::try_parse_from(["", "unpack", "a.hc", "--rm"]).unwrap();
+        cli.apply_config(Config::parse("keep = true").unwrap());
+        assert!(cli.removes_inputs());
+
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("rm = true\nkeep = true").is_err());
+        assert!(Config::parse("level = 3").is_err());
+        assert!(Config::parse("threads = 0").is_err());
+        assert!(Config::parse("suffix = \"\"").is_err());
+        assert!(Config::parse("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn clap_checksum_kinds() {
+        use huffman_format::ChecksumKind;
+
+        let checksum = |args: &[&str]| Cli::try_parse_from(args).map(|cli| cli.checksum());
+
+        assert_eq!(checksum(&["", "pack", "a"]).unwrap(), ChecksumKind::None);
+        assert_eq!(checksum(&["", "pack", "a", "--checksum"]).unwrap(), ChecksumKind::Crc32);
+        assert_eq!(checksum(&["", "pack", "a", "--checksum=xxh64"]).unwrap(), ChecksumKind::Xxh64);
+        assert_eq!(checksum(&["", "pack", "a", "--checksum=none"]).unwrap(), ChecksumKind::None);
+        assert!(checksum(&["", "pack", "a", "--checksum=md5"]).is_err());
+
+        let mut cli = Cli::try_parse_from(["", "pack", "a"]).unwrap();
+        cli.apply_config(super::Config::parse("checksum = \"xxh64\"").unwrap());
+        assert_eq!(cli.checksum(), ChecksumKind::Xxh64);
+
+        let mut cli = Cli::try_parse_from(["", "pack", "a", "--checksum=none"]).unwrap();
+        cli.apply_config(super::Config::parse("checksum = \"xxh64\"").unwrap());
+        assert_eq!(cli.checksum(), ChecksumKind::None);
+    }
+
+    #[test]
+    fn tar_archives_keep_their_extension() {
+        create_temp_files!("a.tar" => tar_path_str, "b.tar.hc" => packed_path_str in temp_dir);
+
+        let cli = Cli::try_parse_from(["", "pack", &tar_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("a.tar.hc"))));
+
+        let cli = Cli::try_parse_from(["", "unpack", &packed_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(temp_dir.path().join("b.tar"))));
+    }
+
+    #[test]
+    fn clap_limits() {
+        let cli = Cli::try_parse_from(["", "unpack", "a.hc", "--max-size", "1M", "--no-verify"]).unwrap();
+        assert_eq!(cli.unpack_options(), huffman_format::UnpackOptions { verify: false, max_size: Some(1 << 20), member: None });
+        assert!(Cli::try_parse_from(["", "unpack", "a.hc", "--stdin-buffer", "4K"]).is_err());
+
+        let cli = Cli::try_parse_from(["", "pack", "-", "--stdin-buffer", "4K"]).unwrap();
+        assert_eq!(cli.stdin_buffer(), 4096);
+
+        let cli = Cli::try_parse_from(["", "unpack", "a.hc"]).unwrap();
+        assert_eq!(cli.unpack_options(), huffman_format::UnpackOptions::default());
+
+        let cli = Cli::try_parse_from(["", "unpack", "a.hc", "--member", "2"]).unwrap();
+        assert_eq!(cli.unpack_options().member, Some(1));
+        assert!(Cli::try_parse_from(["", "unpack", "a.hc", "--member", "0"]).is_err());
+    }
+
+    #[test]
+    fn sizes_are_printed_for_humans() {
+        let cli = Cli::try_parse_from(["", "info", "a.hc", "--human"]).unwrap();
+        assert_eq!(cli.format_size(1000), "1000 bytes");
+        assert_eq!(cli.format_size(1024), "1.0 KiB");
+        assert_eq!(cli.format_size(1_468_006), "1.4 MiB");
+        assert_eq!(cli.format_size(5 << 40), "5.0 TiB");
+        assert_eq!(cli.format_size(5 << 50), "5120.0 TiB");
+
+        let cli = Cli::try_parse_from(["", "info", "a.hc"]).unwrap();
+        assert_eq!(cli.format_size(1_468_006), "1468006 bytes");
+    }
+
+    #[test]
+    fn clap_colors() {
+        let colors = |args: &[&str]| Cli::try_parse_from(["", "pack", "-"].iter().chain(args)).unwrap().colors();
+        assert!(colors(&["--color=always"]));
+        assert!(!colors(&["--color", "never"]));
+
+        assert!(Cli::try_parse_from(["", "pack", "-", "--color=sometimes"]).is_err());
+    }
+
+    #[test]
+    fn version_lists_the_formats_and_the_features() {
+        let err = Cli::try_parse_from(["", "--version"]).unwrap_err();
+        assert_eq!(err.kind(), clap::error::ErrorKind::DisplayVersion);
+
+        let version = err.to_string();
+        assert!(version.contains(huffman_format::VERSION));
+        assert!(version.contains("formats : "));
+        assert!(version.contains("checksums"));
+    }
+
+    #[test]
+    fn env_options_come_before_the_command_line() {
+        let args = |args: &[&str], env_options: Option<&str>| {
+            let args = super::with_env_options(super::command_first(args.iter().map(std::ffi::OsString::from)), env_options.map(std::ffi::OsString::from));
+            Cli::try_parse_from(args)
+        };
+
+        let cli = args(&["", "pack", "a"], Some(" --split-size 6  --checksum=xxh64 ")).unwrap();
+        assert_eq!(cli.pack_args().unwrap().split_size, Some(6));
+        assert_eq!(cli.checksum(), huffman_format::ChecksumKind::Xxh64);
+
+        let cli = args(&["", "pack", "a", "--split-size", "2"], Some("--split-size 6")).unwrap();
+        assert_eq!(cli.pack_args().unwrap().split_size, Some(2));
+
+        let cli = args(&["", "pack", "a"], None).unwrap();
+        assert_eq!(cli.pack_args().unwrap().split_size, None);
+
+        // the options of the other commands are ignored.
+        let cli = args(&["", "unpack", "a"], Some("--split-size 6 -T 2 --checksum=xxh64")).unwrap();
+        assert_eq!(cli.threads(), 2);
+        assert!(args(&["", "unpack", "a"], Some("--unknown")).is_err());
+    }
+
+    #[test]
+    fn options_belong_to_their_commands() {
+        assert!(Cli::try_parse_from(["", "unpack", "a", "--split-size", "3"]).is_err());
+        assert!(Cli::try_parse_from(["", "pack", "a", "--untar"]).is_err());
+        assert!(Cli::try_parse_from(["", "list", "a", "--dry-run"]).is_err());
+        assert!(Cli::try_parse_from(["", "info", "a", "--member", "1"]).is_err());
+
+        assert!(Cli::try_parse_from(["", "info", "a", "-W"]).is_err());
+        assert!(Cli::try_parse_from(["", "test", "a", "--rm"]).is_err());
+        assert!(Cli::try_parse_from(["", "list", "a", "-S", "huf"]).is_err());
+        assert!(Cli::try_parse_from(["", "concat", "a", "-o", "b", "--keep-partial"]).is_err());
+
+        // the options of the command can also come before it.
+        let cli = Cli::try_parse_from(super::command_first(["", "-W", "unpack", "a"].into_iter().map(std::ffi::OsString::from))).unwrap();
+        assert!(cli.overwrite());
+    }
+
+    #[test]
+    fn the_command_can_come_after_the_options() {
+        let command_first = |args: &[&str]| super::command_first(args.iter().map(std::ffi::OsString::from));
+
+        let args = command_first(&["", "--split-size", "3", "-o", "pack", "pack", "a"]);
+        assert_eq!(args, ["", "pack", "--split-size", "3", "-o", "pack", "a"]);
+        assert_eq!(Cli::try_parse_from(args).unwrap().pack_args().unwrap().split_size, Some(3));
+
+        assert_eq!(command_first(&["", "-qo", "out", "unpack", "a"]), ["", "unpack", "-qo", "out", "a"]);
+        assert_eq!(command_first(&["", "-qoout", "unpack", "a"]), ["", "unpack", "-qoout", "a"]);
+        assert_eq!(command_first(&["", "--checksum", "pack", "a"]), ["", "pack", "--checksum", "a"]);
+        assert_eq!(command_first(&["", "pack", "unpack"]), ["", "pack", "unpack"]);
+        assert_eq!(command_first(&["", "-v", "--", "pack"]), ["", "-v", "--", "pack"]);
+    }
+
+    #[test]
+    fn debug_dump_is_hidden() {
+        use clap::CommandFactory;
+
+        assert!(!Cli::command().render_help().to_string().contains("debug-dump"));
+        assert!(Cli::try_parse_from(["", "debug-dump", "a.hc", "--codewords", "3"]).is_ok());
+        assert!(Cli::try_parse_from(["", "pack", "a", "--codewords", "3"]).is_err());
+    }
+}
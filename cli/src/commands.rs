@@ -0,0 +1,1669 @@
+use std::{collections::BTreeMap, io::{self, BufReader, Cursor, IsTerminal, Read, Seek, Write}, process::Stdio, sync::{atomic::{AtomicBool, AtomicUsize, Ordering}, mpsc}, time::{Duration, Instant}};
+
+use anyhow::Context;
+use huffman_format::{ByteTable, HeapNode, HeaderInfo, MemberInfo, PackOptions};
+use ::log::{debug, error, info, warn};
+
+use crate::{args::{self, Cli, Exit, Format, InputsFailed, ValidationError}, io::{volume_path, CompareWrite, CountingRead, CountingWrite, Input, InputRead, Output, OutputWrite, StdoutClosed}, log, progress};
+
+struct FileStats {
+    output: Option<Output>,
+    input_size: u64,
+    output_size: u64,
+    // Only known when packing.
+    entropy: Option<f32>,
+}
+
+impl FileStats {
+    // Compares the bits per byte of the packed data with the entropy, which is the best a code based
+    // on byte frequencies can reach.
+    fn entropy_comparison(&self) -> Option<String> {
+        let entropy = self.entropy? as f64;
+        if self.input_size == 0 {
+            return None
+        }
+
+        let bits_per_byte = self.output_size as f64 * 8.0 / self.input_size as f64;
+        Some(format!(
+            "{:.2} bpb vs {:.2} bpb theoretical, {:.0} % efficient",
+            bits_per_byte, entropy, entropy / bits_per_byte * 100.0
+        ))
+    }
+}
+
+struct InfoStats {
+    header_info: Option<HeaderInfo>,
+    compressed_size: u64,
+}
+
+struct GzipStats {
+    output_size: u64,
+    duration: Duration,
+}
+
+struct BenchStats {
+    input_size: u64,
+    output_size: u64,
+    entropy: f32,
+    pack_duration: Duration,
+    unpack_duration: Duration,
+    // `None` when gzip isn't installed.
+    gzip: Option<GzipStats>,
+}
+
+impl BenchStats {
+    // The smallest payload a code based on byte frequencies can reach.
+    fn entropy_bound(&self) -> u64 {
+        (self.entropy as f64 * self.input_size as f64 / 8.0).ceil() as u64
+    }
+}
+
+struct ByteStats {
+    byte_table: Box<ByteTable>,
+    entropy: f32,
+    estimated_size: u64,
+}
+
+impl ByteStats {
+    fn size(&self) -> u64 {
+        self.byte_table.iter().sum()
+    }
+
+    fn distinct_symbols(&self) -> usize {
+        self.byte_table.iter().filter(|count| **count != 0).count()
+    }
+}
+
+enum Outcome {
+    Info(InfoStats),
+    Processed(FileStats),
+    Bench(BenchStats),
+    Stats(ByteStats),
+    // `None` for an empty file.
+    Tree(Option<HeapNode>),
+    List(Vec<MemberInfo>),
+    Dump(String),
+    // The input is already compressed, or is a symbolic link.
+    Skipped,
+}
+
+#[derive(serde::Serialize)]
+struct HeaderReport {
+    distinct_symbols: usize,
+    max_code_length: usize,
+    header_bits: u64,
+    checksum: Option<String>,
+}
+
+// The durations are in seconds.
+#[derive(serde::Serialize)]
+struct BenchReport {
+    pack_duration: f64,
+    unpack_duration: f64,
+    entropy_bound: u64,
+    gzip_size: Option<u64>,
+    gzip_duration: Option<f64>,
+}
+
+#[derive(serde::Serialize)]
+struct MemberReport {
+    original_size: u64,
+    packed_size: u64,
+    checksum: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct StatsReport {
+    distinct_symbols: usize,
+    // The count of every byte value.
+    histogram: Vec<u64>,
+}
+
+// The result object printed for every file with `--format json`.
+#[derive(serde::Serialize)]
+struct Report {
+    input: String,
+    output: Option<String>,
+    input_size: Option<u64>,
+    output_size: Option<u64>,
+    // The packed size over the original size.
+    ratio: Option<f64>,
+    entropy: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    header: Option<HeaderReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bench: Option<BenchReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    stats: Option<StatsReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    members: Option<Vec<MemberReport>>,
+    // The input is already compressed or is a symbolic link, the reason is in the warnings.
+    skipped: bool,
+    // In seconds.
+    duration: f64,
+    // With `--time`, in seconds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cpu_time: Option<f64>,
+    warnings: Vec<String>,
+    error: Option<String>,
+}
+
+impl Report {
+    fn new(cli: &Cli, input: &Input, res: &anyhow::Result<Outcome>, times: Times, warnings: Vec<String>) -> Self {
+        let mut report = Report {
+            input: input.to_string(),
+            output: None,
+            input_size: None,
+            output_size: None,
+            ratio: None,
+            entropy: None,
+            header: None,
+            bench: None,
+            stats: None,
+            members: None,
+            skipped: matches!(res, Ok(Outcome::Skipped)),
+            duration: times.wall.as_secs_f64(),
+            cpu_time: times.cpu.filter(|_| cli.time).map(|cpu| cpu.as_secs_f64()),
+            warnings,
+            error: None,
+        };
+
+        let ratio = |packed: u64, original: u64| (original != 0).then(|| packed as f64 / original as f64);
+
+        match res {
+            Ok(Outcome::Info(info)) => {
+                report.input_size = Some(info.compressed_size);
+                if let Some(header_info) = &info.header_info {
+                    report.output_size = Some(header_info.original_size);
+                    report.ratio = ratio(info.compressed_size, header_info.original_size);
+                    report.header = Some(HeaderReport {
+                        distinct_symbols: header_info.distinct_symbols,
+                        max_code_length: header_info.max_code_length,
+                        header_bits: header_info.header_bits,
+                        checksum: header_info.checksum.map(|checksum| checksum.to_string()),
+                    });
+                }
+            }
+            Ok(Outcome::Processed(stats)) => {
+                report.output = stats.output.as_ref().map(Output::to_string);
+                report.input_size = Some(stats.input_size);
+                report.output_size = Some(stats.output_size);
+                report.ratio = match cli.command {
+                    args::Command::Pack(_) => ratio(stats.output_size, stats.input_size),
+                    _ => ratio(stats.input_size, stats.output_size),
+                };
+                report.entropy = stats.entropy;
+            }
+            Ok(Outcome::Bench(stats)) => {
+                report.input_size = Some(stats.input_size);
+                report.output_size = Some(stats.output_size);
+                report.ratio = ratio(stats.output_size, stats.input_size);
+                report.entropy = Some(stats.entropy);
+                report.bench = Some(BenchReport {
+                    pack_duration: stats.pack_duration.as_secs_f64(),
+                    unpack_duration: stats.unpack_duration.as_secs_f64(),
+                    entropy_bound: stats.entropy_bound(),
+                    gzip_size: stats.gzip.as_ref().map(|gzip| gzip.output_size),
+                    gzip_duration: stats.gzip.as_ref().map(|gzip| gzip.duration.as_secs_f64()),
+                });
+            }
+            Ok(Outcome::Stats(stats)) => {
+                report.input_size = Some(stats.size());
+                report.output_size = Some(stats.estimated_size);
+                report.ratio = ratio(stats.estimated_size, stats.size());
+                report.entropy = Some(stats.entropy);
+                report.stats = Some(StatsReport {
+                    distinct_symbols: stats.distinct_symbols(),
+                    histogram: stats.byte_table.to_vec(),
+                });
+            }
+            Ok(Outcome::List(members)) => {
+                let packed_size = members.iter().map(|member| member.packed_size).sum();
+                let original_size = members.iter().map(|member| member.original_size).sum();
+                report.input_size = Some(packed_size);
+                report.output_size = Some(original_size);
+                report.ratio = ratio(packed_size, original_size);
+                report.members = Some(members.iter()
+                    .map(|member| MemberReport {
+                        original_size: member.original_size,
+                        packed_size: member.packed_size,
+                        checksum: member.checksum.map(|checksum| checksum.to_string()),
+                    })
+                    .collect());
+            }
+            Ok(Outcome::Tree(_) | Outcome::Dump(_) | Outcome::Skipped) => {}
+            Err(err) => report.error = Some(format!("{:#}", err)),
+        }
+
+        report
+    }
+}
+
+// The report goes to stderr when stdout is used for the data.
+fn print_report(out: &mut impl Write, cli: &Cli, report: &Report) -> io::Result<()> {
+    let json = serde_json::to_string(report).unwrap();
+
+    if cli.writes_to_stdout() {
+        eprintln!("{}", json);
+        Ok(())
+    } else {
+        writeln!(out, "{}", json)
+    }
+}
+
+#[derive(Clone, Copy)]
+struct Times {
+    wall: Duration,
+    // `None` when the platform doesn't report it.
+    cpu: Option<Duration>,
+}
+
+// The user and system time the current thread used so far, or the process where the time of a
+// thread isn't reported.
+#[cfg(unix)]
+fn cpu_time() -> Option<Duration> {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    const WHO: libc::c_int = libc::RUSAGE_THREAD;
+    #[cfg(not(any(target_os = "linux", target_os = "android")))]
+    const WHO: libc::c_int = libc::RUSAGE_SELF;
+
+    let mut usage = std::mem::MaybeUninit::<libc::rusage>::uninit();
+    // SAFETY: `getrusage` fills the struct when it succeeds.
+    let usage = unsafe {
+        if libc::getrusage(WHO, usage.as_mut_ptr()) != 0 {
+            return None
+        }
+        usage.assume_init()
+    };
+
+    let to_duration = |time: libc::timeval| Duration::new(time.tv_sec as u64, time.tv_usec as u32 * 1000);
+    Some(to_duration(usage.ru_utime) + to_duration(usage.ru_stime))
+}
+
+#[cfg(not(unix))]
+fn cpu_time() -> Option<Duration> {
+    None
+}
+
+// The `--time` line, the throughput is of the unpacked data.
+fn print_time(out: &mut impl Write, cli: &Cli, input: &Input, stats: &FileStats, times: Times) -> io::Result<()> {
+    let (original_size, packed_size) = match cli.command {
+        args::Command::Pack(_) => (stats.input_size, stats.output_size),
+        _ => (stats.output_size, stats.input_size),
+    };
+    let throughput = original_size as f64 / times.wall.as_secs_f64() / 1_000_000.0;
+    let ratio = if original_size == 0 { 0.0 } else { packed_size as f64 / original_size as f64 * 100.0 };
+    let cpu = times.cpu.map_or("?".to_string(), |cpu| format!("{:.2?}", cpu));
+
+    let line = match &stats.output {
+        Some(output) => format!("`{}` -> `{}`", input, output),
+        None => format!("`{}`", input),
+    };
+    let line = format!(
+        "{} : {} -> {} ({:.2} %), {:.2?} wall, {} CPU, {:.2} MB/s",
+        line, cli.format_size(stats.input_size), cli.format_size(stats.output_size), ratio, times.wall, cpu, throughput
+    );
+    let line = match stats.entropy_comparison() {
+        Some(comparison) => format!("{}, {}", line, comparison),
+        None => line,
+    };
+
+    if cli.writes_to_stdout() {
+        eprintln!("{}", line);
+        Ok(())
+    } else {
+        writeln!(out, "{}", line)
+    }
+}
+
+// The signatures of the formats which are already compressed, packing them would grow them.
+const COMPRESSED_SIGNATURES: [(&str, &[u8]); 8] = [
+    ("gzip", &[0x1f, 0x8b]),
+    ("zip", &[0x50, 0x4b, 0x03, 0x04]),
+    ("png", &[0x89, 0x50, 0x4e, 0x47, 0x0d, 0x0a, 0x1a, 0x0a]),
+    ("jpeg", &[0xff, 0xd8, 0xff]),
+    ("xz", &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+    ("zstd", &[0x28, 0xb5, 0x2f, 0xfd]),
+    ("bzip2", b"BZh"),
+    ("7z", &[0x37, 0x7a, 0xbc, 0xaf, 0x27, 0x1c]),
+];
+
+// Returns the name of the compressed format the data starts with, and rewinds the reader.
+fn detect_compressed_format<R: Read + Seek>(reader: &mut R) -> io::Result<Option<&'static str>> {
+    let mut header = Vec::new();
+    reader.by_ref().take(8).read_to_end(&mut header)?;
+    reader.rewind()?;
+
+    Ok(COMPRESSED_SIGNATURES.iter()
+        .find(|(_, signature)| header.starts_with(signature))
+        .map(|(name, _)| *name))
+}
+
+// Files from that size are counted on several threads when packing.
+const PARALLEL_COUNTING_MIN_LEN: u64 = 64 << 20;
+
+fn process_input(cli: &Cli, input: &Input, threads: usize) -> anyhow::Result<Outcome> {
+    let output = cli.validate_output(input)?;
+
+    if cli.skip_existing() {
+        let existing = match (&output, cli.split_size()) {
+            (Output::File(path), Some(_)) => Some(volume_path(path, 0)),
+            (Output::File(path), None) => Some(path.clone()),
+            (Output::Stdout, _) => None,
+        };
+        if let Some(existing) = existing.filter(|path| path.exists()) {
+            debug!("`{}` already exists, skipping `{}`.", existing.display(), input);
+            return Ok(Outcome::Skipped)
+        }
+    }
+
+    // the format has no signature, so packed files are recognized by their suffix.
+    if matches!(cli.command, args::Command::Pack(_)) && cli.has_suffix(input) && !cli.force {
+        warn!("`{}` is already packed, skipping it. Use `--force` to pack it anyway.", input);
+        return Ok(Outcome::Skipped)
+    }
+    
+    // the format has no members, so a link can't be stored as such. The links to special files,
+    // like `/dev/fd/63`, are followed.
+    if let (Input::File(path), false, false) = (input, cli.dereference(), matches!(cli.command, args::Command::Cat(_))) {
+        if path.symlink_metadata().is_ok_and(|metadata| metadata.is_symlink()) && !input.is_special() {
+            warn!("`{}` is a symbolic link, skipping it. Use `--dereference` to process its target.", input);
+            return Ok(Outcome::Skipped)
+        }
+    }
+
+    info!("Opening `{}`...", input);
+
+    let input_read = match cli.command {
+        // the signature of compressed formats is read first, so even the dry run must rewind.
+        args::Command::Pack(_) => input.open_seekable(cli.force, cli.stdin_buffer()),
+        args::Command::Unpack(_) | args::Command::Cat(_) => input.open_packed(cli.force),
+        args::Command::Info(_) | args::Command::Test(_) | args::Command::Bench(_) | args::Command::Stats(_) | args::Command::Concat(_) | args::Command::Tree(_) | args::Command::List(_) | args::Command::DebugDump(_) => input.open(cli.force),
+        args::Command::Completions(_) | args::Command::Selftest(_) => unreachable!(),
+    };
+    let mut input_read = input_read.with_context(|| "Failed to open the input file")?;
+
+    if matches!(cli.command, args::Command::Pack(_)) && !cli.force {
+        if let Some(format) = detect_compressed_format(&mut input_read).with_context(|| "Failed to read the input file")? {
+            warn!("`{}` is already compressed with {}, skipping it. Use `--force` to pack it anyway.", input, format);
+            return Ok(Outcome::Skipped)
+        }
+    }
+
+    if cli.dry_run() {
+        let byte_table = huffman_format::get_byte_table(&mut BufReader::new(input_read))
+            .with_context(|| "Failed to read the input file")?;
+        let output_size = match huffman_format::estimate_packed_size(byte_table) {
+            // nothing is written for an empty input, not even the checksum.
+            0 => 0,
+            size => size + cli.checksum().added_len(),
+        };
+        let stats = FileStats {
+            output: Some(output),
+            input_size: byte_table.iter().sum(),
+            output_size,
+            entropy: Some(huffman_format::compute_entropy(byte_table)),
+        };
+        if stats.output_size > stats.input_size && !cli.pack_args().is_some_and(|pack| pack.force_expand) {
+            warn!(
+                "`{}` would get bigger when packed ({} -> {}), it would be skipped. Use `--force-expand` to pack it anyway.",
+                input, cli.format_size(stats.input_size), cli.format_size(stats.output_size)
+            );
+            return Ok(Outcome::Skipped)
+        }
+
+        let ratio = if stats.input_size == 0 { 0.0 } else { stats.output_size as f64 / stats.input_size as f64 * 100.0 };
+        info!(
+            "`{}` -> `{}` : {} -> {} ({:.2} %), estimated",
+            input, stats.output.as_ref().unwrap(), cli.format_size(stats.input_size), cli.format_size(stats.output_size), ratio
+        );
+        if let Some(comparison) = stats.entropy_comparison() {
+            info!("`{}` : {}", input, comparison);
+        }
+
+        return Ok(Outcome::Processed(stats))
+    }
+    
+    info!("Writing to `{}`...", output);
+    let output_write = match cli.split_size() {
+        Some(split_size) => output.open_volumes(cli.overwrite(), split_size),
+        None => output.open(cli.overwrite()),
+    };
+    let mut output_write = output_write.with_context(|| "Failed to create the output file")?;
+
+    let packing = matches!(cli.command, args::Command::Pack(_));
+    // the counting pass of big files is split between the threads, the data is then read once.
+    let byte_table = match (input, &input_read) {
+        (Input::File(path), input_read) if input_read.is_file() && packing && threads > 1 && input.size(false).is_some_and(|size| size >= PARALLEL_COUNTING_MIN_LEN) => {
+            debug!("Counting the bytes of `{}` on {} threads...", input, threads);
+            Some(huffman_format::get_file_byte_table(path, threads).with_context(|| "Failed to read the input file")?)
+        }
+        _ => None,
+    };
+    let passes = if packing && byte_table.is_none() { 2 } else { 1 };
+    let progress = progress::start(input, input.size(!packing), passes);
+    let res = match cli.command {
+        args::Command::Info(_) | args::Command::Test(_) | args::Command::Bench(_) | args::Command::Stats(_) | args::Command::Concat(_) | args::Command::Tree(_) | args::Command::List(_) | args::Command::DebugDump(_) | args::Command::Completions(_) | args::Command::Selftest(_) => unreachable!(),
+        args::Command::Pack(_) => {
+            let options = PackOptions {
+                checksum: cli.checksum(),
+                refuse_expansion: !cli.pack_args().is_some_and(|pack| pack.force_expand),
+                byte_table,
+            };
+            let output_write = progress::write(&mut output_write, &progress);
+            huffman_format::pack_file_with_options(progress::read(&mut input_read, &progress), output_write, &options)
+                .map(|stats| FileStats {
+                    output: Some(output.clone()),
+                    input_size: stats.input_size,
+                    output_size: stats.output_size,
+                    entropy: Some(stats.entropy),
+                })
+                .with_context(|| "Failed to pack the input file")
+        },
+        args::Command::Unpack(_) | args::Command::Cat(_) => {
+            let mut counting_read = CountingRead::new(progress::read(&mut input_read, &progress));
+            let options = cli.unpack_options();
+            huffman_format::unpack_file_with_options(&mut counting_read, progress::write(&mut output_write, &progress), &options)
+                .map(|output_size| FileStats { output: Some(output.clone()), input_size: counting_read.count, output_size, entropy: None })
+                .with_context(|| "Failed to unpack the data")
+        },
+    };
+    let stats = match res {
+        Ok(stats) => stats,
+        Err(err) => {
+            if let Some(expansion) = find_expansion(&err) {
+                warn!(
+                    "`{}` would get bigger when packed ({} -> {}), skipping it. Use `--force-expand` to pack it anyway.",
+                    input, cli.format_size(expansion.input_size), cli.format_size(expansion.packed_size)
+                );
+                return Ok(Outcome::Skipped)
+            }
+            if cli.keep_partial() {
+                match output_write.keep_partial() {
+                    Ok(paths) => paths.iter().for_each(|path| warn!("Kept the partial output in `{}`.", path.display())),
+                    Err(keep_err) => warn!("Failed to keep the partial output of `{}` : {}", input, keep_err),
+                }
+            }
+            return Err(err)
+        }
+    };
+    output_write.finish().with_context(|| "Failed to write the output file")?;
+    progress::finish(&progress);
+    info!("`{}` -> `{}` : {} -> {}", input, output, cli.format_size(stats.input_size), cli.format_size(stats.output_size));
+    if cli.pack_args().is_some_and(|pack| pack.verify_after_pack) {
+        verify_packed_output(cli, &output, &mut input_read)?;
+    }
+    if let Some(comparison) = stats.entropy_comparison() {
+        info!("`{}` : {}", input, comparison);
+    }
+
+    if cli.removes_inputs() {
+        match (input, &output) {
+            (Input::File(_), _) if input.is_special() => {}
+            (Input::File(path), Output::File(_)) => {
+                std::fs::remove_file(path).with_context(|| "Failed to remove the input file")?;
+                info!("Removed `{}`.", input);
+            }
+            (Input::Stdin, _) => {}
+            (_, Output::Stdout) => warn!("`{}` is kept, as the output went to stdout.", input),
+        }
+    }
+
+    Ok(Outcome::Processed(stats))
+}
+
+// A packed output that doesn't unpack to the input is removed, so it's not mistaken for a good one.
+fn verify_packed_output(cli: &Cli, output: &Output, input_read: &mut InputRead) -> anyhow::Result<()> {
+    let Output::File(path) = output else {
+        warn!("The output went to stdout, it can't be verified.");
+        return Ok(())
+    };
+    let packed = match cli.split_size() {
+        Some(_) => Input::File(volume_path(path, 0)),
+        None => Input::File(path.clone()),
+    };
+
+    info!("Verifying `{}`...", output);
+    input_read.rewind().with_context(|| "Failed to read the input file again")?;
+    let res = packed.open_packed(cli.force).and_then(|packed_read| {
+        let mut compare_write = CompareWrite::new(BufReader::new(&mut *input_read));
+        huffman_format::unpack_file(packed_read, &mut compare_write)?;
+        compare_write.finish()
+    });
+
+    if let Err(err) = res {
+        let files = match cli.split_size() {
+            Some(_) => (0..).map(|index| volume_path(path, index)).take_while(|path| path.exists()).collect(),
+            None => vec![path.clone()],
+        };
+        for file in files {
+            if let Err(err) = std::fs::remove_file(&file) {
+                warn!("Failed to remove `{}` : {}", file.display(), err);
+            }
+        }
+        return Err(err).with_context(|| format!("`{}` doesn't unpack to the input, it was removed", output))
+    }
+    info!("`{}` unpacks to the input.", output);
+
+    Ok(())
+}
+
+fn read_info(cli: &Cli, input: &Input) -> anyhow::Result<InfoStats> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+    let mut counting_read = CountingRead::new(input_read);
+
+    let header_info = huffman_format::read_header_info(&mut counting_read)
+        .with_context(|| "Failed to read the headers")?;
+    io::copy(&mut counting_read, &mut io::sink()).with_context(|| "Failed to read the input file")?;
+
+    Ok(InfoStats { header_info, compressed_size: counting_read.count })
+}
+
+fn print_info(out: &mut impl Write, cli: &Cli, input: &Input, info: &InfoStats) -> io::Result<()> {
+    let compressed_size = info.compressed_size;
+
+    writeln!(out, "{}", input)?;
+    let Some(header_info) = &info.header_info else {
+        writeln!(out, "  empty")?;
+        return Ok(())
+    };
+
+    writeln!(out, "  original size    : {}", cli.format_size(header_info.original_size))?;
+    writeln!(out, "  compressed size  : {}", cli.format_size(compressed_size))?;
+    if header_info.original_size != 0 {
+        writeln!(out, "  ratio            : {:.2} %", compressed_size as f64 / header_info.original_size as f64 * 100.0)?;
+    }
+    writeln!(out, "  header size      : {} bits", header_info.header_bits)?;
+    writeln!(out, "  distinct symbols : {}", header_info.distinct_symbols)?;
+    writeln!(out, "  max code length  : {} bits", header_info.max_code_length)?;
+    match header_info.checksum {
+        Some(checksum) => writeln!(out, "  checksum         : {}", checksum)?,
+        None => writeln!(out, "  checksum         : none")?,
+    }
+
+    Ok(())
+}
+
+fn test_input(cli: &Cli, input: &Input) -> anyhow::Result<FileStats> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+    let progress = progress::start(input, input.size(true), 1);
+    let mut counting_read = CountingRead::new(progress::read(input_read, &progress));
+    let mut counting_write = CountingWrite::new(progress::write(io::sink(), &progress));
+
+    let options = cli.unpack_options();
+    if let Err(err) = huffman_format::unpack_file_with_options(&mut counting_read, &mut counting_write, &options) {
+        // the reads are buffered, so the input offset is only approximate.
+        return Err(err).with_context(|| format!(
+            "The data is corrupted after {} decoded bytes, around byte {} of the input",
+            counting_write.count, counting_read.count
+        ))
+    }
+
+    progress::finish(&progress);
+    info!("`{}` : OK", input);
+
+    Ok(FileStats { output: None, input_size: counting_read.count, output_size: counting_write.count, entropy: None })
+}
+
+fn untar_input(cli: &Cli, input: &Input) -> anyhow::Result<FileStats> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+    let mut counting_read = CountingRead::new(input_read);
+
+    let mut command = std::process::Command::new("tar");
+    command.args(["-x", "-f", "-"]).stdin(Stdio::piped());
+    if let Some(directory) = cli.untar_directory() {
+        command.arg("-C").arg(directory);
+    }
+    let mut child = command.spawn().with_context(|| "Failed to run tar")?;
+
+    let mut counting_write = CountingWrite::new(child.stdin.take().unwrap());
+    let options = cli.unpack_options();
+    let res = huffman_format::unpack_file_with_options(&mut counting_read, &mut counting_write, &options);
+    // closes the pipe, so tar sees the end of the archive.
+    let output_size = counting_write.count;
+    drop(counting_write);
+
+    // when tar fails, the data is written to a closed pipe, its own error is the one to report.
+    let status = child.wait().with_context(|| "Failed to run tar")?;
+    if !status.success() {
+        anyhow::bail!("tar failed with {}", status)
+    }
+    res.with_context(|| "Failed to unpack the data")?;
+
+    info!("`{}` : extracted {}", input, cli.format_size(output_size));
+
+    Ok(FileStats { output: None, input_size: counting_read.count, output_size, entropy: None })
+}
+
+fn bench_input(cli: &Cli, input: &Input) -> anyhow::Result<BenchStats> {
+    let mut data = Vec::new();
+    input.open(cli.force).and_then(|mut input_read| input_read.read_to_end(&mut data))
+        .with_context(|| "Failed to read the input file")?;
+
+    let mut packed = Vec::new();
+    let start = Instant::now();
+    let stats = huffman_format::pack_file_with_stats(Cursor::new(&data), &mut packed)
+        .with_context(|| "Failed to pack the input file")?;
+    let pack_duration = start.elapsed();
+
+    let mut unpacked = Vec::with_capacity(data.len());
+    let start = Instant::now();
+    huffman_format::unpack_file(Cursor::new(&packed), &mut unpacked)
+        .with_context(|| "Failed to unpack the packed data")?;
+    let unpack_duration = start.elapsed();
+
+    if unpacked != data {
+        anyhow::bail!("The unpacked data differs from the input")
+    }
+
+    let gzip = run_gzip(&data)
+        .inspect_err(|err| debug!("Skipping the comparison with gzip : {}", err))
+        .ok();
+
+    Ok(BenchStats {
+        input_size: stats.input_size,
+        output_size: stats.output_size,
+        entropy: stats.entropy,
+        pack_duration,
+        unpack_duration,
+        gzip,
+    })
+}
+
+// Compresses with the system's gzip at its default level, if it's installed.
+fn run_gzip(data: &[u8]) -> io::Result<GzipStats> {
+    let start = Instant::now();
+    let mut child = std::process::Command::new("gzip")
+        .arg("-c")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    // gzip writes while it reads, so the input is written from another thread.
+    let output_size = std::thread::scope(|scope| {
+        let writer = scope.spawn(move || stdin.write_all(data));
+        let output_size = io::copy(&mut stdout, &mut io::sink())?;
+        writer.join().unwrap()?;
+
+        io::Result::Ok(output_size)
+    })?;
+
+    if !child.wait()?.success() {
+        return Err(io::Error::other("gzip failed"))
+    }
+
+    Ok(GzipStats { output_size, duration: start.elapsed() })
+}
+
+fn print_bench(out: &mut impl Write, cli: &Cli, input: &Input, stats: &BenchStats) -> io::Result<()> {
+    let percent = |size: u64| if stats.input_size == 0 { 0.0 } else { size as f64 / stats.input_size as f64 * 100.0 };
+    let throughput = |duration: Duration| stats.input_size as f64 / duration.as_secs_f64() / 1_000_000.0;
+
+    writeln!(out, "{}", input)?;
+    writeln!(out, "  size          : {}", cli.format_size(stats.input_size))?;
+    writeln!(out, "  packed size   : {} ({:.2} %)", cli.format_size(stats.output_size), percent(stats.output_size))?;
+    writeln!(out, "  entropy bound : {} ({:.2} %, {:.2} bits per byte)", cli.format_size(stats.entropy_bound()), percent(stats.entropy_bound()), stats.entropy)?;
+    writeln!(out, "  pack time     : {:.2?} ({:.2} MB/s)", stats.pack_duration, throughput(stats.pack_duration))?;
+    writeln!(out, "  unpack time   : {:.2?} ({:.2} MB/s)", stats.unpack_duration, throughput(stats.unpack_duration))?;
+    if let Some(gzip) = &stats.gzip {
+        writeln!(out, "  gzip          : {} ({:.2} %) in {:.2?}", cli.format_size(gzip.output_size), percent(gzip.output_size), gzip.duration)?;
+    }
+
+    Ok(())
+}
+
+fn read_stats(cli: &Cli, input: &Input) -> anyhow::Result<ByteStats> {
+    let input_read = input.open(cli.force).with_context(|| "Failed to open the input file")?;
+    let byte_table = huffman_format::get_byte_table(&mut BufReader::new(input_read))
+        .with_context(|| "Failed to read the input file")?;
+
+    Ok(ByteStats {
+        byte_table: Box::new(byte_table),
+        entropy: huffman_format::compute_entropy(byte_table),
+        estimated_size: huffman_format::estimate_packed_size(byte_table),
+    })
+}
+
+fn print_stats(out: &mut impl Write, cli: &Cli, input: &Input, stats: &ByteStats) -> io::Result<()> {
+    const BAR_WIDTH: u64 = 40;
+
+    let size = stats.size();
+    writeln!(out, "{}", input)?;
+    writeln!(out, "  size             : {}", cli.format_size(size))?;
+    writeln!(out, "  entropy          : {:.2} bits per byte", stats.entropy)?;
+    writeln!(out, "  distinct symbols : {}", stats.distinct_symbols())?;
+    if size != 0 {
+        writeln!(out, "  estimated size   : {} ({:.2} %)", cli.format_size(stats.estimated_size), stats.estimated_size as f64 / size as f64 * 100.0)?;
+    } else {
+        writeln!(out, "  estimated size   : {}", cli.format_size(stats.estimated_size))?;
+    }
+
+    let max_count = stats.byte_table.iter().copied().max().unwrap_or(0);
+    if max_count == 0 {
+        return Ok(())
+    }
+
+    writeln!(out, "  histogram        :")?;
+    for (byte, count) in stats.byte_table.iter().enumerate().filter(|(_, count)| **count != 0) {
+        let char = char::from(byte as u8);
+        let char = if char.is_ascii_graphic() { char } else { ' ' };
+        let bar = "#".repeat((count * BAR_WIDTH).div_ceil(max_count) as usize);
+
+        writeln!(out, "    {:#04x} {} : {:>10} {:>6.2} % {}", byte, char, count, *count as f64 / size as f64 * 100.0, bar)?;
+    }
+
+    Ok(())
+}
+
+// The samples packed by `selftest` when no file is given, covering the edge cases of the tree.
+fn selftest_corpus() -> Vec<(String, Vec<u8>)> {
+    // a xorshift generator, so the data is the same on every platform.
+    let mut state = 0x2545_f491_4f6c_dd1d_u64;
+    let random = (0..64 * 1024).map(|_| {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state as u8
+    }).collect();
+    let skewed = (0..64 * 1024_u32).map(|i| (i.trailing_zeros() as u8).wrapping_mul(37)).collect();
+
+    vec![
+        ("empty".to_string(), Vec::new()),
+        ("single byte".to_string(), vec![42]),
+        ("one symbol".to_string(), vec![b'a'; 10_000]),
+        ("two symbols".to_string(), b"ab".repeat(5_000)),
+        ("every byte".to_string(), (0..=255).cycle().take(256 * 40).collect()),
+        ("skewed".to_string(), skewed),
+        ("random".to_string(), random),
+        ("text".to_string(), include_bytes!("main.rs").to_vec()),
+    ]
+}
+
+pub fn selftest(selftest: &args::Selftest) -> anyhow::Result<()> {
+    let samples = match &selftest.file {
+        Some(path) => {
+            let data = std::fs::read(path).with_context(|| format!("Failed to read `{}`", path.display()))?;
+            vec![(path.display().to_string(), data)]
+        }
+        None => selftest_corpus(),
+    };
+    let temp_dir = tempfile::tempdir().with_context(|| "Failed to create a temporary directory")?;
+    let packed_path = temp_dir.path().join("packed");
+    let unpacked_path = temp_dir.path().join("unpacked");
+    let mut out = OutputWrite::Stdout(io::stdout().lock());
+
+    let mut failed = 0;
+    for (name, data) in &samples {
+        let options = PackOptions { checksum: huffman_format::ChecksumKind::Crc32, ..Default::default() };
+        let start = Instant::now();
+        let res = std::fs::File::create(&packed_path)
+            .and_then(|packed| huffman_format::pack_file_with_options(Cursor::new(data), packed, &options));
+        let pack_duration = start.elapsed();
+
+        let start = Instant::now();
+        let res = res.and_then(|stats| {
+            let packed = std::fs::File::open(&packed_path)?;
+            let unpacked = std::fs::File::create(&unpacked_path)?;
+            huffman_format::unpack_file(packed, unpacked)?;
+
+            Ok(stats)
+        });
+        let unpack_duration = start.elapsed();
+
+        let res = res.and_then(|stats| {
+            if std::fs::read(&unpacked_path)? != *data {
+                return Err(io::Error::other("the unpacked data differs from the original"))
+            }
+
+            Ok(stats)
+        });
+
+        match res {
+            Ok(stats) => writeln!(
+                out,
+                "{:<12} : ok, {} -> {} bytes, packed in {:.2?}, unpacked in {:.2?}",
+                name, stats.input_size, stats.output_size, pack_duration, unpack_duration
+            )?,
+            Err(err) => {
+                failed += 1;
+                writeln!(out, "{:<12} : FAILED, {}", name, err)?;
+            }
+        }
+    }
+
+    if failed > 0 {
+        anyhow::bail!("{} of {} self tests failed", failed, samples.len())
+    }
+
+    Ok(())
+}
+
+// Copies the packed files one after the other, without decoding them, once their headers are checked.
+fn concat_inputs(cli: &Cli, inputs: &[Input]) -> anyhow::Result<()> {
+    let output = cli.validate_output(&inputs[0])?;
+    let mut output_write = output.open(cli.overwrite()).with_context(|| "Failed to create the output file")?;
+
+    for input in inputs {
+        let mut input_read = input.open_seekable(cli.force, cli.stdin_buffer())
+            .with_context(|| format!("Failed to open `{}`", input))?;
+        huffman_format::read_header_info(&mut input_read)
+            .and_then(|_| input_read.rewind())
+            .with_context(|| format!("`{}` isn't a packed file", input))?;
+
+        let size = io::copy(&mut input_read, &mut output_write).with_context(|| format!("Failed to copy `{}`", input))?;
+        info!("`{}` : {}", input, cli.format_size(size));
+    }
+
+    output_write.finish().with_context(|| "Failed to write the output file")?;
+    info!("Joined {} files into `{}`.", inputs.len(), output);
+
+    Ok(())
+}
+
+fn read_tree(cli: &Cli, input: &Input) -> anyhow::Result<Option<HeapNode>> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+
+    huffman_format::read_tree(input_read).with_context(|| "Failed to read the headers")
+}
+
+// A byte as a character when it's printable.
+fn byte_label(byte: u8) -> String {
+    let char = char::from(byte);
+    if char.is_ascii_graphic() {
+        format!("'{}'", char)
+    } else {
+        format!("{:#04x}", byte)
+    }
+}
+
+fn read_members(cli: &Cli, input: &Input) -> anyhow::Result<Vec<MemberInfo>> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+
+    huffman_format::read_members(input_read).with_context(|| "Failed to read the members")
+}
+
+fn dump_input(cli: &Cli, input: &Input, codewords: u64) -> anyhow::Result<String> {
+    let input_read = input.open_packed(cli.force).with_context(|| "Failed to open the input file")?;
+
+    let mut dump = Vec::new();
+    huffman_format::dump_stream(input_read, &mut dump, codewords).with_context(|| "Failed to read the stream")?;
+
+    Ok(String::from_utf8_lossy(&dump).into_owned())
+}
+
+// Formatted like `unzip -l`.
+fn print_members(out: &mut impl Write, cli: &Cli, input: &Input, members: &[MemberInfo]) -> io::Result<()> {
+    let size = |size: u64| if cli.human { cli.format_size(size) } else { size.to_string() };
+    let ratio = |packed: u64, original: u64| match original {
+        0 => "-".to_string(),
+        _ => format!("{:.1}%", packed as f64 / original as f64 * 100.0),
+    };
+
+    writeln!(out, "Archive:  {}", input)?;
+    writeln!(out, "{:>12}  {:>12}  {:>6}  {:<22}  Member", "Length", "Packed", "Ratio", "Checksum")?;
+    writeln!(out, "{:->12}  {:->12}  {:->6}  {:-<22}  ------", "", "", "", "")?;
+    for (index, member) in members.iter().enumerate() {
+        let checksum = member.checksum.map_or("-".to_string(), |checksum| checksum.to_string());
+        writeln!(
+            out,
+            "{:>12}  {:>12}  {:>6}  {:<22}  {}",
+            size(member.original_size), size(member.packed_size), ratio(member.packed_size, member.original_size), checksum, index + 1
+        )?;
+    }
+
+    let original_size = members.iter().map(|member| member.original_size).sum();
+    let packed_size = members.iter().map(|member| member.packed_size).sum();
+    writeln!(out, "{:->12}  {:->12}  {:->6}  {:22}  ------", "", "", "", "")?;
+    writeln!(
+        out,
+        "{:>12}  {:>12}  {:>6}  {:22}  {} member{}",
+        size(original_size), size(packed_size), ratio(packed_size, original_size), "", members.len(), if members.len() == 1 { "" } else { "s" }
+    )
+}
+
+// The frequencies of the bytes aren't stored in the format, only the codes can be printed.
+fn print_tree(out: &mut impl Write, input: &Input, tree: Option<&HeapNode>) -> io::Result<()> {
+    fn print_node(out: &mut impl Write, node: &HeapNode, code: &mut String, prefix: &str) -> io::Result<()> {
+        let HeapNode::Pair { left, right } = node else {
+            return Ok(())
+        };
+
+        let children = [(left, '0'), (right, '1')];
+        let children: Vec<_> = children.iter().filter(|(child, _)| !matches!(***child, HeapNode::Empty)).collect();
+        for (index, (child, bit)) in children.iter().enumerate() {
+            let is_last = index == children.len() - 1;
+            let branch = if is_last { "└─" } else { "├─" };
+
+            code.push(*bit);
+            match &***child {
+                HeapNode::Leaf(byte) => writeln!(out, "{}{}{} {} : {}", prefix, branch, bit, byte_label(*byte), code)?,
+                _ => {
+                    writeln!(out, "{}{}{}", prefix, branch, bit)?;
+                    print_node(out, child, code, &format!("{}{}", prefix, if is_last { "  " } else { "│ " }))?;
+                }
+            }
+            code.pop();
+        }
+
+        Ok(())
+    }
+
+    writeln!(out, "{}", input)?;
+    match tree {
+        Some(tree) => print_node(out, tree, &mut String::new(), "  "),
+        None => writeln!(out, "  empty"),
+    }
+}
+
+fn print_dot(out: &mut impl Write, input: &Input, tree: Option<&HeapNode>) -> io::Result<()> {
+    fn print_node(out: &mut impl Write, node: &HeapNode, id: &mut usize, code: &mut String) -> io::Result<usize> {
+        let node_id = *id;
+        *id += 1;
+
+        match node {
+            HeapNode::Leaf(byte) => {
+                writeln!(out, "  n{} [shape=box, label=\"{}\\n{}\"];", node_id, escape(&byte_label(*byte)), code)?;
+            }
+            HeapNode::Pair { left, right } => {
+                writeln!(out, "  n{} [shape=point];", node_id)?;
+                for (child, bit) in [(left, '0'), (right, '1')] {
+                    if matches!(**child, HeapNode::Empty) {
+                        continue
+                    }
+
+                    code.push(bit);
+                    let child_id = print_node(out, child, id, code)?;
+                    code.pop();
+                    writeln!(out, "  n{} -> n{} [label=\"{}\"];", node_id, child_id, bit)?;
+                }
+            }
+            HeapNode::Empty => {}
+        }
+
+        Ok(node_id)
+    }
+
+    fn escape(str: &str) -> String {
+        str.replace('\\', "\\\\").replace('"', "\\\"")
+    }
+
+    writeln!(out, "digraph \"{}\" {{", escape(&input.to_string()))?;
+    if let Some(tree) = tree {
+        print_node(out, tree, &mut 0, &mut String::new())?;
+    }
+    writeln!(out, "}}")
+}
+
+// The result of an input, with what was measured and logged while it was processed.
+struct Run {
+    res: anyhow::Result<Outcome>,
+    times: Times,
+    warnings: Vec<String>,
+}
+
+// `threads` is the amount of threads the input can use, the others processing other inputs.
+fn run_input(cli: &Cli, input: &Input, threads: usize) -> Run {
+    let start = Instant::now();
+    let cpu_start = cpu_time();
+    let res = match cli.command {
+        args::Command::Info(_) => read_info(cli, input).map(Outcome::Info),
+        args::Command::Test(_) => test_input(cli, input).map(Outcome::Processed),
+        args::Command::Bench(_) => bench_input(cli, input).map(Outcome::Bench),
+        args::Command::Stats(_) => read_stats(cli, input).map(Outcome::Stats),
+        args::Command::Tree(_) => read_tree(cli, input).map(Outcome::Tree),
+        args::Command::List(_) => read_members(cli, input).map(Outcome::List),
+        args::Command::DebugDump(ref dump) => dump_input(cli, input, dump.codewords).map(Outcome::Dump),
+        args::Command::Unpack(_) if cli.untar() => untar_input(cli, input).map(Outcome::Processed),
+        args::Command::Pack(_) | args::Command::Unpack(_) | args::Command::Cat(_) => process_input(cli, input, threads),
+        args::Command::Concat(_) | args::Command::Completions(_) | args::Command::Selftest(_) => unreachable!(),
+    };
+    let times = Times {
+        wall: start.elapsed(),
+        cpu: cpu_start.zip(cpu_time()).map(|(start, end)| end.saturating_sub(start)),
+    };
+
+    Run { res, times, warnings: log::take_warnings() }
+}
+
+// Several files are processed at the same time, up to `--threads`, unless their outputs go to
+// stdout one after the other, an input is stdin, or they are benchmarked. The runs are still
+// given to `handle` in the order of the inputs, which stops everything when it fails.
+fn run_inputs(cli: &Cli, inputs: &[Input], mut handle: impl FnMut(&Input, Run) -> anyhow::Result<()>) -> anyhow::Result<()> {
+    let threads = cli.threads().min(inputs.len());
+    let parallel = threads > 1
+        && !cli.writes_to_stdout()
+        && !inputs.contains(&Input::Stdin)
+        && !matches!(cli.command, args::Command::Bench(_));
+
+    if !parallel {
+        return inputs.iter().try_for_each(|input| handle(input, run_input(cli, input, cli.threads())))
+    }
+
+    let next = AtomicUsize::new(0);
+    let stop = AtomicBool::new(false);
+    std::thread::scope(|scope| {
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..threads {
+            let sender = sender.clone();
+            let (next, stop) = (&next, &stop);
+            scope.spawn(move || {
+                while !stop.load(Ordering::Relaxed) {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(input) = inputs.get(index) else {
+                        break
+                    };
+                    log::set_input(Some(input.to_string()));
+                    if sender.send((index, run_input(cli, input, cli.threads() / threads))).is_err() {
+                        break
+                    }
+                }
+            });
+        }
+        drop(sender);
+
+        // the runs finished before the ones of the previous inputs.
+        let mut pending = BTreeMap::new();
+        let mut handled = 0;
+        for (index, run) in receiver {
+            pending.insert(index, run);
+
+            while let Some(run) = pending.remove(&handled) {
+                if let Err(err) = handle(&inputs[handled], run) {
+                    stop.store(true, Ordering::Relaxed);
+                    return Err(err)
+                }
+                handled += 1;
+            }
+        }
+
+        Ok(())
+    })
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Status {
+    Ok,
+    Skipped,
+    Failed,
+}
+
+// A line of the table printed after several files.
+struct SummaryRow {
+    input: String,
+    // The sizes of the input and the output of the processed files.
+    sizes: Option<(u64, u64)>,
+    wall: Duration,
+    status: Status,
+}
+
+// Aligned, with the ratios of the packed sizes like `--time` gives them.
+fn summary_table(cli: &Cli, rows: &[SummaryRow], colors: bool) -> String {
+    use colored::Colorize;
+
+    let cells: Vec<[String; 5]> = rows.iter().map(|row| {
+        let (input_size, output_size) = match row.sizes {
+            Some((input_size, output_size)) => (cli.format_size(input_size), cli.format_size(output_size)),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let ratio = match (row.sizes, &cli.command) {
+            (Some((input_size, output_size)), args::Command::Pack(_)) if input_size != 0 => format!("{:.2} %", output_size as f64 / input_size as f64 * 100.0),
+            (Some((input_size, output_size)), _) if output_size != 0 => format!("{:.2} %", input_size as f64 / output_size as f64 * 100.0),
+            _ => "-".to_string(),
+        };
+
+        [row.input.clone(), input_size, output_size, ratio, format!("{:.2?}", row.wall)]
+    }).collect();
+
+    let header = ["Name", "In", "Out", "Ratio", "Time"];
+    let widths: Vec<usize> = (0..header.len())
+        .map(|column| cells.iter().map(|row| row[column].chars().count()).chain([header[column].len()]).max().unwrap())
+        .collect();
+    let line = |cells: [&str; 5]| format!(
+        "{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}  {:>w4$}",
+        cells[0], cells[1], cells[2], cells[3], cells[4],
+        w0 = widths[0], w1 = widths[1], w2 = widths[2], w3 = widths[3], w4 = widths[4]
+    );
+
+    let mut table = format!("{}  Status\n", line(header));
+    for (row, cells) in rows.iter().zip(&cells) {
+        let status = match row.status {
+            Status::Ok => "ok".green(),
+            Status::Skipped => "skipped".yellow(),
+            Status::Failed => "failed".red(),
+        };
+        let status = if colors { status.to_string() } else { status.clear().to_string() };
+
+        table += &format!("{}  {}\n", line(cells.each_ref().map(String::as_str)), status);
+    }
+
+    table
+}
+
+fn find_expansion(err: &anyhow::Error) -> Option<&huffman_format::Expansion> {
+    err.chain().find_map(|err| err.downcast_ref::<io::Error>()?.get_ref()?.downcast_ref())
+}
+
+pub fn is_stdout_closed(err: &anyhow::Error) -> bool {
+    err.chain().any(|err| err.downcast_ref::<io::Error>().and_then(|err| err.get_ref()).is_some_and(|err| err.is::<StdoutClosed>()))
+}
+
+pub fn try_main(cli: Cli) -> anyhow::Result<()> {
+    match run_command(cli) {
+        // the reader of stdout is gone, like with `| head`, nothing more can be written.
+        Err(err) if is_stdout_closed(&err) => Ok(()),
+        res => res,
+    }
+}
+
+fn run_command(mut cli: Cli) -> anyhow::Result<()> {
+    match &cli.command {
+        args::Command::Completions(completions) => return Ok(completions.print()?),
+        args::Command::Selftest(selftest_args) => return selftest(selftest_args),
+        _ => {}
+    }
+
+    if !cli.no_config {
+        cli.apply_config(args::Config::load()?);
+    }
+    cli.load_files_from()?;
+    let inputs = cli.validate_inputs()?;
+
+    if cli.split_size().is_some() && (cli.writes_to_stdout() || cli.split_size() == Some(0)) {
+        return Err(ValidationError::InvalidSplit.into())
+    }
+    if matches!(cli.command, args::Command::Pack(_) | args::Command::Concat(_)) && cli.writes_to_stdout() && std::io::stdout().is_terminal() && !cli.force && !cli.dry_run() {
+        return Err(ValidationError::PackedDataToTerminal.into())
+    }
+    if matches!(cli.command, args::Command::Concat(_)) {
+        return concat_inputs(&cli, inputs)
+    }
+
+    if cli.threads() > 1 {
+        debug!("The format has no independent blocks, every file is encoded on a single thread and only the big ones are counted on several.");
+    }
+
+    let mut failed = 0;
+    let mut skipped = 0;
+    let mut exit = Exit::Success;
+    let mut total_input_size = 0;
+    let mut total_output_size = 0;
+    let mut rows = Vec::new();
+    run_inputs(&cli, inputs, |input, Run { res, times, warnings }| {
+        // the reader of stdout is gone, nothing more can be written.
+        if res.as_ref().is_err_and(is_stdout_closed) {
+            return res.map(|_| ())
+        }
+
+        let mut row = SummaryRow { input: input.to_string(), sizes: None, wall: times.wall, status: Status::Ok };
+        match &res {
+            Ok(Outcome::Info(_) | Outcome::Bench(_) | Outcome::Stats(_) | Outcome::Tree(_) | Outcome::List(_) | Outcome::Dump(_)) => {}
+            Ok(Outcome::Skipped) => {
+                skipped += 1;
+                row.status = Status::Skipped;
+            }
+            Ok(Outcome::Processed(stats)) => {
+                total_input_size += stats.input_size;
+                total_output_size += stats.output_size;
+                row.sizes = Some((stats.input_size, stats.output_size));
+            }
+            Err(err) => {
+                failed += 1;
+                if exit == Exit::Success {
+                    exit = Exit::of(err);
+                }
+                row.status = Status::Failed;
+            }
+        }
+        rows.push(row);
+
+        let out = &mut OutputWrite::Stdout(io::stdout().lock());
+        let printed = match cli.format {
+            Format::Json => print_report(out, &cli, &Report::new(&cli, input, &res, times, warnings)),
+            Format::Text => match &res {
+                Ok(Outcome::Info(info)) => print_info(out, &cli, input, info),
+                Ok(Outcome::Bench(stats)) => print_bench(out, &cli, input, stats),
+                Ok(Outcome::Stats(stats)) => print_stats(out, &cli, input, stats),
+                Ok(Outcome::Tree(tree)) if matches!(&cli.command, args::Command::Tree(args) if args.dot) => print_dot(out, input, tree.as_ref()),
+                Ok(Outcome::Tree(tree)) => print_tree(out, input, tree.as_ref()),
+                Ok(Outcome::List(members)) => print_members(out, &cli, input, members),
+                Ok(Outcome::Dump(dump)) => write!(out, "{}\n{}", input, dump),
+                Ok(Outcome::Processed(stats)) if cli.time => print_time(out, &cli, input, stats, times),
+                Ok(Outcome::Processed(_) | Outcome::Skipped) => Ok(()),
+                Err(err) => {
+                    error!("`{}` : {:#}", input, err);
+
+                    if !log::is_active() {
+                        eprintln!("Error : `{}` : {:#}", input, err);
+                    }
+                    Ok(())
+                }
+            }
+        };
+
+        Ok(printed?)
+    })?;
+
+    if inputs.len() > 1 && !matches!(cli.command, args::Command::Info(_) | args::Command::Stats(_) | args::Command::Tree(_) | args::Command::List(_) | args::Command::DebugDump(_)) {
+        if cli.format == Format::Text && !cli.quiet {
+            eprint!("{}", summary_table(&cli, &rows, cli.colors()));
+        }
+        info!(
+            "{} files processed, {} succeeded, {} skipped, {} failed : {} -> {}",
+            inputs.len(), inputs.len() - failed - skipped, skipped, failed, cli.format_size(total_input_size), cli.format_size(total_output_size)
+        );
+    }
+
+    if failed > 0 {
+        return Err(InputsFailed { failed, total: inputs.len(), exit }.into())
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use crate::args::{Cli, ValidationError};
+
+    #[test]
+    fn json_report_of_a_packed_file() {
+        use std::time::Duration;
+
+        use crate::io::{Input, Output};
+
+        use super::{FileStats, Outcome, Report, Times};
+
+        let cli = Cli::try_parse_from(["", "pack", "-", "--format", "json"]).unwrap();
+        let res = Ok(Outcome::Processed(FileStats {
+            output: Some(Output::Stdout),
+            input_size: 200,
+            output_size: 50,
+            entropy: Some(1.5),
+        }));
+
+        let times = Times { wall: Duration::from_secs(2), cpu: Some(Duration::from_secs(1)) };
+        let report = Report::new(&cli, &Input::Stdin, &res, times, vec!["warning".to_string()]);
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({
+                "input": "<stdin>",
+                "output": "<stdout>",
+                "input_size": 200,
+                "output_size": 50,
+                "ratio": 0.25,
+                "entropy": 1.5,
+                "duration": 2.0,
+                "skipped": false,
+                "warnings": ["warning"],
+                "error": null,
+            })
+        );
+
+        let cli = Cli::try_parse_from(["", "pack", "-", "--format", "json", "--time"]).unwrap();
+        let report = Report::new(&cli, &Input::Stdin, &res, times, Vec::new());
+        assert_eq!(serde_json::to_value(&report).unwrap()["cpu_time"], 1.0);
+    }
+
+    #[test]
+    fn bench_packs_and_unpacks_in_memory() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aabb").unwrap();
+
+        let cli = Cli::try_parse_from(["", "bench", &a_path_str]).unwrap();
+        let stats = super::bench_input(&cli, &cli.validate_inputs().unwrap()[0]).unwrap();
+        assert_eq!(stats.input_size, 4);
+        assert_eq!(stats.entropy, 1.0);
+        assert_eq!(stats.entropy_bound(), 1);
+    }
+
+    #[test]
+    fn stats_estimate_the_packed_size() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aaaabbc").unwrap();
+
+        let cli = Cli::try_parse_from(["", "stats", &a_path_str]).unwrap();
+        let stats = super::read_stats(&cli, &cli.validate_inputs().unwrap()[0]).unwrap();
+        assert_eq!(stats.size(), 7);
+        assert_eq!(stats.distinct_symbols(), 3);
+        assert_eq!(stats.byte_table[b'b' as usize], 2);
+
+        let mut packed = Vec::new();
+        huffman_format::pack_file(std::io::Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        assert_eq!(stats.estimated_size, packed.len() as u64);
+    }
+
+    #[test]
+    fn compressed_formats_are_detected() {
+        use std::io::{Cursor, Read};
+
+        use super::detect_compressed_format;
+
+        let mut reader = Cursor::new(vec![0x1f, 0x8b, 0x08, 0x00]);
+        assert_eq!(detect_compressed_format(&mut reader).unwrap(), Some("gzip"));
+        assert_eq!(reader.position(), 0);
+
+        assert_eq!(detect_compressed_format(&mut Cursor::new(b"\x89PNG\r\n\x1a\n....")).unwrap(), Some("png"));
+        assert_eq!(detect_compressed_format(&mut Cursor::new(b"BZh91AY")).unwrap(), Some("bzip2"));
+        assert_eq!(detect_compressed_format(&mut Cursor::new(b"")).unwrap(), None);
+
+        let mut reader = Cursor::new(b"plain text".to_vec());
+        assert_eq!(detect_compressed_format(&mut reader).unwrap(), None);
+        let mut text = String::new();
+        reader.read_to_string(&mut text).unwrap();
+        assert_eq!(text, "plain text");
+    }
+
+    #[test]
+    fn dry_run_writes_nothing() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aaaabbc").unwrap();
+
+        let cli = Cli::try_parse_from(["", "pack", "--dry-run", "--rm", &a_path_str]).unwrap();
+        let Ok(super::Outcome::Processed(stats)) = super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1) else {
+            panic!("the input should be processed")
+        };
+
+        let mut packed = Vec::new();
+        huffman_format::pack_file(std::io::Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        assert_eq!(stats.input_size, 7);
+        assert_eq!(stats.output_size, packed.len() as u64);
+
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 1);
+        assert!(std::fs::exists(&a_path_str).unwrap());
+    }
+
+    #[test]
+    fn packed_size_is_compared_with_the_entropy() {
+        use super::FileStats;
+
+        let stats = FileStats { output: None, input_size: 100, output_size: 50, entropy: Some(3.0) };
+        assert_eq!(stats.entropy_comparison().unwrap(), "4.00 bpb vs 3.00 bpb theoretical, 75 % efficient");
+
+        let stats = FileStats { output: None, input_size: 100, output_size: 50, entropy: None };
+        assert_eq!(stats.entropy_comparison(), None);
+        let stats = FileStats { output: None, input_size: 0, output_size: 0, entropy: Some(0.0) };
+        assert_eq!(stats.entropy_comparison(), None);
+    }
+
+    #[cfg(unix)]
+
+    #[test]
+    fn symbolic_links_are_skipped_unless_dereferenced() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aaaabbc").unwrap();
+        let link_path = temp_dir.path().join("link");
+        std::os::unix::fs::symlink(&a_path_str, &link_path).unwrap();
+        let link_path_str = link_path.display().to_string();
+
+        let cli = Cli::try_parse_from(["", "pack", &link_path_str]).unwrap();
+        let res = super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1);
+        assert!(matches!(res, Ok(super::Outcome::Skipped)));
+        assert!(!std::fs::exists(temp_dir.path().join("link.hc")).unwrap());
+
+        let cli = Cli::try_parse_from(["", "pack", "--no-dereference", "--dereference", &link_path_str]).unwrap();
+        let res = super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1);
+        assert!(matches!(res, Ok(super::Outcome::Processed(_))));
+        assert!(std::fs::exists(temp_dir.path().join("link.hc")).unwrap());
+
+        let cli = Cli::try_parse_from(["", "pack", "--dereference", "--no-dereference", &link_path_str]).unwrap();
+        assert!(!cli.dereference());
+    }
+
+    #[test]
+    fn selftest_passes_on_the_corpus() {
+        use crate::args::Selftest;
+
+        assert!(super::selftest(&Selftest { file: None }).is_ok());
+        assert!(super::selftest(&Selftest { file: Some(PathBuf::from("missing")) }).is_err());
+    }
+
+    #[test]
+    fn untar_extracts_in_the_output_directory() {
+        use std::process::Command;
+
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aaaabbc").unwrap();
+
+        let tar_path = temp_dir.path().join("a.tar");
+        let created = Command::new("tar").arg("-cf").arg(&tar_path).arg("-C").arg(temp_dir.path()).arg("a").status();
+        if !created.is_ok_and(|status| status.success()) {
+            // tar isn't installed.
+            return
+        }
+
+        let packed_path = temp_dir.path().join("a.tar.hc");
+        huffman_format::pack_file(std::fs::File::open(&tar_path).unwrap(), std::fs::File::create(&packed_path).unwrap()).unwrap();
+
+        let extract_dir = temp_dir.path().join("extracted");
+        std::fs::create_dir(&extract_dir).unwrap();
+        let cli = Cli::try_parse_from(["", "unpack", "--untar", &packed_path.display().to_string(), "-o", &extract_dir.display().to_string()]).unwrap();
+        super::untar_input(&cli, &cli.validate_inputs().unwrap()[0]).unwrap();
+
+        assert_eq!(std::fs::read(extract_dir.join("a")).unwrap(), b"aaaabbc");
+    }
+
+    #[test]
+    fn concat_joins_packed_files() {
+        create_temp_files!("a.hc" => a_path_str, "b.hc" => b_path_str, "plain" => plain_path_str in temp_dir);
+        huffman_format::pack_file(std::io::Cursor::new(b"aaaabbc"), std::fs::File::create(&a_path_str).unwrap()).unwrap();
+        huffman_format::pack_file(std::io::Cursor::new(b"xyz"), std::fs::File::create(&b_path_str).unwrap()).unwrap();
+        let all_path_str = temp_dir.path().join("all.hc").display().to_string();
+
+        let cli = Cli::try_parse_from(["", "concat", &a_path_str, &b_path_str, "-o", &all_path_str]).unwrap();
+        super::concat_inputs(&cli, cli.validate_inputs().unwrap()).unwrap();
+
+        let mut unpacked = Vec::new();
+        huffman_format::unpack_file(std::fs::File::open(&all_path_str).unwrap(), &mut unpacked).unwrap();
+        assert_eq!(unpacked, b"aaaabbcxyz");
+
+        let cli = Cli::try_parse_from(["", "concat", &a_path_str, &b_path_str]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Err(ValidationError::ConcatRequiresOutput));
+
+        std::fs::write(&plain_path_str, [0xff; 4]).unwrap();
+        let cli = Cli::try_parse_from(["", "concat", &a_path_str, &plain_path_str, "-o", &all_path_str, "-W"]).unwrap();
+        assert!(super::concat_inputs(&cli, cli.validate_inputs().unwrap()).is_err());
+    }
+
+    #[test]
+    fn split_output_is_read_back_from_its_volumes() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i * i % 251) as u8).collect();
+        std::fs::write(&a_path_str, &data).unwrap();
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, "--split-size", "1K"]).unwrap();
+        let Ok(super::Outcome::Processed(stats)) = super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1) else {
+            panic!("the input should be packed")
+        };
+        let volume_count = stats.output_size.div_ceil(1024);
+        assert!(volume_count > 1);
+        for index in 1..=volume_count {
+            let volume_size = std::fs::metadata(temp_dir.path().join(format!("a.hc.{:03}", index))).unwrap().len();
+            assert!(volume_size <= 1024);
+        }
+        assert!(!std::fs::exists(temp_dir.path().join(format!("a.hc.{:03}", volume_count + 1))).unwrap());
+
+        std::fs::remove_file(&a_path_str).unwrap();
+        let first_volume = temp_dir.path().join("a.hc.001").display().to_string();
+        let cli = Cli::try_parse_from(["", "unpack", &first_volume]).unwrap();
+        assert_eq!(cli.validate_output(&cli.validate_inputs().unwrap()[0]), Ok(crate::io::Output::File(PathBuf::from(&a_path_str))));
+        assert!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1).is_ok());
+        assert_eq!(std::fs::read(&a_path_str).unwrap(), data);
+    }
+
+    #[test]
+    fn closed_stdouts_are_found_in_the_context() {
+        use anyhow::Context;
+
+        let err = Err::<(), _>(crate::io::stdout_closed(std::io::ErrorKind::BrokenPipe.into())).context("Failed to unpack the data").unwrap_err();
+        assert!(super::is_stdout_closed(&err));
+
+        // like the pipe to tar.
+        let err = Err::<(), _>(std::io::Error::from(std::io::ErrorKind::BrokenPipe)).context("Failed to unpack the data").unwrap_err();
+        assert!(!super::is_stdout_closed(&err));
+
+        let err = Err::<(), _>(crate::io::stdout_closed(std::io::ErrorKind::InvalidData.into())).context("Failed to unpack the data").unwrap_err();
+        assert!(!super::is_stdout_closed(&err));
+    }
+
+    #[test]
+    fn partial_outputs_are_kept_on_demand() {
+        create_temp_files!("a.hc" => a_path_str in temp_dir);
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i * i % 251) as u8).collect();
+        let mut packed = Vec::new();
+        huffman_format::pack_file(std::io::Cursor::new(&data), &mut packed).unwrap();
+        std::fs::write(&a_path_str, &packed[..packed.len() / 2]).unwrap();
+
+        let cli = Cli::try_parse_from(["", "unpack", &a_path_str]).unwrap();
+        assert!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1).is_err());
+        assert!(!std::fs::exists(temp_dir.path().join("a.part")).unwrap());
+
+        let cli = Cli::try_parse_from(["", "unpack", &a_path_str, "--keep-partial"]).unwrap();
+        assert!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1).is_err());
+        assert!(!std::fs::exists(temp_dir.path().join("a")).unwrap());
+        let partial = std::fs::read(temp_dir.path().join("a.part")).unwrap();
+        assert!(!partial.is_empty());
+        assert!(data.starts_with(&partial));
+    }
+
+    #[test]
+    fn inputs_are_processed_in_parallel_and_handled_in_order() {
+        create_temp_files!("a" => a_path_str, "b" => b_path_str, "c" => c_path_str, "d" => d_path_str in temp_dir);
+        for (index, path) in [&a_path_str, &b_path_str, &c_path_str, &d_path_str].into_iter().enumerate() {
+            std::fs::write(path, vec![b'a' + index as u8; 1000 * (4 - index)]).unwrap();
+        }
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, &b_path_str, &c_path_str, &d_path_str, "-T", "3"]).unwrap();
+        let inputs = cli.validate_inputs().unwrap();
+        let mut handled = Vec::new();
+        super::run_inputs(&cli, inputs, |input, run| {
+            assert!(matches!(run.res, Ok(super::Outcome::Processed(_))));
+            handled.push(input.clone());
+            Ok(())
+        }).unwrap();
+        assert_eq!(&handled, inputs);
+
+        for name in ["a", "b", "c", "d"] {
+            assert!(std::fs::exists(temp_dir.path().join(format!("{}.hc", name))).unwrap());
+        }
+    }
+
+    #[test]
+    fn files_getting_bigger_are_skipped() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"abcd").unwrap();
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str]).unwrap();
+        assert!(matches!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1), Ok(super::Outcome::Skipped)));
+        assert!(!std::fs::exists(temp_dir.path().join("a.hc")).unwrap());
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, "--force-expand"]).unwrap();
+        assert!(matches!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1), Ok(super::Outcome::Processed(_))));
+        assert!(std::fs::exists(temp_dir.path().join("a.hc")).unwrap());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn named_pipes_are_read_as_streams() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let fifo_path = temp_dir.path().join("fifo");
+        let fifo_path_c = std::ffi::CString::new(fifo_path.as_os_str().as_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(fifo_path_c.as_ptr(), 0o600) }, 0);
+
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i * i % 251) as u8).collect();
+        let writer = std::thread::spawn({
+            let (fifo_path, data) = (fifo_path.clone(), data.clone());
+            move || std::fs::write(fifo_path, data).unwrap()
+        });
+
+        let output_path = temp_dir.path().join("fifo.hc").display().to_string();
+        let cli = Cli::try_parse_from(["", "pack", &fifo_path.display().to_string(), "-o", &output_path, "--rm"]).unwrap();
+        let input = &cli.validate_inputs().unwrap()[0];
+        assert!(input.is_special());
+        assert!(matches!(super::process_input(&cli, input, 1), Ok(super::Outcome::Processed(_))));
+        writer.join().unwrap();
+
+        assert!(std::fs::exists(&fifo_path).unwrap());
+        let mut unpacked = Vec::new();
+        huffman_format::unpack_file(std::fs::File::open(&output_path).unwrap(), &mut unpacked).unwrap();
+        assert_eq!(unpacked, data);
+    }
+
+    #[test]
+    fn members_are_listed_in_the_json_report() {
+        create_temp_files!("a.hc" => a_path_str in temp_dir);
+        let mut packed = Vec::new();
+        huffman_format::pack_file(std::io::Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        huffman_format::pack_file(std::io::Cursor::new(b"xyz"), &mut packed).unwrap();
+        std::fs::write(&a_path_str, &packed).unwrap();
+
+        let cli = Cli::try_parse_from(["", "list", &a_path_str, "--format", "json"]).unwrap();
+        let input = &cli.validate_inputs().unwrap()[0];
+        let times = super::Times { wall: std::time::Duration::ZERO, cpu: None };
+        let report = super::Report::new(&cli, input, &super::read_members(&cli, input).map(super::Outcome::List), times, Vec::new());
+        let report = serde_json::to_value(&report).unwrap();
+
+        assert_eq!(report["input_size"], packed.len());
+        assert_eq!(report["output_size"], 10);
+        let members = report["members"].as_array().unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!(members[1]["original_size"], 3);
+        assert_eq!(members[1]["checksum"], serde_json::Value::Null);
+    }
+
+    #[test]
+    fn packed_output_is_verified_before_removing_the_input() {
+        create_temp_files!("a" => a_path_str in temp_dir);
+        let data: Vec<u8> = (0..3000_u32).map(|i| (i * i % 251) as u8).collect();
+        std::fs::write(&a_path_str, &data).unwrap();
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str, "--verify-after-pack", "--rm", "--split-size", "1K"]).unwrap();
+        assert!(matches!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1), Ok(super::Outcome::Processed(_))));
+        assert!(!std::fs::exists(&a_path_str).unwrap());
+        assert!(std::fs::exists(temp_dir.path().join("a.hc.002")).unwrap());
+    }
+
+    #[test]
+    fn summary_table_is_aligned() {
+        use std::time::Duration;
+
+        use super::{Status, SummaryRow};
+
+        let cli = Cli::try_parse_from(["", "pack", "a"]).unwrap();
+        let rows = [
+            SummaryRow { input: "a.txt".to_string(), sizes: Some((200, 50)), wall: Duration::from_millis(3), status: Status::Ok },
+            SummaryRow { input: "b".to_string(), sizes: None, wall: Duration::from_millis(12), status: Status::Failed },
+        ];
+
+        assert_eq!(
+            super::summary_table(&cli, &rows, false),
+            "Name          In       Out    Ratio     Time  Status\n\
+             a.txt  200 bytes  50 bytes  25.00 %   3.00ms  ok\n\
+             b              -         -        -  12.00ms  failed\n"
+        );
+    }
+
+    #[test]
+    fn existing_outputs_are_skipped_on_demand() {
+        create_temp_files!("a" => a_path_str, "a.hc" => packed_path_str, "b" => b_path_str in temp_dir);
+        std::fs::write(&a_path_str, b"aaaabbc").unwrap();
+        std::fs::write(&b_path_str, b"aaaabbc").unwrap();
+
+        let cli = Cli::try_parse_from(["", "pack", &a_path_str]).unwrap();
+        assert!(super::process_input(&cli, &cli.validate_inputs().unwrap()[0], 1).is_err());
+
+        let cli = Cli::try_parse_from(["", "pack", "--skip-existing", &a_path_str, &b_path_str]).unwrap();
+        let inputs = cli.validate_inputs().unwrap();
+        assert!(matches!(super::process_input(&cli, &inputs[0], 1), Ok(super::Outcome::Skipped)));
+        assert!(matches!(super::process_input(&cli, &inputs[1], 1), Ok(super::Outcome::Processed(_))));
+        assert!(std::fs::read(&packed_path_str).unwrap().is_empty());
+
+        assert!(Cli::try_parse_from(["", "pack", "--skip-existing", "-W", &a_path_str]).is_err());
+    }
+}
@@ -0,0 +1,594 @@
+use std::{fs::{File, Metadata}, io::{self, Cursor, IsTerminal, Read, Seek, StdinLock, StdoutLock, Write}, path::{Path, PathBuf}};
+
+use derive_more::Display;
+use log::{info, warn};
+use tempfile::NamedTempFile;
+
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum Input {
+    #[display("<stdin>")]
+    Stdin,
+
+    #[display("{}", _0.display())]
+    File(PathBuf),
+}
+
+impl Input {
+    pub fn parse_value(str: &str) -> Result<Self, String> {
+        if str.trim() == "-" {
+            return Ok(Self::Stdin)
+        }
+
+        Self::from_path(PathBuf::from(str))
+    }
+
+    pub fn from_path(path: PathBuf) -> Result<Self, String> {
+        // a missing file is reported when opening it, with its own exit code.
+        if std::fs::metadata(&path).is_ok_and(|metadata| !metadata.is_file() && !is_special_file(&metadata)) {
+            return Err("Expected the input path to be a file, a named pipe or a character device.".to_string())
+        }
+
+        Ok(Self::File(path))
+    }
+
+    // A named pipe or a character device, like the `/dev/fd/63` of `<(command)`, which is read
+    // as a stream like stdin.
+    pub fn is_special(&self) -> bool {
+        match self {
+            Self::Stdin => false,
+            Self::File(path) => std::fs::metadata(path).is_ok_and(|metadata| is_special_file(&metadata)),
+        }
+    }
+
+    // The size of the input when it's known, with the next volumes of a `.001` file when it's
+    // read as packed data.
+    pub fn size(&self, packed: bool) -> Option<u64> {
+        let Self::File(path) = self else {
+            return None
+        };
+        if self.is_special() {
+            return None
+        }
+
+        match first_volume_base(path).filter(|_| packed) {
+            Some(base) => Some(
+                (0..).map_while(|index| std::fs::metadata(volume_path(&base, index)).ok()).map(|metadata| metadata.len()).sum()
+            ),
+            None => std::fs::metadata(path).ok().map(|metadata| metadata.len()),
+        }
+    }
+
+    // Without `force`, a terminal as stdin is read as an empty input.
+    pub fn open(&self, force: bool) -> io::Result<InputRead> {
+        match self {
+            Self::Stdin => {
+                if io::stdin().is_terminal() && !force {
+                    warn!("There are no pipes which the program reads from. The result will be empty.");
+                    return Ok(InputRead::Empty)
+                }
+
+                Ok(InputRead::Stdin(io::stdin().lock()))
+            }
+            Self::File(path) => {
+                let file = File::open(path)?;
+                #[cfg(all(target_os = "linux", feature = "io_uring"))]
+                if !self.is_special() {
+                    match crate::uring::UringRead::new(file.try_clone()?) {
+                        Ok(read) => return Ok(InputRead::Uring(Box::new(read))),
+                        Err(err) => info!("io_uring is unavailable, `{}` is read without it : {}", self, err),
+                    }
+                }
+
+                Ok(InputRead::File(file))
+            }
+        }
+    }
+
+    // Like `open`, but the volumes following a `.001` file are read after it.
+    pub fn open_packed(&self, force: bool) -> io::Result<InputRead> {
+        match self {
+            Self::File(path) => match first_volume_base(path) {
+                Some(base) => Ok(InputRead::Volumes(VolumesRead { file: File::open(path)?, base, index: 0 })),
+                None => self.open(force),
+            },
+            _ => self.open(force),
+        }
+    }
+
+    // Like `open`, but stdin and the special files are buffered so the input can be rewound,
+    // in memory up to `buffer_size` bytes.
+    pub fn open_seekable(&self, force: bool, buffer_size: u64) -> io::Result<InputRead> {
+        match self.open(force)? {
+            InputRead::Stdin(stdin) => buffer_stream(stdin, buffer_size),
+            InputRead::File(file) if self.is_special() => buffer_stream(file, buffer_size),
+            input_read => Ok(input_read)
+        }
+    }
+}
+
+fn buffer_stream<R: Read>(stream: R, buffer_size: u64) -> io::Result<InputRead> {
+    let mut buffer = Vec::new();
+    let mut stream = stream.take(buffer_size);
+    stream.read_to_end(&mut buffer)?;
+
+    let mut stream = stream.into_inner();
+    if (buffer.len() as u64) < buffer_size {
+        return Ok(InputRead::Memory(Cursor::new(buffer)))
+    }
+
+    info!("The input is bigger than {} bytes, buffering it in a temporary file...", buffer_size);
+
+    let mut file = tempfile::tempfile()?;
+    file.write_all(&buffer)?;
+    io::copy(&mut stream, &mut file)?;
+    file.rewind()?;
+
+    Ok(InputRead::File(file))
+}
+
+#[cfg(unix)]
+pub fn is_special_file(metadata: &Metadata) -> bool {
+    use std::os::unix::fs::FileTypeExt;
+
+    metadata.file_type().is_fifo() || metadata.file_type().is_char_device()
+}
+
+#[cfg(not(unix))]
+pub fn is_special_file(_metadata: &Metadata) -> bool {
+    false
+}
+
+pub enum InputRead {
+    Stdin(StdinLock<'static>),
+    File(File),
+    #[cfg(all(target_os = "linux", feature = "io_uring"))]
+    Uring(Box<crate::uring::UringRead>),
+    Memory(Cursor<Vec<u8>>),
+    Volumes(VolumesRead),
+    Empty
+}
+
+// The path without the `.001` of a first volume.
+pub fn first_volume_base(path: &Path) -> Option<PathBuf> {
+    (path.extension()? == "001").then(|| path.with_extension(""))
+}
+
+// The path of the volume at that index, starting from 0.
+pub fn volume_path(base: &Path, index: usize) -> PathBuf {
+    let mut path = base.as_os_str().to_owned();
+    path.push(format!(".{:03}", index + 1));
+
+    PathBuf::from(path)
+}
+
+// Reads the volumes one after the other, until the next one is missing.
+pub struct VolumesRead {
+    base: PathBuf,
+    index: usize,
+    file: File,
+}
+
+impl Read for VolumesRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let n = self.file.read(buf)?;
+            if n != 0 || buf.is_empty() {
+                return Ok(n)
+            }
+
+            let next_path = volume_path(&self.base, self.index + 1);
+            self.file = match File::open(&next_path) {
+                Ok(file) => file,
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+                Err(err) => return Err(err),
+            };
+            self.index += 1;
+            info!("Reading the volume `{}`...", next_path.display());
+        }
+    }
+}
+
+impl InputRead {
+    // Whether that's a regular file read directly.
+    pub fn is_file(&self) -> bool {
+        match self {
+            Self::File(_) => true,
+            #[cfg(all(target_os = "linux", feature = "io_uring"))]
+            Self::Uring(_) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Read for InputRead {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Self::Stdin(stdin) => stdin.read(buf),
+            Self::File(file) => file.read(buf),
+            #[cfg(all(target_os = "linux", feature = "io_uring"))]
+            Self::Uring(uring) => uring.read(buf),
+            Self::Memory(cursor) => cursor.read(buf),
+            Self::Volumes(volumes) => volumes.read(buf),
+            Self::Empty => Ok(0)
+        }
+    }
+}
+
+impl Seek for InputRead {
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Self::Stdin(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Can't seek on stdin")),
+            Self::File(file) => file.seek(pos),
+            #[cfg(all(target_os = "linux", feature = "io_uring"))]
+            Self::Uring(uring) => uring.seek(pos),
+            Self::Memory(cursor) => cursor.seek(pos),
+            Self::Volumes(_) => Err(io::Error::new(io::ErrorKind::Unsupported, "Can't seek on volumes")),
+            Self::Empty => Ok(0)
+        }
+    }
+}
+
+
+// Counts the bytes read, for the unpacking statistics.
+pub struct CountingRead<R: Read> {
+    inner: R,
+    pub count: u64,
+}
+
+impl<R: Read> CountingRead<R> {
+    pub fn new(inner: R) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<R: Read> Read for CountingRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+
+        Ok(n)
+    }
+}
+
+impl<R: Read + Seek> Seek for CountingRead<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        self.inner.seek(pos)
+    }
+}
+
+pub struct CountingWrite<W: Write> {
+    inner: W,
+    pub count: u64,
+}
+
+impl<W: Write> CountingWrite<W> {
+    pub fn new(inner: W) -> Self {
+        Self { inner, count: 0 }
+    }
+}
+
+impl<W: Write> Write for CountingWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.count += n as u64;
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// Fails when what is written differs from what `expected` reads, for `--verify-after-pack`.
+pub struct CompareWrite<R: Read> {
+    expected: R,
+    buffer: Vec<u8>,
+    pub position: u64,
+}
+
+impl<R: Read> CompareWrite<R> {
+    pub fn new(expected: R) -> Self {
+        Self { expected, buffer: Vec::new(), position: 0 }
+    }
+
+    // Fails when `expected` has more bytes than what was written.
+    pub fn finish(mut self) -> io::Result<()> {
+        if self.expected.read(&mut [0])? != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The data ends early, after {} bytes", self.position)))
+        }
+
+        Ok(())
+    }
+}
+
+impl<R: Read> Write for CompareWrite<R> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.resize(buf.len(), 0);
+        self.expected.read_exact(&mut self.buffer).map_err(|err| match err.kind() {
+            io::ErrorKind::UnexpectedEof => io::Error::new(io::ErrorKind::InvalidData, "The data is longer"),
+            _ => err,
+        })?;
+
+        if let Some(index) = buf.iter().zip(&self.buffer).position(|(byte, expected)| byte != expected) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, format!("The data differs at byte {}", self.position + index as u64)))
+        }
+        self.position += buf.len() as u64;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, Display, PartialEq, Eq)]
+pub enum Output {
+    #[display("<stdout>")]
+    Stdout,
+    #[display("{}", _0.display())]
+    File(PathBuf)
+}
+
+impl Output {
+    pub fn parse_value(str: &str) -> Result<Self, String> {
+        if str.trim() == "-" {
+            return Ok(Self::Stdout)
+        }
+
+        Ok(Self::File(PathBuf::from(str)))
+    }
+
+    pub fn open(&self, overwrite: bool) -> io::Result<OutputWrite> {
+        match self {
+            Self::Stdout => {
+                Ok(OutputWrite::Stdout(io::stdout().lock()))
+            }
+
+            Self::File(path) => {
+                // checked early to not do the work for nothing, `finish` checks it again.
+                if !overwrite && path.try_exists()? {
+                    return Err(io::Error::new(io::ErrorKind::AlreadyExists, "The file already exists"))
+                }
+
+                Ok(OutputWrite::File { file: temp_file_for(path)?, path: path.clone(), overwrite })
+            }
+        }
+    }
+
+    // Like `open`, but the data is split in volumes of at most `split_size` bytes.
+    pub fn open_volumes(&self, overwrite: bool, split_size: u64) -> io::Result<OutputWrite> {
+        let Self::File(path) = self else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "Volumes can only be written to files"))
+        };
+
+        if !overwrite && volume_path(path, 0).try_exists()? {
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, "The first volume already exists"))
+        }
+
+        Ok(OutputWrite::Volumes { volumes: Vec::new(), path: path.clone(), overwrite, split_size, volume_size: 0 })
+    }
+}
+
+// A temporary file next to `path`, so it can be renamed to it.
+fn temp_file_for(path: &Path) -> io::Result<NamedTempFile> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let prefix = format!(".{}.", path.file_name().unwrap_or_default().to_string_lossy());
+
+    let mut builder = tempfile::Builder::new();
+    builder.prefix(&prefix).suffix(".tmp");
+    // the default of temporary files is 0o600.
+    #[cfg(unix)]
+    builder.permissions(std::os::unix::fs::PermissionsExt::from_mode(0o666));
+
+    let file = builder.tempfile_in(dir)?;
+    crate::signals::register_output(file.path(), path);
+
+    Ok(file)
+}
+
+// Where the output of a failed file is kept, with `--keep-partial`.
+pub fn partial_path(path: &Path) -> PathBuf {
+    let mut partial_path = path.as_os_str().to_owned();
+    partial_path.push(".part");
+
+    PathBuf::from(partial_path)
+}
+
+// A write to stdout failed because its reader is gone, like with `| head`. Unlike the broken pipes
+// to the child processes, this isn't an error.
+#[derive(Debug, thiserror::Error)]
+#[error("The reader of stdout is gone")]
+pub struct StdoutClosed;
+
+// Marks the broken pipe of a write to stdout, so it can be told apart from the other ones.
+pub fn stdout_closed(err: io::Error) -> io::Error {
+    if err.kind() != io::ErrorKind::BrokenPipe {
+        return err
+    }
+    io::Error::new(io::ErrorKind::BrokenPipe, StdoutClosed)
+}
+
+pub enum OutputWrite {
+    Stdout(StdoutLock<'static>),
+    // Written next to the destination and renamed to it by `finish`, so the destination is
+    // never seen half-written. It's removed if it's dropped before.
+    File { file: NamedTempFile, path: PathBuf, overwrite: bool },
+    // Like `File`, for every volume. `volume_size` is the size of the last one.
+    Volumes { volumes: Vec<NamedTempFile>, path: PathBuf, overwrite: bool, split_size: u64, volume_size: u64 }
+}
+
+impl OutputWrite {
+    // Makes sure the data reached the disk, and moves a file to its destination.
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Self::Stdout(mut stdout) => stdout.flush().map_err(stdout_closed),
+            Self::File { file, path, overwrite } => {
+                file.as_file().sync_all()?;
+
+                let temp_path = file.path().to_path_buf();
+                let res = if overwrite { file.persist(&path) } else { file.persist_noclobber(&path) };
+                crate::signals::unregister_output(&temp_path);
+                res.map(|_| ()).map_err(|err| err.error)
+            }
+            Self::Volumes { mut volumes, path, overwrite, .. } => {
+                // an empty output still has its first volume.
+                if volumes.is_empty() {
+                    volumes.push(temp_file_for(&volume_path(&path, 0))?);
+                }
+
+                let count = volumes.len();
+                for (index, file) in volumes.into_iter().enumerate() {
+                    Self::File { file, path: volume_path(&path, index), overwrite }.finish()?;
+                }
+
+                // the volumes of a bigger previous output would be read after the new ones.
+                if overwrite {
+                    let mut index = count;
+                    while volume_path(&path, index).try_exists()? {
+                        std::fs::remove_file(volume_path(&path, index))?;
+                        index += 1;
+                    }
+                }
+
+                Ok(())
+            }
+        }
+    }
+
+    // Moves what was written before a failure next to the destination, with the `.part`
+    // extension, and returns the paths of the files kept.
+    pub fn keep_partial(self) -> io::Result<Vec<PathBuf>> {
+        let files = match self {
+            Self::Stdout(_) => return Ok(Vec::new()),
+            Self::File { file, path, overwrite } => vec![(file, path, overwrite)],
+            Self::Volumes { volumes, path, overwrite, .. } => volumes.into_iter()
+                .enumerate()
+                .map(|(index, file)| (file, volume_path(&path, index), overwrite))
+                .collect(),
+        };
+
+        let mut paths = Vec::new();
+        for (file, path, overwrite) in files {
+            let partial_path = partial_path(&path);
+
+            Self::File { file, path: partial_path.clone(), overwrite }.finish()?;
+            paths.push(partial_path);
+        }
+
+        Ok(paths)
+    }
+}
+
+impl Write for OutputWrite {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Self::File { file, .. } => file.write(buf),
+            Self::Stdout(stdout) => stdout.write(buf).map_err(stdout_closed),
+            Self::Volumes { volumes, path, split_size, volume_size, .. } => {
+                if volumes.is_empty() || *volume_size == *split_size {
+                    volumes.push(temp_file_for(&volume_path(path, volumes.len()))?);
+                    *volume_size = 0;
+                }
+
+                let amount = buf.len().min((*split_size - *volume_size) as usize);
+                let n = volumes.last_mut().unwrap().write(&buf[..amount])?;
+                *volume_size += n as u64;
+
+                Ok(n)
+            }
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Self::File { file, .. } => file.flush(),
+            Self::Stdout(stdout) => stdout.flush().map_err(stdout_closed),
+            Self::Volumes { volumes, .. } => volumes.last_mut().map_or(Ok(()), |file| file.flush()),
+        }
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod tests {
+    use std::path::PathBuf;
+
+    use clap::Parser;
+
+    use crate::args::Cli;
+
+    #[test]
+    fn output_files_appear_once_finished() {
+        use std::io::Write;
+
+        use super::Output;
+
+        create_temp_files!("a" => a_path_str in temp_dir);
+        let output = Output::File(temp_dir.path().join("b"));
+
+        let mut output_write = output.open(false).unwrap();
+        output_write.write_all(b"data").unwrap();
+        assert!(!temp_dir.path().join("b").exists());
+
+        output_write.finish().unwrap();
+        assert_eq!(std::fs::read(temp_dir.path().join("b")).unwrap(), b"data");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+
+        let existing = Output::File(PathBuf::from(&a_path_str));
+        assert_eq!(existing.open(false).err().map(|err| err.kind()), Some(std::io::ErrorKind::AlreadyExists));
+
+        // a dropped output leaves the destination untouched.
+        let mut output_write = existing.open(true).unwrap();
+        output_write.write_all(b"data").unwrap();
+        drop(output_write);
+        assert_eq!(std::fs::read(&a_path_str).unwrap(), b"");
+        assert_eq!(std::fs::read_dir(temp_dir.path()).unwrap().count(), 2);
+    }
+
+    #[test]
+    fn compare_write_finds_the_differences() {
+        use std::io::Write;
+
+        use super::CompareWrite;
+
+        let mut compare_write = CompareWrite::new(&b"abcd"[..]);
+        compare_write.write_all(b"ab").unwrap();
+        compare_write.write_all(b"cd").unwrap();
+        assert!(compare_write.finish().is_ok());
+
+        let mut compare_write = CompareWrite::new(&b"abcd"[..]);
+        assert!(compare_write.write_all(b"abd").is_err());
+
+        let mut compare_write = CompareWrite::new(&b"abcd"[..]);
+        compare_write.write_all(b"abc").unwrap();
+        assert!(compare_write.finish().is_err());
+
+        let mut compare_write = CompareWrite::new(&b"ab"[..]);
+        assert!(compare_write.write_all(b"abc").is_err());
+    }
+
+    #[test]
+    fn progress_counts_every_volume() {
+        create_temp_files!("a" => a_path_str, "b.hc.001" => first_volume_str in temp_dir);
+        std::fs::write(&a_path_str, [0; 10]).unwrap();
+        std::fs::write(&first_volume_str, [0; 4]).unwrap();
+        std::fs::write(temp_dir.path().join("b.hc.002"), [0; 3]).unwrap();
+
+        let a = super::Input::File(a_path_str.into());
+        assert_eq!(a.size(false), Some(10));
+        let first_volume = super::Input::File(first_volume_str.into());
+        assert_eq!(first_volume.size(true), Some(7));
+        assert_eq!(first_volume.size(false), Some(4));
+        assert_eq!(super::Input::Stdin.size(true), None);
+
+        assert!(Cli::try_parse_from(["", "pack", "a", "--progress=json"]).is_ok());
+        assert!(Cli::try_parse_from(["", "pack", "a", "--progress=bar"]).is_err());
+        #[cfg(unix)]
+        assert!(Cli::try_parse_from(["", "pack", "a", "--progress-fd", "3"]).is_err());
+    }
+}
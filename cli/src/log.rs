@@ -0,0 +1,137 @@
+use std::{cell::RefCell, fs::File, io::Write};
+
+use colog::format::CologStyle;
+use env_logger::fmt::Formatter;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use once_cell::race::OnceBool;
+
+struct ColorFormatter;
+
+impl CologStyle for ColorFormatter {
+    fn level_token(&self, level: &Level) -> &str {
+        match level {
+            Level::Error => "Error",
+            Level::Warn => "Warning",
+            Level::Info => "Info",
+            Level::Debug => "Debug",
+            Level::Trace => "Trace",
+        }
+    }
+    fn prefix_token(&self, level: &Level) -> String {
+        self.level_color(level, &format!("{: >7}", self.level_token(level))) + &input_tag()
+    }
+    fn line_separator(&self) -> String {
+        "\n".to_string() + &" ".repeat(7)
+    }
+}
+
+static ACTIVE: OnceBool = OnceBool::new();
+
+fn custom_format(buf: &mut Formatter, record: &Record<'_>) -> Result<(), std::io::Error> {
+    if ACTIVE.get().unwrap() {
+        let color_formatter = ColorFormatter;
+        color_formatter.format(buf, record)
+    } else {
+        Ok(())
+    }
+}
+
+fn file_format(buf: &mut Formatter, record: &Record<'_>) -> Result<(), std::io::Error> {
+    let message = record.args().to_string().replace('\n', &ColorFormatter.line_separator());
+    writeln!(buf, "{} {: >7}{} {}", buf.timestamp_seconds(), ColorFormatter.level_token(&record.level()), input_tag(), message)
+}
+
+thread_local! {
+    // Per thread, as the files processed in parallel log at the same time.
+    static WARNINGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static INPUT: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+fn input_tag() -> String {
+    INPUT.with_borrow(|input| input.as_ref().map_or(String::new(), |input| format!(" [{}]", input)))
+}
+
+// Tags the lines logged by the current thread with the input it processes, so the ones of the
+// files processed in parallel can be told apart.
+pub fn set_input(input: Option<String>) {
+    INPUT.set(input);
+}
+
+// Keeps the warnings, whatever the filter, so they can be reported with the results.
+struct CapturingLogger {
+    inner: env_logger::Logger,
+    file: Option<env_logger::Logger>,
+}
+
+impl Log for CapturingLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() == Level::Warn || self.inner.enabled(metadata)
+            || self.file.as_ref().is_some_and(|file| file.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        if record.level() == Level::Warn {
+            WARNINGS.with_borrow_mut(|warnings| warnings.push(record.args().to_string()));
+        }
+
+        self.inner.log(record);
+        if let Some(file) = &self.file {
+            file.log(record);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+        if let Some(file) = &self.file {
+            file.flush();
+        }
+    }
+}
+
+// When inactive, the errors must be printed to stderr to be seen.
+pub fn is_active() -> bool {
+    ACTIVE.get().unwrap_or(false)
+}
+
+// The warnings logged by the current thread since the last call.
+pub fn take_warnings() -> Vec<String> {
+    WARNINGS.take()
+}
+
+fn filter(builder: &mut env_logger::Builder, level: Option<LevelFilter>) {
+    match level {
+        Some(level) => builder.filter_level(level),
+        None => builder
+            .filter_level(LevelFilter::Info)
+            .parse_env(env_logger::Env::new().filter("RUST_LOG")),
+    };
+}
+
+// The logs are written to stderr, stdout only gets the data and the reports.
+// Without a level from the flags, `RUST_LOG` is used, and the default is `Info`.
+pub fn init(active: bool, level: Option<LevelFilter>, colors: bool, file: Option<File>) {
+    ACTIVE.set(active).unwrap();
+
+    // the formatter checks stdout otherwise.
+    colored::control::set_override(colors);
+
+    let write_style = if colors { env_logger::WriteStyle::Always } else { env_logger::WriteStyle::Never };
+    let mut builder = colog::basic_builder();
+    builder.format(custom_format).target(env_logger::Target::Stderr).write_style(write_style);
+    filter(&mut builder, level);
+    let logger = builder.build();
+
+    let file = file.map(|file| {
+        let mut builder = env_logger::Builder::new();
+        builder
+            .format(file_format)
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .write_style(env_logger::WriteStyle::Never);
+        filter(&mut builder, level);
+        builder.build()
+    });
+
+    // both loggers have the same filter.
+    log::set_max_level(logger.filter().max(LevelFilter::Warn));
+    log::set_boxed_logger(Box::new(CapturingLogger { inner: logger, file })).unwrap();
+}
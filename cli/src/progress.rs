@@ -0,0 +1,159 @@
+use std::{cell::RefCell, fs::File, io::{self, Read, Seek, Write}, rc::Rc, sync::{Mutex, OnceLock}, time::{Duration, Instant}};
+
+const INTERVAL: Duration = Duration::from_millis(500);
+
+enum Output {
+    Stderr,
+    File(File),
+}
+
+static OUTPUT: OnceLock<Mutex<Output>> = OnceLock::new();
+
+// Writes the records to stderr, or to that file, like a descriptor given by `--progress-fd`.
+pub fn init(file: Option<File>) {
+    let output = file.map_or(Output::Stderr, Output::File);
+    let _ = OUTPUT.set(Mutex::new(output));
+}
+
+#[derive(serde::Serialize)]
+struct Record<'a> {
+    input: &'a str,
+    bytes_in: u64,
+    bytes_out: u64,
+    // Unknown when the size of the input is, like for stdin.
+    percent: Option<f64>,
+    eta_secs: Option<f64>,
+    elapsed_secs: f64,
+    done: bool,
+}
+
+pub struct Progress {
+    input: String,
+    total: Option<u64>,
+    // Packing reads the input twice, once to count the bytes.
+    passes: u64,
+    start: Instant,
+    last: Instant,
+    read: u64,
+    position: u64,
+    written: u64,
+}
+
+pub type Shared = Option<Rc<RefCell<Progress>>>;
+
+// `None` when the progress isn't reported.
+pub fn start(input: &impl ToString, total: Option<u64>, passes: u64) -> Shared {
+    OUTPUT.get()?;
+
+    let now = Instant::now();
+    Some(Rc::new(RefCell::new(Progress { input: input.to_string(), total, passes, start: now, last: now, read: 0, position: 0, written: 0 })))
+}
+
+pub fn finish(progress: &Shared) {
+    if let Some(progress) = progress {
+        progress.borrow().write_record(true);
+    }
+}
+
+impl Progress {
+    fn fraction(&self) -> Option<f64> {
+        let work = self.total? * self.passes;
+        if work == 0 {
+            return None
+        }
+
+        Some((self.read as f64 / work as f64).min(1.0))
+    }
+
+    fn tick(&mut self) {
+        if self.last.elapsed() >= INTERVAL {
+            self.last = Instant::now();
+            self.write_record(false);
+        }
+    }
+
+    fn write_record(&self, done: bool) {
+        let Some(output) = OUTPUT.get() else {
+            return
+        };
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let fraction = if done { Some(1.0) } else { self.fraction() };
+        let record = Record {
+            input: &self.input,
+            bytes_in: self.position,
+            bytes_out: self.written,
+            percent: fraction.map(|fraction| fraction * 100.0),
+            eta_secs: fraction.filter(|fraction| *fraction > 0.0).map(|fraction| elapsed * (1.0 - fraction) / fraction),
+            elapsed_secs: elapsed,
+            done,
+        };
+
+        // one write per line, so the records of parallel files aren't mixed.
+        let line = format!("{}\n", serde_json::to_string(&record).unwrap());
+        let _ = match &mut *output.lock().unwrap() {
+            Output::Stderr => io::stderr().write_all(line.as_bytes()),
+            Output::File(file) => file.write_all(line.as_bytes()),
+        };
+    }
+}
+
+pub struct ProgressRead<R> {
+    inner: R,
+    progress: Shared,
+}
+
+pub fn read<R: Read>(inner: R, progress: &Shared) -> ProgressRead<R> {
+    ProgressRead { inner, progress: progress.clone() }
+}
+
+impl<R: Read> Read for ProgressRead<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        if let Some(progress) = &self.progress {
+            let mut progress = progress.borrow_mut();
+            progress.read += n as u64;
+            progress.position += n as u64;
+            progress.tick();
+        }
+
+        Ok(n)
+    }
+}
+
+impl<R: Seek> Seek for ProgressRead<R> {
+    fn seek(&mut self, pos: io::SeekFrom) -> io::Result<u64> {
+        let position = self.inner.seek(pos)?;
+        if let Some(progress) = &self.progress {
+            progress.borrow_mut().position = position;
+        }
+
+        Ok(position)
+    }
+}
+
+pub struct ProgressWrite<W> {
+    inner: W,
+    progress: Shared,
+}
+
+pub fn write<W: Write>(inner: W, progress: &Shared) -> ProgressWrite<W> {
+    ProgressWrite { inner, progress: progress.clone() }
+}
+
+impl<W: Write> Write for ProgressWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        if let Some(progress) = &self.progress {
+            let mut progress = progress.borrow_mut();
+            progress.written += n as u64;
+            progress.tick();
+        }
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
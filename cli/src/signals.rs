@@ -0,0 +1,62 @@
+use std::{path::{Path, PathBuf}, sync::Mutex};
+
+// The temporary files not moved to their destinations yet, with those destinations.
+static OUTPUTS: Mutex<Vec<(PathBuf, PathBuf)>> = Mutex::new(Vec::new());
+
+pub fn register_output(temp_path: &Path, destination: &Path) {
+    OUTPUTS.lock().unwrap().push((temp_path.to_path_buf(), destination.to_path_buf()));
+}
+
+pub fn unregister_output(temp_path: &Path) {
+    OUTPUTS.lock().unwrap().retain(|(path, _)| path != temp_path);
+}
+
+// Must be called before any other thread is spawned, so they all block the signals, which are
+// then received by a thread waiting for them.
+#[cfg(unix)]
+pub fn install(keep_partial: bool) {
+    // SAFETY: the set is initialized by `sigemptyset` before being used.
+    let set = unsafe {
+        let mut set = std::mem::MaybeUninit::<libc::sigset_t>::uninit();
+        libc::sigemptyset(set.as_mut_ptr());
+        for signal in [libc::SIGINT, libc::SIGTERM, libc::SIGHUP] {
+            libc::sigaddset(set.as_mut_ptr(), signal);
+        }
+        let set = set.assume_init();
+        libc::pthread_sigmask(libc::SIG_BLOCK, &set, std::ptr::null_mut());
+
+        set
+    };
+
+    std::thread::spawn(move || {
+        let mut signal = 0;
+        // SAFETY: the set was initialized above.
+        if unsafe { libc::sigwait(&set, &mut signal) } == 0 {
+            interrupt(signal, keep_partial);
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub fn install(_keep_partial: bool) {}
+
+#[cfg(unix)]
+fn interrupt(signal: libc::c_int, keep_partial: bool) -> ! {
+    log::warn!("Interrupted, {} the partial outputs.", if keep_partial { "keeping" } else { "removing" });
+
+    for (temp_path, destination) in OUTPUTS.lock().unwrap().drain(..) {
+        let res = match keep_partial {
+            true => std::fs::rename(&temp_path, crate::io::partial_path(&destination)),
+            false => std::fs::remove_file(&temp_path),
+        };
+        match res {
+            // the file was moved to its destination in the meantime.
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => log::warn!("Failed to clean `{}` up : {}", temp_path.display(), err),
+            Ok(()) => {}
+        }
+    }
+
+    log::logger().flush();
+    std::process::exit(128 + signal)
+}
@@ -0,0 +1,129 @@
+use std::{fs::File, io::{self, Read, Seek, SeekFrom}, os::fd::AsRawFd};
+
+use io_uring::{opcode, types, IoUring};
+
+const CHUNK_LEN: usize = 1 << 20;
+
+pub struct UringRead {
+    file: File,
+    ring: IoUring,
+    // the chunk being used, and the one the next chunk is read into.
+    chunks: [Vec<u8>; 2],
+    current: usize,
+    // the part of the current chunk which wasn't read yet.
+    start: usize,
+    end: usize,
+    // where the next chunk starts in the file.
+    offset: u64,
+    in_flight: bool,
+}
+
+impl UringRead {
+    // Fails where io_uring isn't available, like on old kernels or in some containers.
+    pub fn new(file: File) -> io::Result<Self> {
+        let mut read = Self {
+            file,
+            ring: IoUring::new(2)?,
+            chunks: [vec![0; CHUNK_LEN], vec![0; CHUNK_LEN]],
+            current: 0,
+            start: 0,
+            end: 0,
+            offset: 0,
+            in_flight: false,
+        };
+        read.submit_next()?;
+
+        Ok(read)
+    }
+
+    fn submit_next(&mut self) -> io::Result<()> {
+        let next = &mut self.chunks[1 - self.current];
+        let entry = opcode::Read::new(types::Fd(self.file.as_raw_fd()), next.as_mut_ptr(), next.len() as u32)
+            .offset(self.offset)
+            .build();
+
+        // the chunk is left untouched until the read completes, which is waited for before dropping it.
+        unsafe { self.ring.submission().push(&entry) }.map_err(io::Error::other)?;
+        self.ring.submit()?;
+        self.in_flight = true;
+
+        Ok(())
+    }
+
+    // Waits for the read in flight, returning the amount of bytes read.
+    fn wait(&mut self) -> io::Result<usize> {
+        let entry = loop {
+            match self.ring.submit_and_wait(1) {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+                Err(err) => return Err(err),
+            }
+            if let Some(entry) = self.ring.completion().next() {
+                break entry
+            }
+        };
+        self.in_flight = false;
+
+        match entry.result() {
+            n if n >= 0 => Ok(n as usize),
+            errno => Err(io::Error::from_raw_os_error(-errno)),
+        }
+    }
+}
+
+impl Read for UringRead {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.start == self.end {
+            if !self.in_flight {
+                return Ok(0)
+            }
+
+            let n = self.wait()?;
+            self.current = 1 - self.current;
+            self.start = 0;
+            self.end = n;
+            self.offset += n as u64;
+            if n == 0 {
+                return Ok(0)
+            }
+            self.submit_next()?;
+        }
+
+        let n = buf.len().min(self.end - self.start);
+        buf[..n].copy_from_slice(&self.chunks[self.current][self.start..self.start + n]);
+        self.start += n;
+
+        Ok(n)
+    }
+}
+
+impl Seek for UringRead {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let position = self.offset - (self.end - self.start) as u64;
+        let target = match pos {
+            SeekFrom::Start(target) => Some(target),
+            SeekFrom::Current(delta) => position.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.file.metadata()?.len().checked_add_signed(delta),
+        };
+        let target = target.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "Invalid seek to a negative or overflowing position"))?;
+
+        if self.in_flight {
+            self.wait()?;
+        }
+        self.start = 0;
+        self.end = 0;
+        self.offset = target;
+        self.submit_next()?;
+
+        Ok(target)
+    }
+}
+
+impl Drop for UringRead {
+    fn drop(&mut self) {
+        // the kernel could still write to the chunk otherwise.
+        if self.in_flight {
+            let _ = self.wait();
+        }
+    }
+}
@@ -0,0 +1,65 @@
+// Runs the program with pipes as stdin and stdout, which must be read and written as binary data
+// on every platform, without any newline or end of file translation.
+
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+};
+
+fn run(args: &[&str], input: &[u8]) -> Vec<u8> {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_cli"))
+        .args(args)
+        .env_remove("HC_OPTS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // written from another thread, the program may write its output before reading everything.
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+
+    let output = child.wait_with_output().unwrap();
+    writer.join().unwrap().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+
+    output.stdout
+}
+
+// The bytes text modes translate : new lines, carriage returns, and the end of file of Windows.
+fn sample() -> Vec<u8> {
+    let mut data = b"line\r\nother line\n\x1a\r\r\n\0\xff".repeat(200);
+    data.extend((0..=255).collect::<Vec<u8>>());
+
+    data
+}
+
+#[test]
+fn packed_data_round_trips_through_pipes() {
+    let data = sample();
+
+    let packed = run(&["pack", "-", "--stdout", "--force-expand", "--no-config"], &data);
+    assert!(!packed.is_empty());
+
+    assert_eq!(run(&["unpack", "-", "--stdout", "--no-config"], &packed), data);
+    assert_eq!(run(&["cat", "-", "--no-config"], &packed), data);
+}
+
+#[test]
+fn checksums_are_verified_through_pipes() {
+    let data = sample();
+
+    let packed = run(&["pack", "-", "-c", "--checksum=xxh64", "--no-config"], &data);
+    assert_eq!(run(&["unpack", "-", "-c", "--no-config"], &packed), data);
+}
+
+#[test]
+fn dry_runs_read_from_pipes() {
+    let data = sample();
+
+    assert!(run(&["pack", "-", "--dry-run", "--force-expand", "--no-config"], &data).is_empty());
+    // the signature of gzip, which is looked for before counting.
+    assert!(run(&["pack", "-", "--dry-run", "--no-config"], b"\x1f\x8b\x08\0\0\0\0\0").is_empty());
+}
@@ -0,0 +1,37 @@
+#![no_main]
+
+use std::{fmt::Debug, io::Cursor};
+
+use bitpack::{
+    compact::{CompactNumberU128, CompactNumberU64, VarintU64},
+    BitRead, BitReadable, BitReader, BitWritable, BitWrite, BitWriter, UBits,
+};
+use libfuzzer_sys::fuzz_target;
+
+// Whatever was successfully read must survive a write and a read back. Malformed inputs, like
+// length prefixes bigger than the data, must fail cleanly instead of panicking or allocating too much.
+fn check_readable<T: BitReadable + BitWritable + PartialEq + Debug>(data: &[u8]) {
+    let mut reader = BitReader::new(Cursor::new(data));
+
+    while let Ok(value) = reader.read_readable::<T>() {
+        let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+        writer.write_writable(&value).unwrap();
+        writer.flush().unwrap();
+
+        let bytes = writer.into_inner().into_inner();
+        let mut value_reader = BitReader::new(Cursor::new(bytes.as_slice()));
+        assert_eq!(value_reader.read_readable::<T>().unwrap(), value);
+    }
+}
+
+fuzz_target!(|data: &[u8]| {
+    check_readable::<CompactNumberU64>(data);
+    check_readable::<CompactNumberU128>(data);
+    check_readable::<VarintU64>(data);
+    check_readable::<UBits<1>>(data);
+    check_readable::<UBits<13>>(data);
+    check_readable::<UBits<64>>(data);
+    check_readable::<String>(data);
+    check_readable::<Vec<CompactNumberU64>>(data);
+    check_readable::<Vec<UBits<5>>>(data);
+});
@@ -0,0 +1,46 @@
+#![no_main]
+
+use std::io::Cursor;
+
+use arbitrary::Unstructured;
+use bitpack::{test::WriteOp, BitReader, BitWrite, BitWriter};
+use libfuzzer_sys::fuzz_target;
+
+fn bit_amount(u: &mut Unstructured) -> arbitrary::Result<usize> {
+    u.int_in_range(0..=u8::BITS as usize)
+}
+
+fn write_op(u: &mut Unstructured) -> arbitrary::Result<WriteOp> {
+    let op = match u.int_in_range(0..=2)? {
+        0 => WriteOp::Bits { bits: u.arbitrary()?, amount: bit_amount(u)? },
+        1 => WriteOp::Byte(u.arbitrary()?),
+        _ => WriteOp::Bytes {
+            bytes: u.arbitrary()?,
+            last_byte_amount: if u.arbitrary()? { Some(bit_amount(u)?) } else { None },
+        },
+    };
+
+    Ok(op)
+}
+
+fuzz_target!(|data: &[u8]| {
+    let mut u = Unstructured::new(data);
+    let mut plan = Vec::new();
+    while !u.is_empty() {
+        match write_op(&mut u) {
+            Ok(op) => plan.push(op),
+            Err(_) => break,
+        }
+    }
+
+    let mut writer = BitWriter::new(Cursor::new(Vec::new()));
+    for op in &plan {
+        op.write(&mut writer).unwrap();
+    }
+    writer.flush().unwrap();
+
+    let mut reader = BitReader::new(Cursor::new(writer.into_inner().into_inner()));
+    for op in &plan {
+        assert_eq!(op.read_back(&mut reader).unwrap(), op.expected());
+    }
+});
@@ -0,0 +1,13 @@
+set -e
+
+# bitpack has to stay correct where usize is 32 bits wide, the WASM bindings depend on it. The
+# musl target links without a 32-bit libc installed.
+cargo test -p bitpack --target i686-unknown-linux-musl
+
+# needs a wasm runner, `wasmi` by default. The `should_panic` tests are ignored there, wasm aborts
+# on panics.
+export CARGO_TARGET_WASM32_WASIP1_RUNNER="${CARGO_TARGET_WASM32_WASIP1_RUNNER:-wasmi}"
+cargo test -p bitpack --target wasm32-wasip1
+cargo build -p bitpack --target wasm32-unknown-unknown
+
+echo Done
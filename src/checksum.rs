@@ -0,0 +1,134 @@
+use std::{fmt, io::{self, Write}};
+
+use xxhash_rust::xxh64::Xxh64;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ChecksumKind {
+    // The first version of the format is written, without a header giving the kind.
+    #[default]
+    None,
+    Crc32,
+    Xxh64,
+}
+
+impl ChecksumKind {
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Crc32 => 1,
+            Self::Xxh64 => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Self> {
+        [Self::None, Self::Crc32, Self::Xxh64].into_iter().find(|kind| kind.id() == id)
+    }
+
+    pub(crate) fn checksum_len(self) -> usize {
+        match self {
+            Self::None => 0,
+            Self::Crc32 => 4,
+            Self::Xxh64 => 8,
+        }
+    }
+
+    // The amount of bytes added to the packed data, the header giving the kind and the checksum
+    // after the data.
+    pub fn added_len(self) -> u64 {
+        match self {
+            Self::None => 0,
+            _ => (crate::FORMAT_HEADER_LEN + self.checksum_len()) as u64,
+        }
+    }
+}
+
+// The checksum of the unpacked data.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    Crc32(u32),
+    Xxh64(u64),
+}
+
+impl Checksum {
+    pub fn kind(&self) -> ChecksumKind {
+        match self {
+            Self::Crc32(_) => ChecksumKind::Crc32,
+            Self::Xxh64(_) => ChecksumKind::Xxh64,
+        }
+    }
+
+    pub(crate) fn trailer(&self) -> Vec<u8> {
+        match self {
+            Self::Crc32(value) => value.to_le_bytes().to_vec(),
+            Self::Xxh64(value) => value.to_le_bytes().to_vec(),
+        }
+    }
+
+    // Parses the trailer of a member whose header gives that kind.
+    pub(crate) fn from_trailer(kind: ChecksumKind, bytes: &[u8]) -> Option<Self> {
+        match kind {
+            ChecksumKind::None => None,
+            ChecksumKind::Crc32 => Some(Self::Crc32(u32::from_le_bytes(bytes.try_into().ok()?))),
+            ChecksumKind::Xxh64 => Some(Self::Xxh64(u64::from_le_bytes(bytes.try_into().ok()?))),
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Crc32(value) => write!(f, "crc32:{:08x}", value),
+            Self::Xxh64(value) => write!(f, "xxh64:{:016x}", value),
+        }
+    }
+}
+
+pub(crate) struct Hasher {
+    crc32: Option<crc32fast::Hasher>,
+    xxh64: Option<Xxh64>,
+}
+
+impl Hasher {
+    pub fn new(kind: ChecksumKind) -> Self {
+        Self {
+            crc32: (kind == ChecksumKind::Crc32).then(crc32fast::Hasher::new),
+            xxh64: (kind == ChecksumKind::Xxh64).then(|| Xxh64::new(0)),
+        }
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) {
+        if let Some(crc32) = &mut self.crc32 {
+            crc32.update(bytes);
+        }
+        if let Some(xxh64) = &mut self.xxh64 {
+            xxh64.update(bytes);
+        }
+    }
+
+    pub fn finish(&self, kind: ChecksumKind) -> Option<Checksum> {
+        match kind {
+            ChecksumKind::None => None,
+            ChecksumKind::Crc32 => Some(Checksum::Crc32(self.crc32.as_ref()?.clone().finalize())),
+            ChecksumKind::Xxh64 => Some(Checksum::Xxh64(self.xxh64.as_ref()?.digest())),
+        }
+    }
+}
+
+// Hashes what is written through it.
+pub(crate) struct HashingWrite<W: Write> {
+    pub inner: W,
+    pub hasher: Hasher,
+}
+
+impl<W: Write> Write for HashingWrite<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.hasher.update(&buf[..n]);
+
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,487 @@
+use std::io::{self, Read, Write};
+
+use bitpack::{BitRead, BitReader};
+
+use crate::{table::BYTE_TABLE_LEN, tree::{consts, HeapNode}};
+
+// The amount of bits looked up at once, longer codes are decoded one bit at a time.
+const LOOKUP_BITS: usize = 10;
+// The size of the chunks the decoded bytes are written in.
+const OUTPUT_CHUNK_LEN: usize = 64 * 1024;
+// A tree with a leaf for every byte. Trees read from a file can only be bigger when they are invalid.
+const MAX_NODES: usize = 2 * BYTE_TABLE_LEN - 1;
+// The root is at index 0, so it's never a child.
+const NO_CHILD: u16 = 0;
+
+// The tree in arrays indexed by node, which are faster to walk than the boxed nodes.
+struct FlatTree {
+    left: [u16; MAX_NODES],
+    right: [u16; MAX_NODES],
+    // `None` for the pairs and the empty node, which has no children either.
+    leaves: [Option<u8>; MAX_NODES],
+}
+
+impl FlatTree {
+    fn empty() -> Self {
+        Self { left: [NO_CHILD; MAX_NODES], right: [NO_CHILD; MAX_NODES], leaves: [None; MAX_NODES] }
+    }
+
+    // The index of a new node, the nodes before it being used.
+    fn next_index(len: &mut usize) -> io::Result<usize> {
+        if *len == MAX_NODES {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "The tree has too many nodes"));
+        }
+        *len += 1;
+
+        Ok(*len - 1)
+    }
+
+    // Also returns the longest code length.
+    fn new(tree_root: &HeapNode) -> io::Result<(Self, usize)> {
+        fn visit(node: &HeapNode, depth: usize, tree: &mut FlatTree, len: &mut usize, max_depth: &mut usize) -> io::Result<u16> {
+            let index = FlatTree::next_index(len)?;
+
+            match node {
+                HeapNode::Leaf(byte) => {
+                    tree.leaves[index] = Some(*byte);
+                    *max_depth = (*max_depth).max(depth);
+                }
+                HeapNode::Pair { left, right } => {
+                    tree.left[index] = visit(left, depth + 1, tree, len, max_depth)?;
+                    tree.right[index] = visit(right, depth + 1, tree, len, max_depth)?;
+                }
+                HeapNode::Empty => {}
+            }
+
+            Ok(index as u16)
+        }
+
+        let mut tree = Self::empty();
+        let mut max_depth = 0;
+        visit(tree_root, 0, &mut tree, &mut 0, &mut max_depth)?;
+
+        Ok((tree, max_depth))
+    }
+
+    // Reads the tree like `HeapNode::try_read_root`, but into the arrays instead of a box per node.
+    fn try_read<Br: BitRead>(reader: &mut Br) -> io::Result<Option<(Self, usize)>> {
+        fn read_node<Br: BitRead>(reader: &mut Br, depth: usize, tree: &mut FlatTree, len: &mut usize, max_depth: &mut usize) -> io::Result<u16> {
+            let index = FlatTree::next_index(len)?;
+
+            if reader.read_bits(consts::TYPE_FLAG_SIZE)? == consts::LEAF_FLAG {
+                tree.leaves[index] = Some(reader.read_byte()?);
+                *max_depth = (*max_depth).max(depth);
+            } else {
+                tree.left[index] = read_node(reader, depth + 1, tree, len, max_depth)?;
+                tree.right[index] = read_node(reader, depth + 1, tree, len, max_depth)?;
+            }
+
+            Ok(index as u16)
+        }
+
+        let Some(type_flag) = reader.try_read_bits(consts::TYPE_FLAG_SIZE)? else {
+            return Ok(None);
+        };
+
+        let mut tree = Self::empty();
+        let mut len = 1;
+        let mut max_depth = 1;
+        if type_flag == consts::LEAF_FLAG {
+            // a single leaf is the left child of the root, the right one being the empty node.
+            tree.leaves[1] = Some(reader.read_byte()?);
+            tree.left[0] = 1;
+            tree.right[0] = 2;
+        } else {
+            tree.left[0] = read_node(reader, 1, &mut tree, &mut len, &mut max_depth)?;
+            tree.right[0] = read_node(reader, 1, &mut tree, &mut len, &mut max_depth)?;
+        }
+
+        Ok(Some((tree, max_depth)))
+    }
+
+    // Like `HeapNode::code_lengths`.
+    fn code_lengths(&self) -> [u64; BYTE_TABLE_LEN] {
+        fn visit(tree: &FlatTree, node: u16, depth: u64, lengths: &mut [u64; BYTE_TABLE_LEN]) {
+            if let Some(byte) = tree.leaves[node as usize] {
+                lengths[byte as usize] = depth;
+            }
+            for bit in [consts::LEFT_BIT, consts::RIGHT_BIT] {
+                if let Some(child) = tree.child(node, bit) {
+                    visit(tree, child, depth + 1, lengths);
+                }
+            }
+        }
+
+        let mut lengths = [0; BYTE_TABLE_LEN];
+        visit(self, 0, 0, &mut lengths);
+
+        lengths
+    }
+
+    // `None` for leaves and the empty node.
+    fn child(&self, node: u16, bit: u8) -> Option<u16> {
+        let child = if bit == consts::LEFT_BIT { self.left[node as usize] } else { self.right[node as usize] };
+
+        (child != NO_CHILD).then_some(child)
+    }
+}
+
+// Decodes the codes of up to `LOOKUP_BITS` bits with a single lookup of the next bits.
+pub(crate) struct LookupDecoder {
+    tree: FlatTree,
+    max_code_length: usize,
+    // The byte of a tree with a single leaf, whose data is that byte repeated.
+    single_byte: Option<u8>,
+    // The byte and code length for every value of the next bits, `None` when the code is longer.
+    entries: Vec<Option<(u8, usize)>>,
+}
+
+impl LookupDecoder {
+    pub fn new(tree_root: &HeapNode) -> io::Result<Self> {
+        let (tree, max_code_length) = FlatTree::new(tree_root)?;
+
+        Ok(Self::from_tree(tree, max_code_length))
+    }
+
+    // Reads the tree at the start of a member, `None` when there are no more members.
+    pub fn try_read<Br: BitRead>(reader: &mut Br) -> io::Result<Option<Self>> {
+        Ok(FlatTree::try_read(reader)?.map(|(tree, max_code_length)| Self::from_tree(tree, max_code_length)))
+    }
+
+    // Like `HeapNode::leaf_stats`.
+    pub fn leaf_stats(&self) -> (usize, usize) {
+        (self.tree.leaves.iter().flatten().count(), self.max_code_length)
+    }
+
+    pub fn code_lengths(&self) -> [u64; BYTE_TABLE_LEN] {
+        self.tree.code_lengths()
+    }
+
+    fn from_tree(tree: FlatTree, max_code_length: usize) -> Self {
+        fn fill(tree: &FlatTree, node: u16, code: usize, depth: usize, entries: &mut [Option<(u8, usize)>]) {
+            if let Some(byte) = tree.leaves[node as usize] {
+                // every value starting with the code, the first bit being the lowest one.
+                for high_bits in 0..1 << (LOOKUP_BITS - depth) {
+                    entries[code | high_bits << depth] = Some((byte, depth));
+                }
+            } else if depth < LOOKUP_BITS {
+                for bit in [consts::LEFT_BIT, consts::RIGHT_BIT] {
+                    if let Some(child) = tree.child(node, bit) {
+                        fill(tree, child, code | (bit as usize) << depth, depth + 1, entries);
+                    }
+                }
+            }
+        }
+
+        let mut entries = vec![None; 1 << LOOKUP_BITS];
+        fill(&tree, 0, 0, 0, &mut entries);
+
+        // a leaf on the left of the root and the empty node on its right.
+        let single_byte = match (tree.child(0, consts::LEFT_BIT), tree.child(0, consts::RIGHT_BIT)) {
+            (Some(left), Some(right)) if tree.leaves[right as usize].is_none() && tree.child(right, consts::LEFT_BIT).is_none() => tree.leaves[left as usize],
+            _ => None,
+        };
+
+        Self { tree, max_code_length, single_byte, entries }
+    }
+
+    pub fn decode<R: Read, W: Write>(&self, bit_reader: &mut BitReader<R>, writer: &mut W, total_byte_count: u64) -> io::Result<u64> {
+        if let Some(byte) = self.single_byte {
+            return decode_run(byte, bit_reader, writer, total_byte_count);
+        }
+
+        let mut output = Vec::with_capacity(OUTPUT_CHUNK_LEN);
+        let result = self.decode_in_chunks(bit_reader, writer, &mut output, total_byte_count);
+        // the bytes decoded before an error are written too, so it's known where the data is corrupted.
+        writer.write_all(&output)?;
+
+        result.map(|()| total_byte_count)
+    }
+
+    fn decode_in_chunks<R: Read, W: Write>(&self, bit_reader: &mut BitReader<R>, writer: &mut W, output: &mut Vec<u8>, total_byte_count: u64) -> io::Result<()> {
+        for _ in 0..total_byte_count {
+            // near the end of the data, fewer bits are available and only the shorter codes match.
+            let (bits, available) = bit_reader.fill_lookahead(LOOKUP_BITS)?;
+            let byte = match self.entries[bits as usize] {
+                Some((byte, length)) if length <= available => {
+                    bit_reader.consume_bits(length)?;
+                    byte
+                }
+                _ => self.decode_long(bit_reader)?,
+            };
+
+            output.push(byte);
+            if output.len() == OUTPUT_CHUNK_LEN {
+                writer.write_all(output)?;
+                output.clear();
+            }
+        }
+
+        Ok(())
+    }
+
+    // Walks the tree along the next bits, read at once and only consumed up to the leaf. Codes longer
+    // than 64 bits and truncated data are read bit by bit, which gives the right error.
+    fn decode_long<R: Read>(&self, bit_reader: &mut BitReader<R>) -> io::Result<u8> {
+        if self.max_code_length <= u64::BITS as usize {
+            let (bits, available) = bit_reader.fill_lookahead(self.max_code_length)?;
+
+            let mut node = 0;
+            for depth in 0..=available {
+                if let Some(byte) = self.tree.leaves[node as usize] {
+                    bit_reader.consume_bits(depth)?;
+                    return Ok(byte);
+                }
+                if depth == available {
+                    break;
+                }
+                let Some(child) = self.tree.child(node, (bits >> depth) as u8 & 1) else {
+                    break;
+                };
+                node = child;
+            }
+        }
+
+        self.decode_bit_by_bit(bit_reader)
+    }
+
+    fn decode_bit_by_bit<R: Read>(&self, bit_reader: &mut BitReader<R>) -> io::Result<u8> {
+        let mut node = 0;
+
+        loop {
+            if let Some(byte) = self.tree.leaves[node as usize] {
+                return Ok(byte);
+            }
+
+            let child_bit = bit_reader.read_bits(1)?;
+            let Some(child) = self.tree.child(node, child_bit) else {
+                return Err(io::ErrorKind::InvalidData.into());
+            };
+            node = child;
+        }
+    }
+}
+
+// Every code of a tree with a single leaf is a left bit, so the bits are checked a byte at a time
+// and the byte written in big chunks.
+fn decode_run<R: Read, W: Write>(byte: u8, bit_reader: &mut BitReader<R>, writer: &mut W, total_byte_count: u64) -> io::Result<u64> {
+    let run = vec![byte; OUTPUT_CHUNK_LEN];
+
+    let mut remaining = total_byte_count;
+    while remaining > 0 {
+        let chunk_len = remaining.min(OUTPUT_CHUNK_LEN as u64) as usize;
+
+        let mut checked = 0;
+        while checked < chunk_len {
+            let amount = (chunk_len - checked).min(u8::BITS as usize);
+            // the left bit is 0, a 1 leads to the empty node.
+            let result = bit_reader.read_bits(amount).and_then(|bits| match bits {
+                consts::LEFT_BIT => Ok(()),
+                _ => Err(io::ErrorKind::InvalidData.into()),
+            });
+            if let Err(err) = result {
+                writer.write_all(&run[..checked])?;
+                return Err(err);
+            }
+            checked += amount;
+        }
+
+        writer.write_all(&run[..chunk_len])?;
+        remaining -= chunk_len as u64;
+    }
+
+    Ok(total_byte_count)
+}
+
+// The ways the data can be decoded, the lookup being the one used when unpacking. The others are
+// only kept to compare them in the benchmarks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeStrategy {
+    // One bit at a time along the boxed nodes.
+    TreeWalk,
+    // One bit at a time along the flattened tree.
+    FlatTree,
+    Lookup,
+}
+
+pub(crate) fn decode_with<R: Read, W: Write>(
+    strategy: DecodeStrategy,
+    tree_root: &HeapNode,
+    bit_reader: &mut BitReader<R>,
+    writer: &mut W,
+    total_byte_count: u64,
+) -> io::Result<u64> {
+    match strategy {
+        DecodeStrategy::TreeWalk => {
+            for _ in 0..total_byte_count {
+                let mut current_node = tree_root;
+                let byte = loop {
+                    match current_node {
+                        HeapNode::Leaf(byte) => break *byte,
+                        HeapNode::Pair { left, right } => {
+                            current_node = if bit_reader.read_bits(1)? == consts::LEFT_BIT { left } else { right };
+                        }
+                        HeapNode::Empty => return Err(io::ErrorKind::InvalidData.into()),
+                    }
+                };
+
+                writer.write_all(&[byte])?;
+            }
+
+            Ok(total_byte_count)
+        }
+        DecodeStrategy::FlatTree => {
+            let decoder = LookupDecoder::new(tree_root)?;
+            for _ in 0..total_byte_count {
+                writer.write_all(&[decoder.decode_bit_by_bit(bit_reader)?])?;
+            }
+
+            Ok(total_byte_count)
+        }
+        DecodeStrategy::Lookup => LookupDecoder::new(tree_root)?.decode(bit_reader, writer, total_byte_count),
+    }
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use std::io::Cursor;
+
+    use bitpack::{BitReader, BitWrite, BitWriter};
+
+    use crate::{pack_file, tree::HeapNode, unpack_file};
+
+    use super::{LookupDecoder, OUTPUT_CHUNK_LEN};
+
+    #[test]
+    fn codes_longer_than_the_lookup_are_decoded() {
+        // Fibonacci counts give codes of every length, up to 19 bits here.
+        let mut counts = vec![1, 1];
+        while counts.len() < 20 {
+            counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+        }
+        let data = counts.iter()
+            .enumerate()
+            .flat_map(|(byte, count)| std::iter::repeat_n(byte as u8, *count))
+            .rev()
+            .collect::<Vec<u8>>();
+
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(&data), &mut packed).unwrap();
+
+        let mut unpacked = Vec::new();
+        unpack_file(Cursor::new(packed), &mut unpacked).unwrap();
+        assert_eq!(unpacked, data);
+    }
+
+    #[test]
+    fn codes_longer_than_64_bits_are_decoded() {
+        // every byte is on the left of a chain of pairs, the last one being on the right too.
+        let mut tree_root = HeapNode::Leaf(70);
+        for byte in (0..70).rev() {
+            tree_root = HeapNode::Pair { left: Box::new(HeapNode::Leaf(byte)), right: Box::new(tree_root) };
+        }
+
+        let mut writer = BitWriter::new(Vec::new());
+        for (byte, is_last) in [(70, true), (3, false), (0, false)] {
+            for _ in 0..byte {
+                writer.write_bits(1, 1).unwrap();
+            }
+            if !is_last {
+                writer.write_bits(0, 1).unwrap();
+            }
+        }
+        writer.flush().unwrap();
+        let packed = writer.into_inner();
+
+        let mut unpacked = Vec::new();
+        LookupDecoder::new(&tree_root).unwrap().decode(&mut BitReader::new(&packed[..]), &mut unpacked, 3).unwrap();
+        assert_eq!(unpacked, [70, 3, 0]);
+
+        let mut unpacked = Vec::new();
+        let result = LookupDecoder::new(&tree_root).unwrap().decode(&mut BitReader::new(&packed[..4]), &mut unpacked, 3);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn trees_with_too_many_nodes_are_invalid() {
+        let mut tree_root = HeapNode::Leaf(0);
+        for byte in 0..300 {
+            tree_root = HeapNode::Pair { left: Box::new(HeapNode::Leaf(byte as u8)), right: Box::new(tree_root) };
+        }
+
+        let error = LookupDecoder::new(&tree_root).err().unwrap();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn single_byte_data_is_decoded_in_runs() {
+        let data = vec![b'a'; 100_000];
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(&data), &mut packed).unwrap();
+
+        let mut unpacked = Vec::new();
+        unpack_file(Cursor::new(&packed), &mut unpacked).unwrap();
+        assert_eq!(unpacked, data);
+
+        // a right bit leads to the missing leaf.
+        let len = packed.len();
+        packed[len - 2] = 0xff;
+        let error = unpack_file(Cursor::new(&packed), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::InvalidData);
+
+        let error = unpack_file(Cursor::new(&packed[..len / 2]), &mut Vec::new()).unwrap_err();
+        assert_eq!(error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn bytes_decoded_before_an_error_are_written() {
+        let data = b"abracadabra".repeat(20_000);
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(&data), &mut packed).unwrap();
+
+        let mut unpacked = Vec::new();
+        unpack_file(Cursor::new(&packed[..packed.len() / 2]), &mut unpacked).unwrap_err();
+        assert!(unpacked.len() > OUTPUT_CHUNK_LEN);
+        assert_eq!(unpacked, data[..unpacked.len()]);
+
+        let data = vec![b'a'; 100_000];
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(&data), &mut packed).unwrap();
+
+        let mut unpacked = Vec::new();
+        unpack_file(Cursor::new(&packed[..1000]), &mut unpacked).unwrap_err();
+        assert!(unpacked.len() > 7000);
+        assert_eq!(unpacked, data[..unpacked.len()]);
+    }
+
+    #[test]
+    fn read_trees_are_the_same_as_the_flattened_nodes() {
+        for data in [&b"aaaa"[..], b"ab", b"abbcccddddeeeeeffffff", b"the quick brown fox jumps over the lazy dog"] {
+            let mut packed = Vec::new();
+            pack_file(Cursor::new(data), &mut packed).unwrap();
+
+            let tree_root = HeapNode::try_read_root(&mut BitReader::new(&packed[..])).unwrap().unwrap();
+            let expected = LookupDecoder::new(&tree_root).unwrap();
+            let decoder = LookupDecoder::try_read(&mut BitReader::new(&packed[..])).unwrap().unwrap();
+
+            assert_eq!(decoder.tree.left, expected.tree.left);
+            assert_eq!(decoder.tree.right, expected.tree.right);
+            assert_eq!(decoder.tree.leaves, expected.tree.leaves);
+            assert_eq!(decoder.max_code_length, expected.max_code_length);
+            assert_eq!(decoder.single_byte, expected.single_byte);
+            assert_eq!(decoder.entries, expected.entries);
+            assert_eq!(decoder.leaf_stats(), tree_root.leaf_stats());
+            assert_eq!(decoder.code_lengths(), tree_root.code_lengths());
+        }
+    }
+
+    #[test]
+    fn reading_a_tree_with_too_many_nodes_fails() {
+        // pairs only, which never end.
+        let bytes = [0xFF; 128];
+
+        let err = LookupDecoder::try_read(&mut BitReader::new(&bytes[..])).err().unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,96 @@
+use std::io::{self, BufReader, Read, Write};
+
+use bitpack::{compact::CompactNumberU64, BitRead, BitReader, BitSink, BitWritable, BitWrite, LabeledBitWriter};
+
+use crate::{tree::{consts, HeapNode}, ChecksumKind};
+
+// Writes the nodes of the tree one after the other, each as a field labelled with its code.
+struct LabeledTree<'a>(&'a HeapNode);
+
+impl LabeledTree<'_> {
+    fn write_node<W: BitWrite>(writer: &mut W, node: &HeapNode, code: &mut Vec<u8>) -> io::Result<()> {
+        match node {
+            HeapNode::Leaf(byte) => writer.write_labeled(&format!("leaf {:#04x} {}", byte, code_str(code)), node),
+            HeapNode::Pair { left, right } => {
+                writer.write_labeled(format!("pair {}", code_str(code)).trim_end(), Bits(&[consts::PAIR_FLAG]))?;
+
+                code.push(consts::LEFT_BIT);
+                Self::write_node(writer, left, code)?;
+                code.pop();
+
+                code.push(consts::RIGHT_BIT);
+                Self::write_node(writer, right, code)?;
+                code.pop();
+
+                Ok(())
+            }
+            HeapNode::Empty => Err(io::ErrorKind::InvalidData.into()),
+        }
+    }
+}
+
+impl BitWritable for LabeledTree<'_> {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        Self::write_node(writer, self.0, &mut Vec::new())
+    }
+}
+
+// Single bits, in the order they were read.
+struct Bits<'a>(&'a [u8]);
+
+impl BitWritable for Bits<'_> {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        self.0.iter().try_for_each(|bit| writer.write_bits(*bit, 1))
+    }
+}
+
+fn code_str(code: &[u8]) -> String {
+    code.iter().map(|bit| char::from(b'0' + bit)).collect()
+}
+
+// Prints the layout of the first member of a packed file, to debug the format : the fields of the
+// headers with their offsets in bits, the nodes of the tree, and the first `codewords` codes of the
+// data with the bytes they decode to. The fields are written again to a `LabeledBitWriter`, which
+// gives the same bits as the format has a single encoding.
+pub fn dump_stream<R: Read, W: Write>(reader: R, mut out: W, codewords: u64) -> io::Result<()> {
+    let mut bit_reader = BitReader::new(BufReader::new(reader));
+
+    let Some((checksum_kind, tree_root, total_byte_count)) = crate::read_headers(&mut bit_reader)? else {
+        return writeln!(out, "empty");
+    };
+
+    let mut writer = LabeledBitWriter::new(BitSink::new());
+    if checksum_kind != ChecksumKind::None {
+        writer.write_labeled("format header", crate::FormatHeader(checksum_kind))?;
+    }
+    writer.write_labeled("tree", LabeledTree(&tree_root))?;
+    writer.write_labeled("byte count", CompactNumberU64(total_byte_count))?;
+    let header_bits = writer.bit_len();
+
+    let shown = codewords.min(total_byte_count);
+    for index in 0..shown {
+        let mut code = Vec::new();
+        let mut node = &tree_root;
+        let byte = loop {
+            match node {
+                HeapNode::Leaf(byte) => break *byte,
+                HeapNode::Pair { left, right } => {
+                    let bit = bit_reader.read_bits(1)?;
+                    code.push(bit);
+                    node = if bit == consts::LEFT_BIT { left } else { right };
+                }
+                HeapNode::Empty => return Err(io::ErrorKind::InvalidData.into()),
+            }
+        };
+
+        writer.write_labeled(&format!("code {} {} -> {:#04x}", index, code_str(&code), byte), Bits(&code))?;
+    }
+
+    writeln!(out, "{} bytes, {} header bits", total_byte_count, header_bits)?;
+    writer.dump(&mut out)?;
+    if shown < total_byte_count {
+        writeln!(out, "... {} more codes", total_byte_count - shown)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,158 @@
+use std::io::{self, Write};
+
+use bitpack::{BitWrite, BitWriter};
+
+use crate::tree::HuffmanCodeTable;
+
+const WORD_BITS: usize = u32::BITS as usize;
+// The size of the runs of zero bytes written for a single byte input.
+const RUN_CHUNK_LEN: usize = 64 * 1024;
+
+// Writes the codes of whole chunks at once. The bits are gathered in a word and sent to the
+// writer as bytes, instead of going through the bit writer for every code.
+pub(crate) struct WordEncoder<'t> {
+    code_table: &'t HuffmanCodeTable,
+    word: u64,
+    word_len: usize,
+    bytes: Vec<u8>,
+}
+
+impl<'t> WordEncoder<'t> {
+    pub fn new(code_table: &'t HuffmanCodeTable) -> Self {
+        Self { code_table, word: 0, word_len: 0, bytes: Vec::new() }
+    }
+
+    // `amount` is at most `WORD_BITS`.
+    fn push(&mut self, bits: u64, amount: usize) {
+        self.word |= bits << self.word_len;
+        self.word_len += amount;
+
+        if self.word_len >= WORD_BITS {
+            self.bytes.extend_from_slice(&(self.word as u32).to_le_bytes());
+            self.word >>= WORD_BITS;
+            self.word_len -= WORD_BITS;
+        }
+    }
+
+    // Encodes `buf` after the bits already written, the last partial byte stays in the writer.
+    pub fn write_chunk<W: Write>(&mut self, writer: &mut BitWriter<W>, buf: &[u8]) -> io::Result<()> {
+        let (pending, pending_len) = writer.take_pending();
+        self.word = pending as u64;
+        self.word_len = pending_len;
+
+        for byte in buf {
+            let code = self.code_table[*byte as usize].unwrap();
+            if code.len() <= WORD_BITS {
+                self.push(code.bits(), code.len());
+            } else {
+                self.push(code.bits() & u32::MAX as u64, WORD_BITS);
+                self.push(code.bits() >> WORD_BITS, code.len() - WORD_BITS);
+            }
+        }
+
+        while self.word_len >= u8::BITS as usize {
+            self.bytes.push(self.word as u8);
+            self.word >>= u8::BITS;
+            self.word_len -= u8::BITS as usize;
+        }
+
+        writer.aligned_writer().write_all(&self.bytes)?;
+        self.bytes.clear();
+
+        writer.write_bits(self.word as u8, self.word_len)
+    }
+}
+
+// Writes the codes of `count` bytes of a tree with a single leaf, whose code is a single 0 bit,
+// without encoding them one by one.
+pub(crate) fn write_run<W: Write>(writer: &mut BitWriter<W>, count: u64) -> io::Result<()> {
+    let (pending, pending_len) = writer.take_pending();
+    let total_bits = pending_len as u64 + count;
+    let mut byte_amount = total_bits / u8::BITS as u64;
+    if byte_amount == 0 {
+        return writer.write_bits(pending, total_bits as usize);
+    }
+
+    let mut run = vec![0; byte_amount.min(RUN_CHUNK_LEN as u64) as usize];
+    run[0] = pending;
+    while byte_amount > 0 {
+        let n = byte_amount.min(run.len() as u64) as usize;
+        writer.aligned_writer().write_all(&run[..n])?;
+        run[0] = 0;
+        byte_amount -= n as u64;
+    }
+
+    writer.write_bits(0, (total_bits % u8::BITS as u64) as usize)
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use bitpack::{BitWrite, BitWriter};
+
+    use crate::{table::BYTE_TABLE_LEN, tree::get_huffman_tree_and_codes};
+
+    use super::{write_run, WordEncoder, RUN_CHUNK_LEN};
+
+    // Writes `data` after `offset` bits with the encoder and one code at a time.
+    fn encode_both_ways(counts: &[u64], data: &[u8], offset: usize) -> (Vec<u8>, Vec<u8>) {
+        let mut byte_table = [0; BYTE_TABLE_LEN];
+        byte_table[..counts.len()].copy_from_slice(counts);
+        let (_, code_table) = get_huffman_tree_and_codes(byte_table).unwrap();
+
+        let mut expected = BitWriter::new(Vec::new());
+        expected.write_bits(0b101, offset).unwrap();
+        for byte in data {
+            expected.write_writable(code_table[*byte as usize].as_ref().unwrap()).unwrap();
+        }
+        expected.flush().unwrap();
+
+        let mut actual = BitWriter::new(Vec::new());
+        actual.write_bits(0b101, offset).unwrap();
+        let mut encoder = WordEncoder::new(&code_table);
+        for chunk in data.chunks(7) {
+            encoder.write_chunk(&mut actual, chunk).unwrap();
+        }
+        actual.flush().unwrap();
+
+        (actual.into_inner(), expected.into_inner())
+    }
+
+    #[test]
+    fn chunks_are_encoded_like_single_codes() {
+        let data = b"abracadabra, abracadabra".map(|byte| byte % 8);
+        for offset in [0, 3] {
+            let (actual, expected) = encode_both_ways(&[1, 2, 3, 5, 8, 13, 21, 34], &data, offset);
+            assert_eq!(actual, expected);
+        }
+    }
+
+    #[test]
+    fn codes_longer_than_a_word_are_encoded() {
+        // Fibonacci counts give codes of every length.
+        let mut counts = vec![1, 1];
+        while counts.len() < 40 {
+            counts.push(counts[counts.len() - 1] + counts[counts.len() - 2]);
+        }
+        let data = (0..40).chain((0..40).rev()).collect::<Vec<u8>>();
+
+        let (actual, expected) = encode_both_ways(&counts, &data, 3);
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn runs_are_encoded_like_single_codes() {
+        for offset in [0, 3, 7] {
+            for count in [0, 1, 4, 5, 13, RUN_CHUNK_LEN * 8 * 2 + 3] {
+                let (_, expected) = encode_both_ways(&[1], &vec![0; count], offset);
+
+                let mut actual = BitWriter::new(Vec::new());
+                actual.write_bits(0b101, offset).unwrap();
+                write_run(&mut actual, count as u64).unwrap();
+                actual.flush().unwrap();
+
+                assert_eq!(actual.into_inner(), expected, "offset {} count {}", offset, count);
+            }
+        }
+    }
+}
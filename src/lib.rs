@@ -1,131 +1,871 @@
 #![cfg_attr(coverage_nightly, feature(coverage_attribute))]
 
-use std::io::{self, BufRead, BufReader, Read, Seek, Write};
+use std::{fmt, io::{self, BufRead, BufReader, BufWriter, Read, Seek, Write}};
 
-use bitpack::{compact::CompactNumberU64, BitRead, BitReader, BitWrite, BitWriter};
+use bitpack::{compact::CompactNumberU64, BitRead, BitReader, BitWritable, BitWrite, BitWriter};
 use log::*;
 
+mod checksum;
+mod decode;
+mod dump;
+mod encode;
 mod table;
 mod tree;
-use tree::HeapNode;
+pub use checksum::{Checksum, ChecksumKind};
+#[doc(hidden)]
+pub use decode::DecodeStrategy;
+pub use dump::dump_stream;
+use checksum::{Hasher, HashingWrite};
+use decode::LookupDecoder;
+use encode::WordEncoder;
+pub use table::{compute_entropy, get_byte_table, get_file_byte_table, ByteTable, BYTE_TABLE_LEN};
+pub use tree::HeapNode;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+
+// The members without a checksum are written with the first version, which has no version field,
+// and the ones with a checksum with the second.
+pub const FORMATS: &str = "1, 2 with a checksum (crc32, xxh64)";
+
+pub const FEATURES: &[&str] = &[
+    "checksums",
+    "concatenated files",
+    #[cfg(feature = "trace")]
+    "trace",
+];
 
-struct ByteCounter<W: Write> {
-    inner: W,
-    byte_count: u64, // TODO: find size of byte count
+pub fn pack_file<R: Read + Seek, W: Write>(reader: R, writer: W) -> io::Result<u64> {
+    pack_file_with_stats(reader, writer).map(|stats| stats.output_size)
 }
 
-impl<W: Write> ByteCounter<W> {
-    fn new(inner: W) -> Self {
-        Self {
-            inner,
-            byte_count: 0,
-        }
+#[derive(Clone, Debug, PartialEq)]
+pub struct PackStats {
+    pub input_size: u64,
+    pub output_size: u64,
+    // In bits per byte.
+    pub entropy: f32,
+}
+
+// Same as `pack_file`, but also returns what was measured during the counting pass.
+pub fn pack_file_with_stats<R: Read + Seek, W: Write>(reader: R, writer: W) -> io::Result<PackStats> {
+    pack_file_with_options(reader, writer, &PackOptions::default())
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PackOptions {
+    // Added after the packed data, with its kind in a header of the second version of the format,
+    // which the readers of the first version can't read.
+    pub checksum: ChecksumKind,
+    // Fails with `Expansion` before writing anything when the packed data would be bigger than
+    // the input.
+    pub refuse_expansion: bool,
+    // The counts of the bytes of the input, when they were computed beforehand like with
+    // `get_file_byte_table`. The input is then read only once, from its current position.
+    pub byte_table: Option<ByteTable>,
+}
+
+// The error of an input which doesn't get smaller when packed, with `refuse_expansion`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Expansion {
+    pub input_size: u64,
+    pub packed_size: u64,
+}
+
+impl fmt::Display for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Packing would expand the data from {} to {} bytes", self.input_size, self.packed_size)
     }
 }
 
-impl<W: Write> Write for ByteCounter<W> {
-    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-        let n = self.inner.write(buf)?;
-        self.byte_count += n as u64;
+impl std::error::Error for Expansion {}
+
+// The size up to which inputs are read only once when packing.
+const IN_MEMORY_INPUT_LEN: usize = 4 << 20;
 
-        Ok(n)
+fn write_data<R: BufRead, W: Write>(reader: &mut R, bit_writer: &mut BitWriter<W>, encoder: &mut WordEncoder<'_>, hasher: &mut Hasher) -> io::Result<()> {
+    loop {
+        let buf = reader.fill_buf()?;
+        if buf.is_empty() {
+            break;
+        }
+
+        encoder.write_chunk(bit_writer, buf)?;
+        hasher.update(buf);
+
+        let n = buf.len();
+        reader.consume(n);
     }
 
-    fn flush(&mut self) -> io::Result<()> {
-        self.inner.flush()
+    Ok(())
+}
+
+// Writes the data of an input made of a single byte, which isn't read again as it's only that
+// byte repeated.
+fn write_single_byte_data<W: Write>(byte: u8, total_byte_count: u64, bit_writer: &mut BitWriter<W>, hasher: &mut Hasher) -> io::Result<()> {
+    encode::write_run(bit_writer, total_byte_count)?;
+
+    let run = [byte; 8192];
+    let mut remaining = total_byte_count;
+    while remaining > 0 {
+        let n = remaining.min(run.len() as u64) as usize;
+        hasher.update(&run[..n]);
+        remaining -= n as u64;
     }
+
+    Ok(())
 }
 
-pub fn pack_file<R: Read + Seek, W: Write>(reader: R, writer: W) -> io::Result<u64> {
+pub fn pack_file_with_options<R: Read + Seek, W: Write>(reader: R, writer: W, options: &PackOptions) -> io::Result<PackStats> {
     let mut buf_reader = BufReader::new(reader);
-    let mut bit_writer = BitWriter::new(ByteCounter::new(writer));
+    // the headers and the trailer are written a byte at a time.
+    let mut bit_writer = BitWriter::new(BufWriter::new(writer));
 
     info!("Computing byte table...");
-    
-    let byte_table = table::get_byte_table(&mut buf_reader)?;
+
+    // small inputs are kept in memory while counting, so they are packed without reading them again.
+    let mut in_memory = Vec::new();
+    let mut fits_in_memory = false;
+    let byte_table = match options.byte_table {
+        Some(byte_table) => byte_table,
+        None => {
+            (&mut buf_reader).take(IN_MEMORY_INPUT_LEN as u64 + 1).read_to_end(&mut in_memory)?;
+            fits_in_memory = in_memory.len() <= IN_MEMORY_INPUT_LEN;
+
+            let mut byte_table = table::get_byte_table(&mut &in_memory[..])?;
+            if !fits_in_memory {
+                let rest = table::get_byte_table(&mut buf_reader)?;
+                byte_table.iter_mut().zip(rest).for_each(|(count, rest_count)| *count += rest_count);
+                buf_reader.rewind()?;
+            }
+            byte_table
+        }
+    };
     let total_byte_count = byte_table.iter().sum();
-    info!("File infos : \n - size : {} bytes\n - entropy : {}", total_byte_count, table::compute_entropy(byte_table));
+    let entropy = table::compute_entropy(byte_table);
+    info!("File infos : \n - size : {} bytes\n - entropy : {}", total_byte_count, entropy);
+
+    if options.refuse_expansion && total_byte_count != 0 {
+        let packed_size = estimate_packed_size(byte_table) + options.checksum.added_len();
+        if packed_size > total_byte_count {
+            return Err(io::Error::other(Expansion { input_size: total_byte_count, packed_size }));
+        }
+    }
 
     info!("Computing huffman tree...");
     let Some((tree_root, code_table)) = tree::get_huffman_tree_and_codes(byte_table) else {
-        return Ok(0);
+        return Ok(PackStats { input_size: 0, output_size: 0, entropy: 0.0 });
     };
     // dbg!(&tree_root);
 
     // dbg!(total_byte_count);
 
-    buf_reader.rewind()?;
-
     info!("Writing file headers...");
 
-    bit_writer.write_writable(tree_root)?;
-    bit_writer.write_writable(CompactNumberU64(total_byte_count))?;
+    if options.checksum != ChecksumKind::None {
+        bit_writer.write_labeled("format header", FormatHeader(options.checksum))?;
+    }
+    bit_writer.write_labeled("tree", tree_root)?;
+    bit_writer.write_labeled("byte count", CompactNumberU64(total_byte_count))?;
 
     info!("Writing data...");
 
-    loop {
-        let buf = buf_reader.fill_buf()?;
-        if buf.is_empty() {
-            break;
-        }
+    let mut hasher = Hasher::new(options.checksum);
+    let mut encoder = WordEncoder::new(&code_table);
+    // with an entropy of 0, the data is one byte repeated and its codes are all the same bit.
+    if let Some(byte) = byte_table.iter().position(|count| *count == total_byte_count) {
+        write_single_byte_data(byte as u8, total_byte_count, &mut bit_writer, &mut hasher)?;
+    } else if fits_in_memory {
+        write_data(&mut &in_memory[..], &mut bit_writer, &mut encoder, &mut hasher)?;
+    } else {
+        drop(in_memory);
+        write_data(&mut buf_reader, &mut bit_writer, &mut encoder, &mut hasher)?;
+    }
 
-        for byte in buf {
-            let code = code_table[*byte as usize].as_ref().unwrap();
-            bit_writer.write_writable(code)?;
-        }
+    bit_writer.flush()?;
 
-        let n = buf.len();
-        buf_reader.consume(n);
+    if let Some(checksum) = hasher.finish(options.checksum) {
+        bit_writer.write_bytes(&checksum.trailer(), None)?;
+        bit_writer.flush()?;
     }
+    bit_writer.aligned_writer().flush()?;
 
-    bit_writer.flush()?;
+    Ok(PackStats { input_size: total_byte_count, output_size: bit_writer.bytes_written(), entropy })
+}
 
-    Ok(bit_writer.into_inner().byte_count)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HeaderInfo {
+    pub original_size: u64,
+    pub distinct_symbols: usize,
+    pub max_code_length: usize,
+    // The size of the tree and the byte count.
+    pub header_bits: u64,
+    pub checksum: Option<Checksum>,
 }
 
-pub fn unpack_file<R: Read + Seek, W: Write>(reader: R, mut writer: W) -> io::Result<u64> {
-    let buf_reader = BufReader::new(reader);
-    let mut bit_reader = BitReader::new(buf_reader);
+// Members with a checksum start with a header of the second version of the format. It reads like a
+// tree of a single leaf, the version, for no bytes, which the first version never writes as empty
+// inputs give no member. The kind of the checksum fills the rest of its `FORMAT_HEADER_LEN` bytes.
+const FORMAT_VERSION: u8 = 2;
+pub(crate) const FORMAT_HEADER_LEN: usize = 4;
+const FORMAT_FLAGS_SIZE: usize = 7;
+
+pub(crate) struct FormatHeader(pub ChecksumKind);
+
+impl BitWritable for FormatHeader {
+    fn write<W: BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_bits(tree::consts::LEAF_FLAG, tree::consts::TYPE_FLAG_SIZE)?;
+        writer.write_byte(FORMAT_VERSION)?;
+        writer.write_writable(CompactNumberU64(0))?;
+        writer.write_bits(self.0.id(), FORMAT_FLAGS_SIZE)
+    }
+}
+
+// Consumes the header of the second version at the start of a member, if there's one, and returns
+// the kind of checksum it gives.
+fn read_format_header<R: Read>(bit_reader: &mut BitReader<R>) -> io::Result<ChecksumKind> {
+    let bytes = bit_reader.peek_bytes(FORMAT_HEADER_LEN)?;
+    let mut header = BitReader::new(&bytes[..]);
+    let is_header = |header: &mut BitReader<&[u8]>| -> io::Result<bool> {
+        Ok(header.read_bits(tree::consts::TYPE_FLAG_SIZE)? == tree::consts::LEAF_FLAG
+            && header.read_byte()? == FORMAT_VERSION
+            && header.read_readable::<CompactNumberU64>()?.0 == 0)
+    };
+    if !is_header(&mut header).unwrap_or(false) {
+        return Ok(ChecksumKind::None);
+    }
 
-    info!("Reading file headers...");
+    let kind = ChecksumKind::from_id(header.read_bits(FORMAT_FLAGS_SIZE)?)
+        .filter(|kind| *kind != ChecksumKind::None)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Unknown kind of checksum"))?;
+    bit_reader.consume_bits(FORMAT_HEADER_LEN * u8::BITS as usize)?;
 
-    let Some(tree_root): Option<HeapNode> = HeapNode::try_read_root(&mut bit_reader)? else {
-        return Ok(0);
+    Ok(kind)
+}
+
+// A header of the second version followed by nothing isn't an empty file.
+fn missing_tree<T>(checksum_kind: ChecksumKind) -> io::Result<Option<T>> {
+    match checksum_kind {
+        ChecksumKind::None => Ok(None),
+        _ => Err(io::ErrorKind::UnexpectedEof.into()),
+    }
+}
+
+fn read_headers<R: Read>(bit_reader: &mut BitReader<R>) -> io::Result<Option<(ChecksumKind, HeapNode, u64)>> {
+    let checksum_kind = read_format_header(bit_reader)?;
+    let Some(tree_root): Option<HeapNode> = HeapNode::try_read_root(bit_reader)? else {
+        return missing_tree(checksum_kind);
     };
     // dbg!(&tree_root);
 
     let CompactNumberU64(total_byte_count) = bit_reader.read_readable()?;
     // dbg!(total_byte_count);
 
-    info!("Reading file data...");
+    Ok(Some((checksum_kind, tree_root, total_byte_count)))
+}
+
+// Like `read_headers`, but the tree is read straight into the decoder, without allocating its nodes.
+// Only `read_tree`, `unpack_with_strategy` and the dump need the nodes.
+fn read_decoder_headers<R: Read>(bit_reader: &mut BitReader<R>) -> io::Result<Option<(ChecksumKind, LookupDecoder, u64)>> {
+    let checksum_kind = read_format_header(bit_reader)?;
+    let Some(decoder) = LookupDecoder::try_read(bit_reader)? else {
+        return missing_tree(checksum_kind);
+    };
+    let CompactNumberU64(total_byte_count) = bit_reader.read_readable()?;
+
+    Ok(Some((checksum_kind, decoder, total_byte_count)))
+}
+
+// Reads the headers of a packed file, or of its first member when it's concatenated. Returns `None`
+// for an empty file. The checksum is stored after the data, so the data of a member with one is
+// decoded to find it, without keeping it.
+pub fn read_header_info<R: Read>(reader: R) -> io::Result<Option<HeaderInfo>> {
+    let mut bit_reader = BitReader::new(BufReader::new(reader));
+
+    let Some((checksum_kind, decoder, total_byte_count)) = read_decoder_headers(&mut bit_reader)? else {
+        return Ok(None);
+    };
+
+    let checksum = match checksum_kind {
+        ChecksumKind::None => None,
+        _ => {
+            decoder.decode(&mut bit_reader, &mut io::sink(), total_byte_count)?;
+            read_trailer(&mut bit_reader, checksum_kind)?
+        }
+    };
+    let (distinct_symbols, max_code_length) = decoder.leaf_stats();
+
+    Ok(Some(HeaderInfo {
+        original_size: total_byte_count,
+        distinct_symbols,
+        max_code_length,
+        header_bits: header_bits(distinct_symbols, total_byte_count),
+        checksum,
+    }))
+}
+
+// Reads the Huffman tree of a packed file, or of its first member when it's concatenated. Returns
+// `None` for an empty file.
+pub fn read_tree<R: Read>(reader: R) -> io::Result<Option<HeapNode>> {
+    let mut bit_reader = BitReader::new(BufReader::new(reader));
+
+    Ok(read_headers(&mut bit_reader)?.map(|(_, tree_root, _)| tree_root))
+}
+
+// Decodes the first member with `strategy`, without checking its checksum. Only meant for the
+// benchmarks, `unpack_file` always uses the fastest strategy.
+#[doc(hidden)]
+pub fn unpack_with_strategy<R: Read, W: Write>(reader: R, mut writer: W, strategy: DecodeStrategy) -> io::Result<u64> {
+    let mut bit_reader = BitReader::new(BufReader::new(reader));
+    let Some((_, tree_root, total_byte_count)) = read_headers(&mut bit_reader)? else {
+        return Ok(0);
+    };
+
+    decode::decode_with(strategy, &tree_root, &mut bit_reader, &mut writer, total_byte_count)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MemberInfo {
+    pub original_size: u64,
+    // With the headers and the checksum.
+    pub packed_size: u64,
+    pub checksum: Option<Checksum>,
+}
+
+// Counts the bytes written, to know how many bits their codes took.
+struct ByteTableWrite {
+    byte_table: ByteTable,
+}
+
+impl Write for ByteTableWrite {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        for byte in buf {
+            self.byte_table[*byte as usize] += 1;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+// Lists the members of a packed file, the files joined with `cat` or `hc concat`. The members
+// don't store where they end, so they are decoded without keeping the data. An empty file has no
+// members.
+pub fn read_members<R: Read>(reader: R) -> io::Result<Vec<MemberInfo>> {
+    let mut bit_reader = BitReader::new(BufReader::new(reader));
+
+    let mut members = Vec::new();
+    while let Some((checksum_kind, decoder, total_byte_count)) = read_decoder_headers(&mut bit_reader)? {
+        let mut writer = ByteTableWrite { byte_table: [0; BYTE_TABLE_LEN] };
+        decoder.decode(&mut bit_reader, &mut writer, total_byte_count)?;
+        let checksum = read_trailer(&mut bit_reader, checksum_kind)?;
+
+        let (distinct_symbols, _) = decoder.leaf_stats();
+        let data_bits: u64 = writer.byte_table.iter()
+            .zip(decoder.code_lengths())
+            .map(|(count, length)| count * length)
+            .sum();
+        let packed_size = (header_bits(distinct_symbols, total_byte_count) + data_bits).div_ceil(u8::BITS as u64)
+            + checksum_kind.added_len();
+
+        members.push(MemberInfo { original_size: total_byte_count, packed_size, checksum });
+    }
+
+    Ok(members)
+}
+
+fn header_bits(distinct_symbols: usize, total_byte_count: u64) -> u64 {
+    // every leaf is a flag and a byte, and every pair a flag.
+    let leaf_bits = (tree::consts::TYPE_FLAG_SIZE + u8::BITS as usize) as u64;
+    let pair_bits = tree::consts::TYPE_FLAG_SIZE as u64;
+    let tree_bits = distinct_symbols as u64 * leaf_bits + (distinct_symbols as u64 - 1) * pair_bits;
+    let byte_count_bits = CompactNumberU64(total_byte_count).encoded_len() as u64 * 8;
+
+    tree_bits + byte_count_bits
+}
+
+// The size in bytes `pack_file` outputs for an input with those byte counts, without packing it.
+pub fn estimate_packed_size(byte_table: ByteTable) -> u64 {
+    let Some((_, code_table)) = tree::get_huffman_tree_and_codes(byte_table) else {
+        return 0;
+    };
+
+    let total_byte_count = byte_table.iter().sum();
+    let distinct_symbols = byte_table.iter().filter(|count| **count != 0).count();
+
+    let data_bits: u64 = byte_table.iter()
+        .zip(&code_table)
+        .filter_map(|(count, code)| Some(count * code.as_ref()?.len() as u64))
+        .sum();
+
+    (header_bits(distinct_symbols, total_byte_count) + data_bits).div_ceil(u8::BITS as u64)
+}
+
+pub fn unpack_file<R: Read + Seek, W: Write>(reader: R, writer: W) -> io::Result<u64> {
+    unpack_file_with_options(reader, writer, &UnpackOptions::default())
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UnpackOptions {
+    // Compares the unpacked data with its checksum, when there's one.
+    pub verify: bool,
+    // Fails before decoding a member which would make the unpacked data bigger than that.
+    pub max_size: Option<u64>,
+    // Only unpacks the member at that index, from 0. Where the members end isn't stored, so the
+    // ones before it are still decoded, without writing them, and the time it takes grows with
+    // their size. Jumping to a member would need an index of the members in the format.
+    pub member: Option<usize>,
+}
+
+impl Default for UnpackOptions {
+    fn default() -> Self {
+        Self { verify: true, max_size: None, member: None }
+    }
+}
+
+// Packed files can be concatenated, their members are unpacked one after the other.
+pub fn unpack_file_with_options<R: Read + Seek, W: Write>(reader: R, writer: W, options: &UnpackOptions) -> io::Result<u64> {
+    let buf_reader = BufReader::new(reader);
+    let mut bit_reader = BitReader::new(buf_reader);
+    let mut writer = BufWriter::new(HashingWrite { inner: writer, hasher: Hasher::new(ChecksumKind::None) });
 
     let mut bytes_read = 0;
-    while bytes_read < total_byte_count {
-        let mut current_node = &tree_root;
+    let mut index = 0;
+    loop {
+        info!("Reading file headers...");
+
+        let Some((checksum_kind, decoder, total_byte_count)) = read_decoder_headers(&mut bit_reader)? else {
+            break;
+        };
 
-        loop {
-            match current_node {
-                HeapNode::Leaf(byte) => {
-                    writer.write_all(&[*byte])?;
-                    bytes_read += 1;
+        if options.member.is_some_and(|member| index < member) {
+            decoder.decode(&mut bit_reader, &mut io::sink(), total_byte_count)?;
+            read_trailer(&mut bit_reader, checksum_kind)?;
 
-                    break;
-                }
-                HeapNode::Pair { left, right } => {
-                    let child_bit = bit_reader.read_bits(1)?;
+            index += 1;
+            continue;
+        }
+
+        if let Some(max_size) = options.max_size.filter(|max_size| bytes_read + total_byte_count > *max_size) {
+            return Err(io::Error::new(
+                io::ErrorKind::FileTooLarge,
+                format!("The unpacked data would be {} bytes, over the limit of {} bytes", bytes_read + total_byte_count, max_size),
+            ));
+        }
 
-                    match child_bit {
-                        tree::consts::LEFT_BIT => current_node = left,
-                        tree::consts::RIGHT_BIT => current_node = right,
+        info!("Reading file data...");
 
-                        _ => unreachable!(),
-                    }
-                }
+        writer.get_mut().hasher = Hasher::new(if options.verify { checksum_kind } else { ChecksumKind::None });
+        bytes_read += decoder.decode(&mut bit_reader, &mut writer, total_byte_count)?;
+        writer.flush()?;
 
-                HeapNode::Empty => return Err(io::ErrorKind::InvalidData.into()),
+        if let (Some(expected), true) = (read_trailer(&mut bit_reader, checksum_kind)?, options.verify) {
+            let actual = writer.get_ref().hasher.finish(checksum_kind);
+            if actual != Some(expected) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Checksum mismatch : expected {}, found {}", expected, actual.unwrap()),
+                ));
             }
+            info!("Checksum verified : {}", expected);
         }
+
+        index += 1;
+        if options.member.is_some() {
+            break;
+        }
+    }
+
+    if let Some(member) = options.member.filter(|member| index <= *member) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("There's no member {}, the file has {}", member + 1, index),
+        ));
     }
 
+    writer.flush()?;
+
     Ok(bytes_read)
 }
+
+// Consumes the end of a member after its data : the padding up to the next byte, then the checksum
+// of the kind its header gives.
+fn read_trailer<R: Read>(bit_reader: &mut BitReader<R>, checksum_kind: ChecksumKind) -> io::Result<Option<Checksum>> {
+    if !bit_reader.is_aligned() {
+        bit_reader.consume_bits(u8::BITS as usize - bit_reader.bit_cursor())?;
+    }
+
+    let mut bytes = vec![0; checksum_kind.checksum_len()];
+    bit_reader.aligned_reader().read_exact(&mut bytes)?;
+
+    Ok(Checksum::from_trailer(checksum_kind, &bytes))
+}
+
+#[cfg(test)]
+#[cfg_attr(coverage_nightly, coverage(off))]
+mod test {
+    use std::io::{self, Cursor, Read, Seek, Write};
+
+    use crate::{
+        dump_stream, estimate_packed_size, get_byte_table, pack_file, pack_file_with_options, pack_file_with_stats, read_header_info,
+        read_members, read_tree, unpack_file, unpack_file_with_options, unpack_with_strategy, DecodeStrategy, ChecksumKind, Expansion, HeaderInfo, HeapNode, PackOptions, UnpackOptions,
+        IN_MEMORY_INPUT_LEN, get_file_byte_table,
+    };
+
+    #[test]
+    fn header_info() {
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+
+        let info = read_header_info(&packed[..]).unwrap().unwrap();
+        assert_eq!(
+            info,
+            HeaderInfo {
+                original_size: 7,
+                distinct_symbols: 3,
+                max_code_length: 2,
+                header_bits: 3 * 9 + 2 + 2 * 8,
+                checksum: None,
+            }
+        );
+
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b""), &mut packed).unwrap();
+        assert_eq!(read_header_info(&packed[..]).unwrap(), None);
+    }
+
+    #[test]
+    fn pack_stats() {
+        let mut packed = Vec::new();
+        let stats = pack_file_with_stats(Cursor::new(b"aabb"), &mut packed).unwrap();
+
+        assert_eq!(stats.input_size, 4);
+        assert_eq!(stats.output_size, packed.len() as u64);
+        assert_eq!(stats.entropy, 1.0);
+    }
+
+    #[test]
+    fn estimated_size_matches_the_packed_size() {
+        let inputs: [&[u8]; 4] = [b"", b"aaaa", b"aaaabbc", &(0..=255).cycle().take(3000).collect::<Vec<u8>>()];
+
+        for input in inputs {
+            let mut packed = Vec::new();
+            pack_file(Cursor::new(input), &mut packed).unwrap();
+
+            let byte_table = get_byte_table(&mut Cursor::new(input)).unwrap();
+            assert_eq!(estimate_packed_size(byte_table), packed.len() as u64);
+        }
+    }
+
+    #[test]
+    fn checksums_are_verified() {
+        for kind in [ChecksumKind::Crc32, ChecksumKind::Xxh64] {
+            let mut packed = Vec::new();
+            let options = PackOptions { checksum: kind, ..Default::default() };
+            let stats = pack_file_with_options(Cursor::new(b"aaaabbc"), &mut packed, &options).unwrap();
+            assert_eq!(stats.output_size, packed.len() as u64);
+
+            let mut plain = Vec::new();
+            pack_file(Cursor::new(b"aaaabbc"), &mut plain).unwrap();
+            assert_eq!(packed.len() as u64, plain.len() as u64 + kind.added_len());
+
+            let checksum = read_header_info(&packed[..]).unwrap().unwrap().checksum.unwrap();
+            assert_eq!(checksum.kind(), kind);
+
+            let mut unpacked = Vec::new();
+            unpack_file(Cursor::new(&packed), &mut unpacked).unwrap();
+            assert_eq!(unpacked, b"aaaabbc");
+
+            // flips a bit of the stored checksum.
+            let checksum_byte = packed.len() - 1;
+            packed[checksum_byte] ^= 1;
+            let err = unpack_file(Cursor::new(&packed), &mut Vec::new()).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+            let options = UnpackOptions { verify: false, ..Default::default() };
+            unpack_file_with_options(Cursor::new(&packed), &mut Vec::new(), &options).unwrap();
+        }
+    }
+
+    #[test]
+    fn checksum_kind_is_read_from_the_header_of_each_member() {
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        pack_file_with_options(Cursor::new(b"xyz"), &mut packed, &PackOptions { checksum: ChecksumKind::Crc32, ..Default::default() }).unwrap();
+        pack_file(Cursor::new(b"hello"), &mut packed).unwrap();
+
+        let kinds: Vec<_> = read_members(&packed[..]).unwrap().iter().map(|member| member.checksum.map(|checksum| checksum.kind())).collect();
+        assert_eq!(kinds, [None, Some(ChecksumKind::Crc32), None]);
+
+        // the checksum is the one of the first member, not found at the end of the file.
+        assert_eq!(read_header_info(&packed[..]).unwrap().unwrap().checksum, None);
+        let info = read_header_info(&packed[read_members(&packed[..]).unwrap()[0].packed_size as usize..]).unwrap().unwrap();
+        assert_eq!(info.checksum.map(|checksum| checksum.kind()), Some(ChecksumKind::Crc32));
+    }
+
+    #[test]
+    fn unknown_checksum_kinds_are_refused() {
+        use bitpack::{BitWrite, BitWriter};
+
+        let mut packed = Vec::new();
+        pack_file_with_options(Cursor::new(b"aaaabbc"), &mut packed, &PackOptions { checksum: ChecksumKind::Crc32, ..Default::default() }).unwrap();
+
+        let mut header = BitWriter::new(Vec::new());
+        header.write_bits(crate::tree::consts::LEAF_FLAG, crate::tree::consts::TYPE_FLAG_SIZE).unwrap();
+        header.write_byte(crate::FORMAT_VERSION).unwrap();
+        header.write_writable(bitpack::compact::CompactNumberU64(0)).unwrap();
+        header.write_bits(3, crate::FORMAT_FLAGS_SIZE).unwrap();
+        let mut unknown = header.into_inner();
+        assert_eq!(unknown.len(), crate::FORMAT_HEADER_LEN);
+        unknown.extend_from_slice(&packed[crate::FORMAT_HEADER_LEN..]);
+
+        let err = unpack_file(Cursor::new(&unknown), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn concatenated_files_are_unpacked_one_after_the_other() {
+        let mut packed = Vec::new();
+        pack_file_with_options(Cursor::new(b"aaaabbc"), &mut packed, &PackOptions { checksum: ChecksumKind::Crc32, ..Default::default() }).unwrap();
+        pack_file(Cursor::new(b""), &mut packed).unwrap();
+        pack_file(Cursor::new(b"xyz"), &mut packed).unwrap();
+        pack_file_with_options(Cursor::new(b"zzz"), &mut packed, &PackOptions { checksum: ChecksumKind::Xxh64, ..Default::default() }).unwrap();
+
+        let mut unpacked = Vec::new();
+        assert_eq!(unpack_file(Cursor::new(&packed), &mut unpacked).unwrap(), 13);
+        assert_eq!(unpacked, b"aaaabbcxyzzzz");
+    }
+
+    #[test]
+    fn unpacking_stops_at_the_max_size() {
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        pack_file(Cursor::new(b"xyz"), &mut packed).unwrap();
+
+        let options = UnpackOptions { max_size: Some(10), ..Default::default() };
+        assert_eq!(unpack_file_with_options(Cursor::new(&packed), &mut Vec::new(), &options).unwrap(), 10);
+
+        let mut unpacked = Vec::new();
+        let options = UnpackOptions { max_size: Some(9), ..Default::default() };
+        let err = unpack_file_with_options(Cursor::new(&packed), &mut unpacked, &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::FileTooLarge);
+        assert_eq!(unpacked, b"aaaabbc");
+    }
+
+    #[test]
+    fn tree_is_read_from_the_headers() {
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b"aaab"), &mut packed).unwrap();
+
+        let HeapNode::Pair { left, right } = read_tree(&packed[..]).unwrap().unwrap() else {
+            panic!("the root should be a pair")
+        };
+        assert_eq!((*left, *right), (HeapNode::Leaf(b'a'), HeapNode::Leaf(b'b')));
+
+        assert_eq!(read_tree(&b""[..]).unwrap(), None);
+    }
+
+    #[test]
+    fn expansion_is_refused_before_writing() {
+        let options = PackOptions { refuse_expansion: true, ..Default::default() };
+
+        let mut packed = Vec::new();
+        let err = pack_file_with_options(Cursor::new(b"abcd"), &mut packed, &options).unwrap_err();
+        let expansion = err.get_ref().and_then(|err| err.downcast_ref::<Expansion>()).unwrap();
+        assert_eq!(expansion.input_size, 4);
+        assert!(expansion.packed_size > 4);
+        assert!(packed.is_empty());
+
+        let data = [b'a'; 100];
+        assert!(pack_file_with_options(Cursor::new(&data), &mut packed, &options).is_ok());
+        assert!(pack_file_with_options(Cursor::new(b""), &mut Vec::new(), &options).is_ok());
+    }
+
+    #[test]
+    fn members_are_listed_with_their_sizes() {
+        let mut packed = Vec::new();
+        let first_size = pack_file(Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        let options = PackOptions { checksum: ChecksumKind::Xxh64, ..Default::default() };
+        pack_file_with_options(Cursor::new(b"x"), &mut packed, &options).unwrap();
+
+        let members = read_members(&packed[..]).unwrap();
+        assert_eq!(members.len(), 2);
+        assert_eq!((members[0].original_size, members[0].packed_size, members[0].checksum), (7, first_size, None));
+        assert_eq!((members[1].original_size, members[1].packed_size), (1, packed.len() as u64 - first_size));
+        assert_eq!(members[1].checksum.map(|checksum| checksum.kind()), Some(ChecksumKind::Xxh64));
+
+        assert_eq!(read_members(&b""[..]).unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn stream_is_dumped_with_the_offsets() {
+        let data = b"abracadabra";
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(data), &mut packed).unwrap();
+
+        let mut dump = Vec::new();
+        dump_stream(Cursor::new(&packed), &mut dump, 4).unwrap();
+        let dump = String::from_utf8(dump).unwrap();
+
+        // 5 leaves of 9 bits, 4 pairs of 1 bit, and the byte count on 2 bytes.
+        assert!(dump.starts_with("11 bytes, 65 header bits\n"));
+        assert!(dump.contains(" tree "));
+        assert!(dump.contains("leaf 0x61 "));
+        assert!(dump.contains("49..65 "));
+        assert!(dump.contains("code 0 1 -> 0x61"));
+        assert_eq!(dump.matches("code ").count(), 4);
+        assert!(dump.ends_with("... 7 more codes\n"));
+
+        let mut dump = Vec::new();
+        dump_stream(Cursor::new([]), &mut dump, 4).unwrap();
+        assert_eq!(dump, b"empty\n");
+    }
+
+    #[test]
+    fn a_single_member_is_unpacked() {
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(b"aaaabbc"), &mut packed).unwrap();
+        pack_file_with_options(Cursor::new(b"xyz"), &mut packed, &PackOptions { checksum: ChecksumKind::Crc32, ..Default::default() }).unwrap();
+        pack_file(Cursor::new(b"hello"), &mut packed).unwrap();
+
+        for (member, expected) in [(0, &b"aaaabbc"[..]), (1, b"xyz"), (2, b"hello")] {
+            let mut unpacked = Vec::new();
+            let options = UnpackOptions { member: Some(member), ..Default::default() };
+            assert_eq!(unpack_file_with_options(Cursor::new(&packed), &mut unpacked, &options).unwrap(), expected.len() as u64);
+            assert_eq!(unpacked, expected);
+        }
+
+        let options = UnpackOptions { member: Some(3), ..Default::default() };
+        let err = unpack_file_with_options(Cursor::new(&packed), &mut Vec::new(), &options).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn packed_data_is_written_in_chunks() {
+        struct CountingWrite {
+            writes: usize,
+            bytes: Vec<u8>,
+        }
+
+        impl Write for CountingWrite {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                self.writes += 1;
+                self.bytes.extend_from_slice(buf);
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let data = (0..=255).cycle().take(10_000).collect::<Vec<u8>>();
+        let options = PackOptions { checksum: ChecksumKind::Crc32, ..Default::default() };
+
+        let mut counting = CountingWrite { writes: 0, bytes: Vec::new() };
+        pack_file_with_options(Cursor::new(&data), &mut counting, &options).unwrap();
+        assert!(counting.writes < 10, "{} writes", counting.writes);
+
+        let mut packed = Vec::new();
+        pack_file_with_options(Cursor::new(&data), &mut packed, &options).unwrap();
+        assert_eq!(counting.bytes, packed);
+    }
+
+    #[test]
+    fn every_byte_is_counted() {
+        // not a multiple of the 8 bytes counted at once.
+        let data = (0..=255u8).cycle().take(1001).chain([7; 13]).collect::<Vec<u8>>();
+
+        let byte_table = get_byte_table(&mut Cursor::new(&data)).unwrap();
+        for byte in 0..=255u8 {
+            assert_eq!(byte_table[byte as usize], data.iter().filter(|other| **other == byte).count() as u64);
+        }
+    }
+
+    #[test]
+    fn small_inputs_are_read_once() {
+        struct Unseekable(Cursor<Vec<u8>>);
+
+        impl Read for Unseekable {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.read(buf)
+            }
+        }
+
+        impl Seek for Unseekable {
+            fn seek(&mut self, _: io::SeekFrom) -> io::Result<u64> {
+                Err(io::ErrorKind::Unsupported.into())
+            }
+        }
+
+        let data = b"abracadabra".repeat(1000);
+        let mut packed = Vec::new();
+        pack_file(Unseekable(Cursor::new(data.clone())), &mut packed).unwrap();
+
+        let mut expected = Vec::new();
+        pack_file(Cursor::new(&data), &mut expected).unwrap();
+        assert_eq!(packed, expected);
+
+        let data = vec![b'a'; IN_MEMORY_INPUT_LEN + 1];
+        let error = pack_file(Unseekable(Cursor::new(data)), io::sink()).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+    }
+
+    #[test]
+    fn every_decode_strategy_gives_the_same_data() {
+        let data = b"it was the best of times, it was the worst of times".repeat(20);
+        let mut packed = Vec::new();
+        pack_file(Cursor::new(&data), &mut packed).unwrap();
+
+        for strategy in [DecodeStrategy::TreeWalk, DecodeStrategy::FlatTree, DecodeStrategy::Lookup] {
+            let mut unpacked = Vec::new();
+            assert_eq!(unpack_with_strategy(&packed[..], &mut unpacked, strategy).unwrap(), data.len() as u64);
+            assert_eq!(unpacked, data, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn files_are_counted_on_several_threads() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("data");
+        // more than the 64 KiB counted at least by each thread.
+        let data = b"the quick brown fox jumps over the lazy dog".repeat(5000);
+        std::fs::write(&path, &data).unwrap();
+
+        let byte_table = get_byte_table(&mut Cursor::new(&data)).unwrap();
+        for threads in [1, 3, 8, 1000] {
+            assert_eq!(get_file_byte_table(&path, threads).unwrap(), byte_table);
+        }
+
+        // files bigger than 4 GiB, whose ranges don't fit in a 32-bit `usize`.
+        let (starts, range_len) = crate::table::file_ranges(5 << 32, 5);
+        assert_eq!((starts.collect::<Vec<_>>(), range_len), ((0..5).map(|index| index << 32).collect(), 1 << 32));
+        let (starts, range_len) = crate::table::file_ranges((8 << 32) + 1, 8);
+        assert_eq!((starts.count(), range_len), (8, (1 << 32) + 1));
+
+        let mut packed = Vec::new();
+        let options = PackOptions { byte_table: Some(byte_table), ..Default::default() };
+        pack_file_with_options(std::fs::File::open(&path).unwrap(), &mut packed, &options).unwrap();
+
+        let mut expected = Vec::new();
+        pack_file(Cursor::new(&data), &mut expected).unwrap();
+        assert_eq!(packed, expected);
+    }
+
+    #[test]
+    fn single_byte_inputs_are_packed_as_runs() {
+        for len in [1, 9, IN_MEMORY_INPUT_LEN + 3] {
+            let data = vec![b'z'; len];
+            let options = PackOptions { checksum: ChecksumKind::Xxh64, ..Default::default() };
+            let mut packed = Vec::new();
+            let stats = pack_file_with_options(Cursor::new(&data), &mut packed, &options).unwrap();
+            assert_eq!(stats.output_size, estimate_packed_size(get_byte_table(&mut &data[..]).unwrap()) + ChecksumKind::Xxh64.added_len());
+
+            let mut unpacked = Vec::new();
+            unpack_file(Cursor::new(packed), &mut unpacked).unwrap();
+            assert_eq!(unpacked, data);
+        }
+    }
+}
@@ -1,11 +1,13 @@
-use std::io::{self, BufRead};
+use std::{fs::File, io::{self, BufRead, BufReader, Read, Seek, SeekFrom}, path::Path};
 
 pub const BYTE_TABLE_LEN: usize = u8::MAX as usize + 1;
 
 pub type ByteTable = [u64; BYTE_TABLE_LEN];
 
 pub fn get_byte_table<R: BufRead>(reader: &mut R) -> io::Result<ByteTable> {
-    let mut byte_table = [0; BYTE_TABLE_LEN];
+    // consecutive bytes are counted in different tables, so that incrementing the count of a byte
+    // doesn't wait for the previous increment when the same byte repeats.
+    let mut byte_tables = [[0u64; BYTE_TABLE_LEN]; 4];
 
     loop {
         let buf = reader.fill_buf()?;
@@ -13,15 +15,54 @@ pub fn get_byte_table<R: BufRead>(reader: &mut R) -> io::Result<ByteTable> {
             break;
         }
 
-        for byte in buf {
-            byte_table[*byte as usize] += 1;
+        let mut words = buf.chunks_exact(8);
+        for word in &mut words {
+            let word = u64::from_le_bytes(word.try_into().unwrap());
+            for (index, shift) in (0..u64::BITS).step_by(8).enumerate() {
+                byte_tables[index % 4][(word >> shift) as u8 as usize] += 1;
+            }
+        }
+        for byte in words.remainder() {
+            byte_tables[0][*byte as usize] += 1;
         }
 
         let n = buf.len();
         reader.consume(n);
     }
 
-    Ok(byte_table)
+    Ok(core::array::from_fn(|byte| byte_tables.iter().map(|table| table[byte]).sum()))
+}
+
+// The smallest part of a file counted by a thread.
+const MIN_RANGE_LEN: u64 = 64 * 1024;
+
+// The start of the ranges of a file of `len` bytes counted by up to `threads` threads, and their
+// length, the last range being shorter. Computed on 64 bits, as the file can be bigger than `usize`.
+pub(crate) fn file_ranges(len: u64, threads: usize) -> (impl Iterator<Item = u64>, u64) {
+    let range_len = len.div_ceil(threads.max(1) as u64).max(MIN_RANGE_LEN);
+    let starts = (0..threads.max(1) as u64).map(move |index| index * range_len).take_while(move |start| *start < len);
+
+    (starts, range_len)
+}
+
+// Counts the bytes of the file on up to `threads` threads, each reading a range of it.
+pub fn get_file_byte_table(path: &Path, threads: usize) -> io::Result<ByteTable> {
+    let (starts, range_len) = file_ranges(std::fs::metadata(path)?.len(), threads);
+
+    let tables = std::thread::scope(|scope| {
+        let workers = starts
+            .map(|start| scope.spawn(move || {
+                let mut file = File::open(path)?;
+                file.seek(SeekFrom::Start(start))?;
+
+                get_byte_table(&mut BufReader::new(file.take(range_len)))
+            }))
+            .collect::<Vec<_>>();
+
+        workers.into_iter().map(|worker| worker.join().unwrap()).collect::<io::Result<Vec<_>>>()
+    })?;
+
+    Ok(core::array::from_fn(|byte| tables.iter().map(|table| table[byte]).sum()))
 }
 
 pub fn compute_entropy(table: ByteTable) -> f32 {
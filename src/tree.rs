@@ -1,4 +1,4 @@
-use std::io;
+use std::{collections::VecDeque, io};
 
 use crate::table::{ByteTable, BYTE_TABLE_LEN};
 
@@ -7,61 +7,31 @@ use consts::{LEAF_FLAG, PAIR_FLAG};
 
 pub type HuffmanCodeTable = [Option<HuffmanCode>; BYTE_TABLE_LEN];
 
-#[derive(Debug, PartialEq, Eq)]
-pub struct HuffmanCode(Vec<u8>, usize);
-
-impl BitWritable for HuffmanCode {
-    fn write<W: bitpack::BitWrite>(&self, writer: &mut W) -> io::Result<()> {
-        if self.0.is_empty() {
-            return Ok(())
-        }
-
-        writer.write_bits(self.0[0], self.1)?;
-        for byte in &self.0[1..] {
-            writer.write_byte(*byte)?;
-        }
-
-        Ok(())
-    }
-}
+// The longest code, so that every code fits in a `u64`. Longer codes need more than 27 TB of input.
+pub const MAX_CODE_LENGTH: usize = u64::BITS as usize;
 
-struct HuffmanCodeBuilder {
-    bit_cursor: usize,
-    bytes: Vec<u8>,
-    bit_buff: u8,
+// The bits of the code in the order they are written, the first one being the lowest, and their
+// amount.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HuffmanCode {
+    bits: u64,
+    len: u8,
 }
 
-impl HuffmanCodeBuilder {
-    fn new() -> Self {
-        Self {
-            bytes: Vec::new(),
-            bit_cursor: 0,
-            bit_buff: 0,
-        }
+impl HuffmanCode {
+    // In bits.
+    pub fn len(&self) -> usize {
+        self.len as usize
     }
 
-    fn write_bit(&mut self, bit: u8) {
-        assert!(bit <= 1);
-
-        if self.bit_cursor == u8::BITS as usize {
-            self.bytes.push(self.bit_buff);
-            self.bit_buff = 0;
-            self.bit_cursor = 0;
-        }
-
-        self.bit_buff <<= 1;
-        self.bit_buff |= bit;
-        self.bit_cursor += 1;
+    pub(crate) fn bits(&self) -> u64 {
+        self.bits
     }
+}
 
-    fn finish(mut self) -> HuffmanCode {
-        if self.bit_cursor != 0 {
-            self.bytes.push(self.bit_buff);
-        }
-
-        self.bytes.reverse();
-
-        HuffmanCode(self.bytes, self.bit_cursor)
+impl BitWritable for HuffmanCode {
+    fn write<W: bitpack::BitWrite>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_bits_u64(self.bits, self.len as usize)
     }
 }
 
@@ -78,10 +48,12 @@ pub mod consts {
 #[derive(Debug, PartialEq, Eq)]
 pub enum HeapNode {
     Leaf(u8),
+    // The left child has the code bit 0, and the right one 1.
     Pair {
         left: Box<HeapNode>,
         right: Box<HeapNode>,
     },
+    // The missing right child of a tree with a single leaf.
     Empty,
 }
 
@@ -110,6 +82,39 @@ impl HeapNode {
 
         Ok(Some(tree_root))
     }
+
+    // The amount of leaves, and the depth of the deepest one which is the longest code length.
+    pub fn leaf_stats(&self) -> (usize, usize) {
+        match self {
+            Self::Leaf(_) => (1, 0),
+            Self::Pair { left, right } => {
+                let (left_leaves, left_depth) = left.leaf_stats();
+                let (right_leaves, right_depth) = right.leaf_stats();
+
+                (left_leaves + right_leaves, left_depth.max(right_depth) + 1)
+            }
+            Self::Empty => (0, 0),
+        }
+    }
+
+    // The length of the code of every byte, 0 for the bytes which aren't in the tree.
+    pub fn code_lengths(&self) -> [u64; BYTE_TABLE_LEN] {
+        fn visit(node: &HeapNode, depth: u64, lengths: &mut [u64; BYTE_TABLE_LEN]) {
+            match node {
+                HeapNode::Leaf(byte) => lengths[*byte as usize] = depth,
+                HeapNode::Pair { left, right } => {
+                    visit(left, depth + 1, lengths);
+                    visit(right, depth + 1, lengths);
+                }
+                HeapNode::Empty => {}
+            }
+        }
+
+        let mut lengths = [0; BYTE_TABLE_LEN];
+        visit(self, 0, &mut lengths);
+
+        lengths
+    }
 }
 
 impl BitWritable for HeapNode {
@@ -154,74 +159,105 @@ impl BitReadable for HeapNode {
     }
 }
 
-fn write_bit_to_node(
-    node: &HeapNode,
-    bit: u8,
-    binary_repr_builders: &mut [HuffmanCodeBuilder; BYTE_TABLE_LEN],
-) {
+fn get_codes(node: &HeapNode, code: HuffmanCode, codes: &mut HuffmanCodeTable) {
     match node {
-        HeapNode::Leaf(byte) => binary_repr_builders[*byte as usize].write_bit(bit),
+        HeapNode::Leaf(byte) => codes[*byte as usize] = Some(code),
         HeapNode::Pair { left, right } => {
-            write_bit_to_node(left, bit, binary_repr_builders);
-            write_bit_to_node(right, bit, binary_repr_builders);
+            for (child, bit) in [(left, consts::LEFT_BIT), (right, consts::RIGHT_BIT)] {
+                let code = HuffmanCode { bits: code.bits | (bit as u64) << code.len, len: code.len + 1 };
+                get_codes(child, code, codes);
+            }
         }
         HeapNode::Empty => panic!("Empty node should only be used when reading")
     }
 }
 
-pub fn get_huffman_tree_and_codes(byte_table: ByteTable) -> Option<(HeapNode, HuffmanCodeTable)> {
-    let mut binary_repr_builders = core::array::from_fn(|_| HuffmanCodeBuilder::new());
+// The pairs grouped by count, the smallest first. A group is never empty, and its last pair is the
+// last one made.
+type Pairs = VecDeque<(u64, Vec<HeapNode>)>;
+
+fn push_pair(pairs: &mut Pairs, count: u64, pair: HeapNode) {
+    match pairs.back_mut() {
+        Some((last_count, group)) if *last_count == count => group.push(pair),
+        _ => pairs.push_back((count, vec![pair])),
+    }
+}
 
-    let mut nodes = byte_table
+// On equal counts, pairs are taken before leaves, the last made pair and the leaf with the highest
+// byte first. That gives the same trees as sorting all the nodes before each merge.
+fn pop_smallest(leaves: &mut VecDeque<(u64, HeapNode)>, pairs: &mut Pairs) -> Option<(u64, HeapNode)> {
+    match (leaves.front(), pairs.front_mut()) {
+        (Some((leaf_count, _)), Some((pair_count, _))) if leaf_count < pair_count => leaves.pop_front(),
+        (_, Some((pair_count, group))) => {
+            let pair = (*pair_count, group.pop().unwrap());
+            if group.is_empty() {
+                pairs.pop_front();
+            }
+            Some(pair)
+        }
+        (_, None) => leaves.pop_front(),
+    }
+}
+
+fn get_huffman_tree(byte_table: ByteTable) -> Option<HeapNode> {
+    // the leaves are sorted once. The pairs are made with counts that never decrease, so they are
+    // already sorted in the order they are made, and the smallest node is at the front of a queue.
+    let mut leaves = byte_table
         .into_iter()
         .enumerate()
         .filter(|(_, count)| *count != 0)
         .map(|(byte, count)| (count, HeapNode::Leaf(u8::try_from(byte).unwrap())))
+        .rev()
         .collect::<Vec<_>>();
+    leaves.sort_by_key(|(count, _)| *count);
 
-    if nodes.is_empty() {
-        return None;
-    }
-
-    while nodes.len() > 1 {
-        nodes.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+    let mut leaves = VecDeque::from(leaves);
+    let mut pairs = Pairs::new();
 
-        let (right_count, right_node) = nodes.pop().unwrap();
-        let (left_count, left_node) = nodes.pop().unwrap();
-
-        write_bit_to_node(&left_node, consts::LEFT_BIT, &mut binary_repr_builders);
-        write_bit_to_node(&right_node, consts::RIGHT_BIT, &mut binary_repr_builders);
+    // each merge turns two nodes into one, until only the root is left.
+    for _ in 1..leaves.len() {
+        let (right_count, right_node) = pop_smallest(&mut leaves, &mut pairs).unwrap();
+        let (left_count, left_node) = pop_smallest(&mut leaves, &mut pairs).unwrap();
 
         let pair = HeapNode::Pair {
             left: Box::new(left_node),
             right: Box::new(right_node),
         };
-        nodes.push((left_count + right_count, pair));
-    }
-
-    let (_, root) = nodes.pop().unwrap();
-    if matches!(&root, HeapNode::Leaf(_)) {
-        write_bit_to_node(&root, consts::LEFT_BIT, &mut binary_repr_builders);
+        push_pair(&mut pairs, left_count + right_count, pair);
     }
 
-    let mut reprs = [const { None }; BYTE_TABLE_LEN];
-    for (index, repr) in binary_repr_builders.into_iter().enumerate() {
-        let repr = repr.finish();
+    pop_smallest(&mut leaves, &mut pairs).map(|(_, root)| root)
+}
 
-        if !repr.0.is_empty() {
-            reprs[index] = Some(repr)
+pub fn get_huffman_tree_and_codes(byte_table: ByteTable) -> Option<(HeapNode, HuffmanCodeTable)> {
+    let mut counts = byte_table;
+    let root = loop {
+        let root = get_huffman_tree(counts)?;
+        if root.leaf_stats().1 <= MAX_CODE_LENGTH {
+            break root;
         }
+
+        // closer counts give a shallower tree, at the cost of slightly longer codes.
+        counts = counts.map(|count| count.div_ceil(2));
+    };
+
+    let mut codes = [None; BYTE_TABLE_LEN];
+    if let HeapNode::Leaf(byte) = root {
+        codes[byte as usize] = Some(HuffmanCode { bits: consts::LEFT_BIT as u64, len: 1 });
+    } else {
+        get_codes(&root, HuffmanCode { bits: 0, len: 0 }, &mut codes);
     }
 
-    Some((root, reprs))
+    Some((root, codes))
 }
 
 #[cfg(test)]
 #[cfg_attr(coverage_nightly, coverage(off))]
+#[allow(clippy::unusual_byte_groupings)]
 mod test {
     use crate::table::{ByteTable, BYTE_TABLE_LEN};
 
-    use super::{get_huffman_tree_and_codes, HeapNode, HuffmanCode, HuffmanCodeBuilder, HuffmanCodeTable};
+    use super::{get_huffman_tree_and_codes, HeapNode, HuffmanCode, HuffmanCodeTable, MAX_CODE_LENGTH};
 
     macro_rules! create_byte_table {
         ($($index: literal : $count: literal),*) => {{
@@ -235,16 +271,33 @@ mod test {
     }
 
     macro_rules! create_huffman_code_table {
-        ($($index: literal : $slice: expr, $last_count: literal),*) => {{
+        ($($index: literal : $bits: literal, $len: literal),*) => {{
             #[allow(unused_mut)]
             let mut table: HuffmanCodeTable = core::array::from_fn(|_| Default::default());
 
-            $(table[$index] = Some(HuffmanCode($slice.into(), $last_count));)*
+            $(table[$index] = Some(HuffmanCode { bits: $bits, len: $len });)*
 
             table
         }};
     }
 
+    #[test]
+    fn leaf_stats() {
+        let byte_table = create_byte_table! {
+            0: 1,
+            1: 1,
+            2: 2
+        };
+        let (tree, _) = get_huffman_tree_and_codes(byte_table).unwrap();
+        assert_eq!(tree.leaf_stats(), (3, 2));
+
+        let single_leaf = HeapNode::Pair {
+            left: Box::new(HeapNode::Leaf(0)),
+            right: Box::new(HeapNode::Empty),
+        };
+        assert_eq!(single_leaf.leaf_stats(), (1, 1));
+    }
+
     #[test]
     fn empty_table_should_not_give_tree() {
         let byte_table = create_byte_table!();
@@ -263,7 +316,7 @@ mod test {
         
         let expected = HeapNode::Leaf(0);
         let expected_code_table = create_huffman_code_table! {
-            0: [0b0], 1
+            0: 0b0, 1
         };
 
         assert_eq!(tree, expected);
@@ -281,8 +334,8 @@ mod test {
         
         let expected = HeapNode::Pair { left: Box::new(HeapNode::Leaf(0)), right: Box::new(HeapNode::Leaf(1)) };
         let expected_code_table = create_huffman_code_table! {
-            0: [0b0], 1,
-            1: [0b1], 1
+            0: 0b0, 1,
+            1: 0b1, 1
         };
 
         assert_eq!(tree, expected);
@@ -311,10 +364,10 @@ mod test {
             })
         };
         let expected_code_table = create_huffman_code_table! {
-            0: [0b01], 2,
-            1: [0b11], 2,
-            2: [0b00], 2,
-            3: [0b10], 2
+            0: 0b01, 2,
+            1: 0b11, 2,
+            2: 0b00, 2,
+            3: 0b10, 2
         };
 
         assert_eq!(tree, expected);
@@ -322,33 +375,21 @@ mod test {
     }
 
     #[test]
-    fn test_huffman_code_builder_can_build_more_than_eight_bits() {
-        let mut builder =  HuffmanCodeBuilder::new();
-
-        for _ in 0..10 {
-            builder.write_bit(1);
+    fn codes_are_limited_to_64_bits() {
+        // Fibonacci counts give a tree as deep as there are bytes.
+        let mut byte_table: ByteTable = [0; BYTE_TABLE_LEN];
+        byte_table[..2].copy_from_slice(&[1, 1]);
+        for byte in 2..80 {
+            byte_table[byte] = byte_table[byte - 1] + byte_table[byte - 2];
         }
 
-        let output = builder.finish();
-        assert_eq!(
-            output,
-            HuffmanCode(vec![0b11, 0b11111111], 2)
-        )
-    }
-
-    #[test]
-    fn test_huffman_code_builder_can_build_exactly_eight_bits() {
-        let mut builder =  HuffmanCodeBuilder::new();
-
-        for _ in 0..8 {
-            builder.write_bit(1);
+        let (tree, codes) = get_huffman_tree_and_codes(byte_table).unwrap();
+        let (leaves, depth) = tree.leaf_stats();
+        assert_eq!(leaves, 80);
+        assert!(depth <= MAX_CODE_LENGTH, "{depth}");
+        for (code, length) in codes.iter().zip(tree.code_lengths()) {
+            assert_eq!(code.map_or(0, |code| code.len() as u64), length);
         }
-
-        let output = builder.finish();
-        assert_eq!(
-            output,
-            HuffmanCode(vec![0b11111111], 8)
-        )
     }
 
     mod read {
@@ -470,6 +511,26 @@ mod test {
         }
     }
 
+    mod round_trip {
+        use crate::tree::HeapNode;
+
+        #[test]
+        fn single_leaf_round_trip() {
+            bitpack::test::assert_round_trip(HeapNode::Leaf(0b11110000));
+        }
+
+        #[test]
+        fn two_level_not_complete_binary_tree_round_trip() {
+            bitpack::test::assert_round_trip(HeapNode::Pair {
+                left: Box::new(HeapNode::Pair {
+                    left: Box::new(HeapNode::Leaf(0b11110000)),
+                    right: Box::new(HeapNode::Leaf(0b00110011)),
+                }),
+                right: Box::new(HeapNode::Leaf(0b11000111)),
+            });
+        }
+    }
+
     mod write {
         use bitpack::BitWrite;
 
@@ -571,4 +632,44 @@ mod test {
             assert_eq!(output.cursor_position, 5);
         }
     }
+
+    #[test]
+    fn trees_are_the_same_as_when_sorting_before_each_merge() {
+        fn sorted_merge(byte_table: ByteTable) -> HeapNode {
+            let mut nodes = byte_table.into_iter()
+                .enumerate()
+                .filter(|(_, count)| *count != 0)
+                .map(|(byte, count)| (count, HeapNode::Leaf(byte as u8)))
+                .collect::<Vec<_>>();
+
+            while nodes.len() > 1 {
+                nodes.sort_by_key(|(count, _)| std::cmp::Reverse(*count));
+
+                let (right_count, right) = nodes.pop().unwrap();
+                let (left_count, left) = nodes.pop().unwrap();
+                nodes.push((left_count + right_count, HeapNode::Pair { left: Box::new(left), right: Box::new(right) }));
+            }
+
+            nodes.pop().unwrap().1
+        }
+
+        // small counts, so there are many equal ones.
+        let mut seed = 1u64;
+        for symbols in [1, 2, 5, 17, 64, 256] {
+            let byte_table: ByteTable = core::array::from_fn(|byte| {
+                seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+                if byte < symbols { seed >> 61 } else { 0 }
+            });
+            if byte_table.iter().all(|count| *count == 0) {
+                continue;
+            }
+
+            let (tree, _) = get_huffman_tree_and_codes(byte_table).unwrap();
+            assert_eq!(tree, sorted_merge(byte_table));
+        }
+
+        // every pair of a level has the same count.
+        let byte_table = [3; 256];
+        assert_eq!(get_huffman_tree_and_codes(byte_table).unwrap().0, sorted_merge(byte_table));
+    }
 }